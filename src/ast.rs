@@ -0,0 +1,598 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast_printer::Parenthesizer;
+use crate::token::Span;
+
+pub type Program = Vec<Stmt>;
+
+#[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
+pub enum Stmt {
+    Expression(Expr),
+    Print(Expr),
+    Var(Identifier, Option<Expr>),
+    Block(Vec<Stmt>),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    /// A loop, with the increment of the `for` loop it was desugared from,
+    /// if any, which runs after every pass through the body.
+    While(Expr, Box<Stmt>, Option<Expr>),
+    Break,
+    Continue,
+    Function(Rc<FunctionDecl>),
+    Return(Option<Expr>),
+    Class(Rc<ClassDecl>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClassDecl {
+    pub name: Identifier,
+    pub superclass: Option<Identifier>,
+    /// Set on every new instance, in order, before `init` runs.
+    pub fields: Vec<FieldDecl>,
+    pub methods: Vec<Rc<FunctionDecl>>,
+}
+
+/// `name = initializer;` in a class body.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FieldDecl {
+    pub name: Identifier,
+    pub initializer: Expr,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct FunctionDecl {
+    pub name: Identifier,
+    pub params: Vec<Identifier>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
+pub enum Expr {
+    /// Left operand, operator, right operand, and the location and span of
+    /// the operator.
+    Binary(Box<Expr>, BinaryOperator, Box<Expr>, Location, Span),
+    Logical(Box<Expr>, LogicalOperator, Box<Expr>),
+    /// `left, right`: evaluates both and yields `right`.
+    Comma(Box<Expr>, Box<Expr>),
+    /// `condition ? then : else`.
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// The operator, its operand, and the location and span of the operator.
+    Unary(UnaryOperator, Box<Expr>, Location, Span),
+    Grouping(Box<Expr>),
+    Literal(Literal),
+    /// `"a${x}b"`: the text and interpolated values, in order.
+    Interpolation(Vec<Segment>),
+    Variable(Identifier),
+    Assign(Identifier, Box<Expr>),
+    /// `target op= value`, with the location and span of the operator. The
+    /// target is a variable, property or element, and the expressions for
+    /// its object and index run once, for both the read and the write.
+    CompoundAssign(Box<Expr>, BinaryOperator, Box<Expr>, Location, Span),
+    /// `++target`, `target--` and so on, with the location and span of the
+    /// operator. Like a compound assignment's, the target's object and index
+    /// run once.
+    Update(Box<Expr>, UpdateOperator, Fixity, Location, Span),
+    /// Callee, arguments, the location of the opening parenthesis, and the
+    /// span from it through the closing one.
+    ///
+    /// The spans of calls and property accesses cover only their own link
+    /// of a chain such as `a.b().c`, so an error can point at that link
+    /// rather than at everything before it.
+    Call(Box<Expr>, Vec<Expr>, Location, Span),
+    /// Object, property name, and the span of the `.` and the name.
+    Get(Box<Expr>, Identifier, Span),
+    /// Object, property name, value, and the span of the `.` and the name.
+    Set(Box<Expr>, Identifier, Box<Expr>, Span),
+    /// `[a, b]`: the elements, and the location of the opening bracket.
+    List(Vec<Expr>, Location),
+    /// `{key: value}`: the entries, and the location of the opening brace.
+    Map(Vec<(Expr, Expr)>, Location),
+    /// `object[index]`, with the location of the opening bracket and the
+    /// span from it through the closing one.
+    Index(Box<Expr>, Box<Expr>, Location, Span),
+    /// `object[start:end]`, where either bound may be left out, with the
+    /// location of the opening bracket and the span from it through the
+    /// closing one.
+    Slice(
+        Box<Expr>,
+        Option<Box<Expr>>,
+        Option<Box<Expr>>,
+        Location,
+        Span,
+    ),
+    /// `object[index] = value`, with the location and span of the index.
+    SetIndex(Box<Expr>, Box<Expr>, Box<Expr>, Location, Span),
+    This(Location),
+    /// The location of the `super` keyword and the method looked up on it.
+    Super(Location, Identifier),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at line {}, column {}", self.line, self.column)
+    }
+}
+
+/// A name as written in the source, along with where it was written.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Identifier {
+    pub name: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Identifier {
+    pub fn location(&self) -> String {
+        format!("at line {}, column {}", self.line, self.column)
+    }
+
+    pub fn position(&self) -> Location {
+        Location {
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[non_exhaustive]
+pub enum BinaryOperator {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[non_exhaustive]
+pub enum LogicalOperator {
+    And,
+    Or,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[non_exhaustive]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+    BitNot,
+}
+
+/// A part of an interpolated string.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Segment {
+    /// Text as written, with any `\${` already turned into `${`.
+    Text(String),
+    /// The expression inside a `${}`, which is shown as `print` shows it.
+    Value(Expr),
+}
+
+/// `++` or `--`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[non_exhaustive]
+pub enum UpdateOperator {
+    Increment,
+    Decrement,
+}
+
+/// Whether `++` or `--` is written before its target, and yields the new
+/// value, or after it, and yields the old one.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Fixity {
+    Prefix,
+    Postfix,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
+pub enum Literal {
+    Number(i32),
+    String(String),
+    Bool(bool),
+    Nil,
+}
+
+pub trait ExprVisitor<T> {
+    fn visit_binary(
+        &mut self,
+        left: &Expr,
+        op: BinaryOperator,
+        right: &Expr,
+        location: Location,
+        span: Span,
+    ) -> T;
+    fn visit_logical(&mut self, left: &Expr, op: LogicalOperator, right: &Expr) -> T;
+    fn visit_comma(&mut self, left: &Expr, right: &Expr) -> T;
+    fn visit_conditional(&mut self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) -> T;
+    fn visit_unary(&mut self, op: UnaryOperator, right: &Expr, location: Location, span: Span)
+    -> T;
+    fn visit_grouping(&mut self, expr: &Expr) -> T;
+    fn visit_literal(&mut self, literal: &Literal) -> T;
+    fn visit_interpolation(&mut self, segments: &[Segment]) -> T;
+    fn visit_variable(&mut self, name: &Identifier) -> T;
+    fn visit_assign(&mut self, name: &Identifier, value: &Expr) -> T;
+    fn visit_compound_assign(
+        &mut self,
+        target: &Expr,
+        op: BinaryOperator,
+        value: &Expr,
+        location: Location,
+        span: Span,
+    ) -> T;
+    fn visit_update(
+        &mut self,
+        target: &Expr,
+        op: UpdateOperator,
+        fixity: Fixity,
+        location: Location,
+        span: Span,
+    ) -> T;
+    fn visit_call(
+        &mut self,
+        callee: &Expr,
+        arguments: &[Expr],
+        location: Location,
+        span: Span,
+    ) -> T;
+    fn visit_get(&mut self, object: &Expr, name: &Identifier, span: Span) -> T;
+    fn visit_set(&mut self, object: &Expr, name: &Identifier, value: &Expr, span: Span) -> T;
+    fn visit_list(&mut self, elements: &[Expr], location: Location) -> T;
+    fn visit_map(&mut self, entries: &[(Expr, Expr)], location: Location) -> T;
+    fn visit_index(&mut self, object: &Expr, index: &Expr, location: Location, span: Span) -> T;
+    fn visit_slice(
+        &mut self,
+        object: &Expr,
+        start: Option<&Expr>,
+        end: Option<&Expr>,
+        location: Location,
+        span: Span,
+    ) -> T;
+    fn visit_set_index(
+        &mut self,
+        object: &Expr,
+        index: &Expr,
+        value: &Expr,
+        location: Location,
+        span: Span,
+    ) -> T;
+    fn visit_this(&mut self, location: Location) -> T;
+    fn visit_super(&mut self, location: Location, method: &Identifier) -> T;
+}
+
+pub trait StmtVisitor<T> {
+    fn visit_expression(&mut self, expr: &Expr) -> T;
+    fn visit_print(&mut self, expr: &Expr) -> T;
+    fn visit_var(&mut self, name: &Identifier, initializer: Option<&Expr>) -> T;
+    fn visit_block(&mut self, statements: &[Stmt]) -> T;
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) -> T;
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt, increment: Option<&Expr>) -> T;
+    fn visit_break(&mut self) -> T;
+    fn visit_continue(&mut self) -> T;
+    fn visit_function(&mut self, declaration: &Rc<FunctionDecl>) -> T;
+    fn visit_return(&mut self, value: Option<&Expr>) -> T;
+    fn visit_class(&mut self, declaration: &Rc<ClassDecl>) -> T;
+}
+
+impl Expr {
+    pub fn binary(
+        left: Expr,
+        op: BinaryOperator,
+        right: Expr,
+        location: Location,
+        span: Span,
+    ) -> Self {
+        Expr::Binary(Box::new(left), op, Box::new(right), location, span)
+    }
+
+    pub fn logical(left: Expr, op: LogicalOperator, right: Expr) -> Self {
+        Expr::Logical(Box::new(left), op, Box::new(right))
+    }
+
+    pub fn comma(left: Expr, right: Expr) -> Self {
+        Expr::Comma(Box::new(left), Box::new(right))
+    }
+
+    pub fn conditional(condition: Expr, then_branch: Expr, else_branch: Expr) -> Self {
+        Expr::Conditional(
+            Box::new(condition),
+            Box::new(then_branch),
+            Box::new(else_branch),
+        )
+    }
+
+    pub fn unary(op: UnaryOperator, right: Expr, location: Location, span: Span) -> Self {
+        Expr::Unary(op, Box::new(right), location, span)
+    }
+
+    pub fn grouping(expr: Expr) -> Self {
+        Expr::Grouping(Box::new(expr))
+    }
+
+    pub fn literal(literal: Literal) -> Self {
+        Expr::Literal(literal)
+    }
+
+    pub fn assign(name: Identifier, value: Expr) -> Self {
+        Expr::Assign(name, Box::new(value))
+    }
+
+    pub fn compound_assign(
+        target: Expr,
+        op: BinaryOperator,
+        value: Expr,
+        location: Location,
+        span: Span,
+    ) -> Self {
+        Expr::CompoundAssign(Box::new(target), op, Box::new(value), location, span)
+    }
+
+    pub fn update(
+        target: Expr,
+        op: UpdateOperator,
+        fixity: Fixity,
+        location: Location,
+        span: Span,
+    ) -> Self {
+        Expr::Update(Box::new(target), op, fixity, location, span)
+    }
+
+    pub fn call(callee: Expr, arguments: Vec<Expr>, location: Location, span: Span) -> Self {
+        Expr::Call(Box::new(callee), arguments, location, span)
+    }
+
+    pub fn get(object: Expr, name: Identifier, span: Span) -> Self {
+        Expr::Get(Box::new(object), name, span)
+    }
+
+    pub fn set(object: Expr, name: Identifier, value: Expr, span: Span) -> Self {
+        Expr::Set(Box::new(object), name, Box::new(value), span)
+    }
+
+    pub fn index(object: Expr, index: Expr, location: Location, span: Span) -> Self {
+        Expr::Index(Box::new(object), Box::new(index), location, span)
+    }
+
+    pub fn slice(
+        object: Expr,
+        start: Option<Expr>,
+        end: Option<Expr>,
+        location: Location,
+        span: Span,
+    ) -> Self {
+        Expr::Slice(
+            Box::new(object),
+            start.map(Box::new),
+            end.map(Box::new),
+            location,
+            span,
+        )
+    }
+
+    pub fn set_index(
+        object: Expr,
+        index: Expr,
+        value: Expr,
+        location: Location,
+        span: Span,
+    ) -> Self {
+        Expr::SetIndex(
+            Box::new(object),
+            Box::new(index),
+            Box::new(value),
+            location,
+            span,
+        )
+    }
+
+    pub fn accept<T>(&self, visitor: &mut impl ExprVisitor<T>) -> T {
+        match self {
+            Expr::Binary(left, op, right, location, span) => {
+                visitor.visit_binary(left, *op, right, *location, *span)
+            }
+            Expr::Logical(left, op, right) => visitor.visit_logical(left, *op, right),
+            Expr::Comma(left, right) => visitor.visit_comma(left, right),
+            Expr::Conditional(condition, then_branch, else_branch) => {
+                visitor.visit_conditional(condition, then_branch, else_branch)
+            }
+            Expr::Unary(op, right, location, span) => {
+                visitor.visit_unary(*op, right, *location, *span)
+            }
+            Expr::Grouping(expr) => visitor.visit_grouping(expr),
+            Expr::Literal(literal) => visitor.visit_literal(literal),
+            Expr::Interpolation(segments) => visitor.visit_interpolation(segments),
+            Expr::Variable(name) => visitor.visit_variable(name),
+            Expr::Assign(name, value) => visitor.visit_assign(name, value),
+            Expr::CompoundAssign(target, op, value, location, span) => {
+                visitor.visit_compound_assign(target, *op, value, *location, *span)
+            }
+            Expr::Update(target, op, fixity, location, span) => {
+                visitor.visit_update(target, *op, *fixity, *location, *span)
+            }
+            Expr::Call(callee, arguments, location, span) => {
+                visitor.visit_call(callee, arguments, *location, *span)
+            }
+            Expr::Get(object, name, span) => visitor.visit_get(object, name, *span),
+            Expr::Set(object, name, value, span) => visitor.visit_set(object, name, value, *span),
+            Expr::List(elements, location) => visitor.visit_list(elements, *location),
+            Expr::Map(entries, location) => visitor.visit_map(entries, *location),
+            Expr::Index(object, index, location, span) => {
+                visitor.visit_index(object, index, *location, *span)
+            }
+            Expr::Slice(object, start, end, location, span) => {
+                visitor.visit_slice(object, start.as_deref(), end.as_deref(), *location, *span)
+            }
+            Expr::SetIndex(object, index, value, location, span) => {
+                visitor.visit_set_index(object, index, value, *location, *span)
+            }
+            Expr::This(location) => visitor.visit_this(*location),
+            Expr::Super(location, method) => visitor.visit_super(*location, method),
+        }
+    }
+}
+
+impl Stmt {
+    pub fn accept<T>(&self, visitor: &mut impl StmtVisitor<T>) -> T {
+        match self {
+            Stmt::Expression(expr) => visitor.visit_expression(expr),
+            Stmt::Print(expr) => visitor.visit_print(expr),
+            Stmt::Var(name, initializer) => visitor.visit_var(name, initializer.as_ref()),
+            Stmt::Block(statements) => visitor.visit_block(statements),
+            Stmt::If(condition, then_branch, else_branch) => {
+                visitor.visit_if(condition, then_branch, else_branch.as_deref())
+            }
+            Stmt::While(condition, body, increment) => {
+                visitor.visit_while(condition, body, increment.as_ref())
+            }
+            Stmt::Break => visitor.visit_break(),
+            Stmt::Continue => visitor.visit_continue(),
+            Stmt::Function(declaration) => visitor.visit_function(declaration),
+            Stmt::Return(value) => visitor.visit_return(value.as_ref()),
+            Stmt::Class(declaration) => visitor.visit_class(declaration),
+        }
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessEqual => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterEqual => ">=",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+            BinaryOperator::Power => "**",
+            BinaryOperator::BitAnd => "&",
+            BinaryOperator::BitOr => "|",
+            BinaryOperator::BitXor => "^",
+            BinaryOperator::ShiftLeft => "<<",
+            BinaryOperator::ShiftRight => ">>",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl fmt::Display for LogicalOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keyword = match self {
+            LogicalOperator::And => "and",
+            LogicalOperator::Or => "or",
+        };
+        write!(f, "{}", keyword)
+    }
+}
+
+impl fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            UnaryOperator::Negate => "-",
+            UnaryOperator::Not => "!",
+            UnaryOperator::BitNot => "~",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl fmt::Display for UpdateOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            UpdateOperator::Increment => "++",
+            UpdateOperator::Decrement => "--",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Number(n) => write!(f, "{}", n),
+            Literal::String(s) => write!(f, "{:?}", s),
+            Literal::Bool(b) => write!(f, "{}", b),
+            Literal::Nil => write!(f, "nil"),
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.accept(&mut Parenthesizer))
+    }
+}
+
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.accept(&mut Parenthesizer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_as_prefix_expression() {
+        let at = Location { line: 1, column: 1 };
+        let expr = Expr::binary(
+            Expr::unary(
+                UnaryOperator::Negate,
+                Expr::literal(Literal::Number(1)),
+                at,
+                Span::default(),
+            ),
+            BinaryOperator::Multiply,
+            Expr::grouping(Expr::binary(
+                Expr::literal(Literal::String("a".to_string())),
+                BinaryOperator::Add,
+                Expr::literal(Literal::Nil),
+                at,
+                Span::default(),
+            )),
+            at,
+            Span::default(),
+        );
+        assert_eq!(expr.to_string(), r#"(* (- 1) (group (+ "a" nil)))"#);
+    }
+
+    #[test]
+    fn displays_statements() {
+        let stmt = Stmt::Print(Expr::literal(Literal::Bool(true)));
+        assert_eq!(stmt.to_string(), "(print true)");
+
+        let name = Identifier {
+            name: "x".to_string(),
+            line: 1,
+            column: 5,
+        };
+        let stmt = Stmt::Var(name.clone(), None);
+        assert_eq!(stmt.to_string(), "(var x)");
+        let stmt = Stmt::Expression(Expr::assign(name.clone(), Expr::Variable(name)));
+        assert_eq!(stmt.to_string(), "(expr (= x x))");
+    }
+}
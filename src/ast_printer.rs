@@ -0,0 +1,685 @@
+use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::ast::{
+    BinaryOperator, ClassDecl, Expr, ExprVisitor, Fixity, FunctionDecl, Identifier, Literal,
+    Location, LogicalOperator, Program, Segment, Stmt, StmtVisitor, UnaryOperator, UpdateOperator,
+};
+use crate::token::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    /// One line per statement in parenthesized prefix form.
+    SExpr,
+    /// One line per node, indented under its parent.
+    Tree,
+}
+
+/// Renders `program` in the given style, ending with a newline unless the
+/// program is empty.
+pub fn print(program: &Program, style: Style) -> String {
+    match style {
+        Style::SExpr => program
+            .iter()
+            .map(|stmt| format!("{}\n", stmt.accept(&mut Parenthesizer)))
+            .collect(),
+        Style::Tree => {
+            let mut printer = TreePrinter {
+                depth: 0,
+                out: String::new(),
+            };
+            for stmt in program {
+                stmt.accept(&mut printer);
+            }
+            printer.out
+        }
+    }
+}
+
+// Renders nodes in a parenthesized prefix form, e.g. `(+ 1 (* 2 3))`.
+pub(crate) struct Parenthesizer;
+
+impl ExprVisitor<String> for Parenthesizer {
+    fn visit_binary(
+        &mut self,
+        left: &Expr,
+        op: BinaryOperator,
+        right: &Expr,
+        _location: Location,
+        _span: Span,
+    ) -> String {
+        format!("({} {} {})", op, left.accept(self), right.accept(self))
+    }
+
+    fn visit_logical(&mut self, left: &Expr, op: LogicalOperator, right: &Expr) -> String {
+        format!("({} {} {})", op, left.accept(self), right.accept(self))
+    }
+
+    fn visit_comma(&mut self, left: &Expr, right: &Expr) -> String {
+        format!("(, {} {})", left.accept(self), right.accept(self))
+    }
+
+    fn visit_conditional(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Expr,
+        else_branch: &Expr,
+    ) -> String {
+        format!(
+            "(?: {} {} {})",
+            condition.accept(self),
+            then_branch.accept(self),
+            else_branch.accept(self)
+        )
+    }
+
+    fn visit_unary(
+        &mut self,
+        op: UnaryOperator,
+        right: &Expr,
+        _location: Location,
+        _span: Span,
+    ) -> String {
+        format!("({} {})", op, right.accept(self))
+    }
+
+    fn visit_grouping(&mut self, expr: &Expr) -> String {
+        format!("(group {})", expr.accept(self))
+    }
+
+    fn visit_literal(&mut self, literal: &Literal) -> String {
+        literal.to_string()
+    }
+
+    fn visit_interpolation(&mut self, segments: &[Segment]) -> String {
+        let mut parts = vec!["interpolate".to_string()];
+        parts.extend(segments.iter().map(|segment| match segment {
+            Segment::Text(s) => format!("{:?}", s),
+            Segment::Value(expr) => expr.accept(self),
+        }));
+        format!("({})", parts.join(" "))
+    }
+
+    fn visit_variable(&mut self, name: &Identifier) -> String {
+        name.name.clone()
+    }
+
+    fn visit_assign(&mut self, name: &Identifier, value: &Expr) -> String {
+        format!("(= {} {})", name.name, value.accept(self))
+    }
+
+    fn visit_compound_assign(
+        &mut self,
+        target: &Expr,
+        op: BinaryOperator,
+        value: &Expr,
+        _location: Location,
+        _span: Span,
+    ) -> String {
+        format!("({}= {} {})", op, target.accept(self), value.accept(self))
+    }
+
+    fn visit_update(
+        &mut self,
+        target: &Expr,
+        op: UpdateOperator,
+        fixity: Fixity,
+        _location: Location,
+        _span: Span,
+    ) -> String {
+        let fixity = match fixity {
+            Fixity::Prefix => "prefix",
+            Fixity::Postfix => "postfix",
+        };
+        format!("({} {} {})", fixity, op, target.accept(self))
+    }
+
+    fn visit_call(
+        &mut self,
+        callee: &Expr,
+        arguments: &[Expr],
+        _location: Location,
+        _span: Span,
+    ) -> String {
+        let mut parts = vec![callee.accept(self)];
+        parts.extend(arguments.iter().map(|a| a.accept(self)));
+        format!("(call {})", parts.join(" "))
+    }
+
+    fn visit_get(&mut self, object: &Expr, name: &Identifier, _span: Span) -> String {
+        format!("(. {} {})", object.accept(self), name.name)
+    }
+
+    fn visit_set(&mut self, object: &Expr, name: &Identifier, value: &Expr, _span: Span) -> String {
+        format!(
+            "(= (. {} {}) {})",
+            object.accept(self),
+            name.name,
+            value.accept(self)
+        )
+    }
+
+    fn visit_list(&mut self, elements: &[Expr], _location: Location) -> String {
+        let mut parts = vec!["list".to_string()];
+        parts.extend(elements.iter().map(|e| e.accept(self)));
+        format!("({})", parts.join(" "))
+    }
+
+    fn visit_map(&mut self, entries: &[(Expr, Expr)], _location: Location) -> String {
+        let mut parts = vec!["map".to_string()];
+        for (key, value) in entries {
+            parts.push(format!("({} {})", key.accept(self), value.accept(self)));
+        }
+        format!("({})", parts.join(" "))
+    }
+
+    fn visit_index(
+        &mut self,
+        object: &Expr,
+        index: &Expr,
+        _location: Location,
+        _span: Span,
+    ) -> String {
+        format!("(index {} {})", object.accept(self), index.accept(self))
+    }
+
+    fn visit_slice(
+        &mut self,
+        object: &Expr,
+        start: Option<&Expr>,
+        end: Option<&Expr>,
+        _location: Location,
+        _span: Span,
+    ) -> String {
+        let mut bound = |bound: Option<&Expr>| bound.map_or("_".to_string(), |b| b.accept(self));
+        let start = bound(start);
+        let end = bound(end);
+        format!("(slice {} {} {})", object.accept(self), start, end)
+    }
+
+    fn visit_set_index(
+        &mut self,
+        object: &Expr,
+        index: &Expr,
+        value: &Expr,
+        _location: Location,
+        _span: Span,
+    ) -> String {
+        format!(
+            "(= (index {} {}) {})",
+            object.accept(self),
+            index.accept(self),
+            value.accept(self)
+        )
+    }
+
+    fn visit_this(&mut self, _location: Location) -> String {
+        "this".to_string()
+    }
+
+    fn visit_super(&mut self, _location: Location, method: &Identifier) -> String {
+        format!("(super {})", method.name)
+    }
+}
+
+impl StmtVisitor<String> for Parenthesizer {
+    fn visit_expression(&mut self, expr: &Expr) -> String {
+        format!("(expr {})", expr.accept(self))
+    }
+
+    fn visit_print(&mut self, expr: &Expr) -> String {
+        format!("(print {})", expr.accept(self))
+    }
+
+    fn visit_var(&mut self, name: &Identifier, initializer: Option<&Expr>) -> String {
+        match initializer {
+            Some(initializer) => format!("(var {} {})", name.name, initializer.accept(self)),
+            None => format!("(var {})", name.name),
+        }
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) -> String {
+        let statements: Vec<String> = statements.iter().map(|s| s.accept(self)).collect();
+        format!("(block {})", statements.join(" "))
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: Option<&Stmt>,
+    ) -> String {
+        match else_branch {
+            Some(else_branch) => format!(
+                "(if {} {} {})",
+                condition.accept(self),
+                then_branch.accept(self),
+                else_branch.accept(self)
+            ),
+            None => format!(
+                "(if {} {})",
+                condition.accept(self),
+                then_branch.accept(self)
+            ),
+        }
+    }
+
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt, increment: Option<&Expr>) -> String {
+        match increment {
+            Some(increment) => format!(
+                "(while {} {} {})",
+                condition.accept(self),
+                body.accept(self),
+                increment.accept(self)
+            ),
+            None => format!("(while {} {})", condition.accept(self), body.accept(self)),
+        }
+    }
+
+    fn visit_break(&mut self) -> String {
+        "(break)".to_string()
+    }
+
+    fn visit_continue(&mut self) -> String {
+        "(continue)".to_string()
+    }
+
+    fn visit_function(&mut self, declaration: &Rc<FunctionDecl>) -> String {
+        let params: Vec<&str> = declaration.params.iter().map(|p| p.name.as_str()).collect();
+        let body: Vec<String> = declaration.body.iter().map(|s| s.accept(self)).collect();
+        format!(
+            "(fun {} ({}) {})",
+            declaration.name.name,
+            params.join(" "),
+            body.join(" ")
+        )
+    }
+
+    fn visit_return(&mut self, value: Option<&Expr>) -> String {
+        match value {
+            Some(value) => format!("(return {})", value.accept(self)),
+            None => "(return)".to_string(),
+        }
+    }
+
+    fn visit_class(&mut self, declaration: &Rc<ClassDecl>) -> String {
+        let mut members = Vec::new();
+        for field in &declaration.fields {
+            let initializer = field.initializer.accept(self);
+            members.push(format!("(field {} {})", field.name.name, initializer));
+        }
+        for method in &declaration.methods {
+            members.push(self.visit_function(method));
+        }
+        match &declaration.superclass {
+            Some(superclass) => format!(
+                "(class {} < {} {})",
+                declaration.name.name,
+                superclass.name,
+                members.join(" ")
+            ),
+            None => format!("(class {} {})", declaration.name.name, members.join(" ")),
+        }
+    }
+}
+
+struct TreePrinter {
+    depth: usize,
+    out: String,
+}
+
+impl TreePrinter {
+    fn line(&mut self, text: impl Display) {
+        self.out += &format!("{:indent$}{}\n", "", text, indent = self.depth * 2);
+    }
+
+    // Writes `label`, then whatever `children` writes indented beneath it.
+    fn node(&mut self, label: impl Display, children: impl FnOnce(&mut Self)) {
+        self.line(label);
+        self.depth += 1;
+        children(self);
+        self.depth -= 1;
+    }
+
+    fn function(&mut self, label: &str, declaration: &FunctionDecl) {
+        let params: Vec<&str> = declaration.params.iter().map(|p| p.name.as_str()).collect();
+        let label = format!("{} {}({})", label, declaration.name.name, params.join(", "));
+        self.node(label, |p| {
+            for stmt in &declaration.body {
+                stmt.accept(p);
+            }
+        });
+    }
+}
+
+impl ExprVisitor<()> for TreePrinter {
+    fn visit_binary(
+        &mut self,
+        left: &Expr,
+        op: BinaryOperator,
+        right: &Expr,
+        _location: Location,
+        _span: Span,
+    ) {
+        self.node(format!("Binary {}", op), |p| {
+            left.accept(p);
+            right.accept(p);
+        });
+    }
+
+    fn visit_logical(&mut self, left: &Expr, op: LogicalOperator, right: &Expr) {
+        self.node(format!("Logical {}", op), |p| {
+            left.accept(p);
+            right.accept(p);
+        });
+    }
+
+    fn visit_comma(&mut self, left: &Expr, right: &Expr) {
+        self.node("Comma", |p| {
+            left.accept(p);
+            right.accept(p);
+        });
+    }
+
+    fn visit_conditional(&mut self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) {
+        self.node("Conditional", |p| {
+            condition.accept(p);
+            then_branch.accept(p);
+            else_branch.accept(p);
+        });
+    }
+
+    fn visit_unary(&mut self, op: UnaryOperator, right: &Expr, _location: Location, _span: Span) {
+        self.node(format!("Unary {}", op), |p| right.accept(p));
+    }
+
+    fn visit_grouping(&mut self, expr: &Expr) {
+        self.node("Grouping", |p| expr.accept(p));
+    }
+
+    fn visit_literal(&mut self, literal: &Literal) {
+        self.line(format!("Literal {}", literal));
+    }
+
+    fn visit_interpolation(&mut self, segments: &[Segment]) {
+        self.node("Interpolation", |p| {
+            for segment in segments {
+                match segment {
+                    Segment::Text(s) => p.line(format!("Text {:?}", s)),
+                    Segment::Value(expr) => expr.accept(p),
+                }
+            }
+        });
+    }
+
+    fn visit_variable(&mut self, name: &Identifier) {
+        self.line(format!("Variable {}", name.name));
+    }
+
+    fn visit_assign(&mut self, name: &Identifier, value: &Expr) {
+        self.node(format!("Assign {}", name.name), |p| value.accept(p));
+    }
+
+    fn visit_compound_assign(
+        &mut self,
+        target: &Expr,
+        op: BinaryOperator,
+        value: &Expr,
+        _location: Location,
+        _span: Span,
+    ) {
+        self.node(format!("CompoundAssign {}=", op), |p| {
+            target.accept(p);
+            value.accept(p);
+        });
+    }
+
+    fn visit_update(
+        &mut self,
+        target: &Expr,
+        op: UpdateOperator,
+        fixity: Fixity,
+        _location: Location,
+        _span: Span,
+    ) {
+        let label = match fixity {
+            Fixity::Prefix => format!("Prefix {}", op),
+            Fixity::Postfix => format!("Postfix {}", op),
+        };
+        self.node(label, |p| target.accept(p));
+    }
+
+    fn visit_call(&mut self, callee: &Expr, arguments: &[Expr], _location: Location, _span: Span) {
+        self.node("Call", |p| {
+            callee.accept(p);
+            for argument in arguments {
+                argument.accept(p);
+            }
+        });
+    }
+
+    fn visit_get(&mut self, object: &Expr, name: &Identifier, _span: Span) {
+        self.node(format!("Get .{}", name.name), |p| object.accept(p));
+    }
+
+    fn visit_set(&mut self, object: &Expr, name: &Identifier, value: &Expr, _span: Span) {
+        self.node(format!("Set .{}", name.name), |p| {
+            object.accept(p);
+            value.accept(p);
+        });
+    }
+
+    fn visit_list(&mut self, elements: &[Expr], _location: Location) {
+        self.node("List", |p| {
+            for element in elements {
+                element.accept(p);
+            }
+        });
+    }
+
+    fn visit_map(&mut self, entries: &[(Expr, Expr)], _location: Location) {
+        self.node("Map", |p| {
+            for (key, value) in entries {
+                p.node("Entry", |p| {
+                    key.accept(p);
+                    value.accept(p);
+                });
+            }
+        });
+    }
+
+    fn visit_index(&mut self, object: &Expr, index: &Expr, _location: Location, _span: Span) {
+        self.node("Index", |p| {
+            object.accept(p);
+            index.accept(p);
+        });
+    }
+
+    fn visit_slice(
+        &mut self,
+        object: &Expr,
+        start: Option<&Expr>,
+        end: Option<&Expr>,
+        _location: Location,
+        _span: Span,
+    ) {
+        let label = match (start, end) {
+            (Some(_), Some(_)) => "Slice",
+            (Some(_), None) => "Slice from",
+            (None, Some(_)) => "Slice to",
+            (None, None) => "Slice all",
+        };
+        self.node(label, |p| {
+            object.accept(p);
+            start
+                .into_iter()
+                .chain(end)
+                .for_each(|bound| bound.accept(p));
+        });
+    }
+
+    fn visit_set_index(
+        &mut self,
+        object: &Expr,
+        index: &Expr,
+        value: &Expr,
+        _location: Location,
+        _span: Span,
+    ) {
+        self.node("SetIndex", |p| {
+            object.accept(p);
+            index.accept(p);
+            value.accept(p);
+        });
+    }
+
+    fn visit_this(&mut self, _location: Location) {
+        self.line("This");
+    }
+
+    fn visit_super(&mut self, _location: Location, method: &Identifier) {
+        self.line(format!("Super .{}", method.name));
+    }
+}
+
+impl StmtVisitor<()> for TreePrinter {
+    fn visit_expression(&mut self, expr: &Expr) {
+        self.node("Expression", |p| expr.accept(p));
+    }
+
+    fn visit_print(&mut self, expr: &Expr) {
+        self.node("Print", |p| expr.accept(p));
+    }
+
+    fn visit_var(&mut self, name: &Identifier, initializer: Option<&Expr>) {
+        self.node(format!("Var {}", name.name), |p| {
+            if let Some(initializer) = initializer {
+                initializer.accept(p);
+            }
+        });
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) {
+        self.node("Block", |p| {
+            for stmt in statements {
+                stmt.accept(p);
+            }
+        });
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) {
+        self.node("If", |p| {
+            condition.accept(p);
+            then_branch.accept(p);
+            if let Some(else_branch) = else_branch {
+                else_branch.accept(p);
+            }
+        });
+    }
+
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt, increment: Option<&Expr>) {
+        self.node("While", |p| {
+            condition.accept(p);
+            body.accept(p);
+            if let Some(increment) = increment {
+                increment.accept(p);
+            }
+        });
+    }
+
+    fn visit_break(&mut self) {
+        self.line("Break");
+    }
+
+    fn visit_continue(&mut self) {
+        self.line("Continue");
+    }
+
+    fn visit_function(&mut self, declaration: &Rc<FunctionDecl>) {
+        self.function("Fun", declaration);
+    }
+
+    fn visit_return(&mut self, value: Option<&Expr>) {
+        self.node("Return", |p| {
+            if let Some(value) = value {
+                value.accept(p);
+            }
+        });
+    }
+
+    fn visit_class(&mut self, declaration: &Rc<ClassDecl>) {
+        let label = match &declaration.superclass {
+            Some(superclass) => format!("Class {} < {}", declaration.name.name, superclass.name),
+            None => format!("Class {}", declaration.name.name),
+        };
+        self.node(label, |p| {
+            for field in &declaration.fields {
+                p.node(format!("Field {}", field.name.name), |p| {
+                    field.initializer.accept(p)
+                });
+            }
+            for method in &declaration.methods {
+                p.function("Method", method);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::scanner::scan;
+
+    fn render(source: &str, style: Style) -> String {
+        print(&parse(&scan(source).unwrap()).unwrap(), style)
+    }
+
+    #[test]
+    fn prints_s_expressions() {
+        assert_eq!(
+            render("var a = 1 + 2 * 3;\nprint -(a);", Style::SExpr),
+            "(var a (+ 1 (* 2 3)))\n(print (- (group a)))\n"
+        );
+    }
+
+    #[test]
+    fn prints_an_indented_tree() {
+        let source = r#"
+            class B < A {
+                n = 0;
+                f(x) { if (x or nil) return this.g(x); }
+            }
+            print "a" + 1 * 2;
+        "#;
+        let expected = [
+            "Class B < A",
+            "  Field n",
+            "    Literal 0",
+            "  Method f(x)",
+            "    If",
+            "      Logical or",
+            "        Variable x",
+            "        Literal nil",
+            "      Return",
+            "        Call",
+            "          Get .g",
+            "            This",
+            "          Variable x",
+            "Print",
+            "  Binary +",
+            "    Literal \"a\"",
+            "    Binary *",
+            "      Literal 1",
+            "      Literal 2",
+            "",
+        ];
+        assert_eq!(render(source, Style::Tree), expected.join("\n"));
+    }
+
+    #[test]
+    fn prints_nothing_for_an_empty_program() {
+        assert_eq!(render("", Style::SExpr), "");
+        assert_eq!(render("// only a comment", Style::Tree), "");
+    }
+}
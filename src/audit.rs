@@ -0,0 +1,312 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::ast::{Expr, FunctionDecl, Identifier, Program, Segment, Stmt};
+
+/// Something outside the interpreter a script can reach through a native
+/// function.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub enum Capability {
+    Clock,
+    Filesystem,
+    /// Reading from standard input.
+    Input,
+    Network,
+    Process,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Capability::Clock => "clock",
+            Capability::Filesystem => "filesystem",
+            Capability::Input => "input",
+            Capability::Network => "network",
+            Capability::Process => "process",
+        };
+        f.pad(name)
+    }
+}
+
+// What each native in the standard library reaches, or `None` for those
+// that only compute.
+const NATIVES: &[(&str, Option<Capability>)] = &[
+    ("clock", Some(Capability::Clock)),
+    ("len", None),
+    ("substr", None),
+    ("parseNumber", None),
+    ("str", None),
+    ("abs", None),
+    ("floor", None),
+    ("sqrt", None),
+    ("readLine", Some(Capability::Input)),
+    ("readFile", Some(Capability::Filesystem)),
+    ("writeFile", Some(Capability::Filesystem)),
+    ("flush", None),
+    ("push", None),
+    ("pop", None),
+    ("keys", None),
+    ("values", None),
+    ("has", None),
+];
+
+/// A place where a script refers to a native that needs a capability.
+#[derive(Debug, PartialEq)]
+pub struct Use {
+    pub capability: Capability,
+    pub native: Identifier,
+}
+
+/// Lists every reference to a native that needs a capability, sorted by
+/// capability and then position.
+///
+/// A reference counts whether or not it is called, since the function can
+/// be called later through another variable. Names a script declares
+/// itself inside a function or block are not natives there; a global the
+/// script declares with a native's name is still reported, because the
+/// native is in place until the declaration runs.
+pub fn audit(program: &Program) -> Vec<Use> {
+    let mut auditor = Auditor {
+        scopes: Vec::new(),
+        uses: Vec::new(),
+    };
+    for stmt in program {
+        auditor.stmt(stmt);
+    }
+    let mut uses = auditor.uses;
+    uses.sort_by_key(|u| (u.capability, u.native.line, u.native.column));
+    uses
+}
+
+/// The capabilities `uses` need, each once.
+pub fn capabilities(uses: &[Use]) -> Vec<Capability> {
+    let mut capabilities: Vec<Capability> = uses.iter().map(|u| u.capability).collect();
+    capabilities.dedup();
+    capabilities
+}
+
+struct Auditor {
+    // The names declared in each enclosing function and block scope. The
+    // globals are not tracked.
+    scopes: Vec<HashSet<String>>,
+    uses: Vec<Use>,
+}
+
+impl Auditor {
+    fn declare(&mut self, name: &Identifier) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.name.clone());
+        }
+    }
+
+    fn reference(&mut self, name: &Identifier) {
+        if self.scopes.iter().any(|scope| scope.contains(&name.name)) {
+            return;
+        }
+        if let Some((_, Some(capability))) = NATIVES.iter().find(|(n, _)| *n == name.name) {
+            self.uses.push(Use {
+                capability: *capability,
+                native: name.clone(),
+            });
+        }
+    }
+
+    fn block(&mut self, statements: &[Stmt]) {
+        self.scopes.push(HashSet::new());
+        for stmt in statements {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn function(&mut self, declaration: &FunctionDecl) {
+        self.scopes
+            .push(declaration.params.iter().map(|p| p.name.clone()).collect());
+        for stmt in &declaration.body {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(expr) | Stmt::Print(expr) => self.expr(expr),
+            Stmt::Var(name, initializer) => {
+                if let Some(initializer) = initializer {
+                    self.expr(initializer);
+                }
+                self.declare(name);
+            }
+            Stmt::Block(statements) => self.block(statements),
+            Stmt::If(condition, then_branch, else_branch) => {
+                self.expr(condition);
+                self.stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::While(condition, body, increment) => {
+                self.expr(condition);
+                self.stmt(body);
+                if let Some(increment) = increment {
+                    self.expr(increment);
+                }
+            }
+            Stmt::Break | Stmt::Continue => {}
+            Stmt::Function(declaration) => {
+                self.declare(&declaration.name);
+                self.function(declaration);
+            }
+            Stmt::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            Stmt::Class(declaration) => {
+                self.declare(&declaration.name);
+                if let Some(superclass) = &declaration.superclass {
+                    self.reference(superclass);
+                }
+                for field in &declaration.fields {
+                    self.expr(&field.initializer);
+                }
+                for method in &declaration.methods {
+                    self.function(method);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary(left, _, right, ..)
+            | Expr::Logical(left, _, right)
+            | Expr::Comma(left, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Conditional(condition, then_branch, else_branch) => {
+                self.expr(condition);
+                self.expr(then_branch);
+                self.expr(else_branch);
+            }
+            Expr::Unary(_, expr, ..)
+            | Expr::Grouping(expr)
+            | Expr::Get(expr, ..)
+            | Expr::Update(expr, ..) => self.expr(expr),
+            Expr::Literal(_) | Expr::This(_) | Expr::Super(..) => {}
+            Expr::Interpolation(segments) => {
+                for segment in segments {
+                    if let Segment::Value(expr) = segment {
+                        self.expr(expr);
+                    }
+                }
+            }
+            Expr::Variable(name) => self.reference(name),
+            Expr::Assign(_, value) => self.expr(value),
+            Expr::CompoundAssign(target, _, value, ..) => {
+                self.expr(target);
+                self.expr(value);
+            }
+            Expr::Call(callee, arguments, ..) => {
+                self.expr(callee);
+                arguments.iter().for_each(|a| self.expr(a));
+            }
+            Expr::Set(object, _, value, _) => {
+                self.expr(object);
+                self.expr(value);
+            }
+            Expr::List(elements, _) => elements.iter().for_each(|e| self.expr(e)),
+            Expr::Map(entries, _) => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            Expr::Index(object, index, ..) => {
+                self.expr(object);
+                self.expr(index);
+            }
+            Expr::Slice(object, start, end, ..) => {
+                self.expr(object);
+                start.iter().chain(end).for_each(|bound| self.expr(bound));
+            }
+            Expr::SetIndex(object, index, value, ..) => {
+                self.expr(object);
+                self.expr(index);
+                self.expr(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::parser::parse;
+    use crate::scanner::scan;
+
+    fn audit_source(source: &str) -> Vec<(Capability, String)> {
+        audit(&parse(&scan(source).unwrap()).unwrap())
+            .into_iter()
+            .map(|u| {
+                (
+                    u.capability,
+                    format!("{} {}", u.native.name, u.native.location()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reports_natives_that_need_capabilities() {
+        type Expected<'a> = &'a [(Capability, &'a str)];
+        let cases: &[(&str, &str, Expected)] = &[
+            ("pure natives", "print len(\"abc\") + abs(-1);", &[]),
+            (
+                "sorted by capability",
+                "var text = readFile(\"in\");\nprint clock();\nwriteFile(\"out\", text);",
+                &[
+                    (Capability::Clock, "clock at line 2, column 7"),
+                    (Capability::Filesystem, "readFile at line 1, column 12"),
+                    (Capability::Filesystem, "writeFile at line 3, column 1"),
+                ],
+            ),
+            (
+                "references that are not calls",
+                "var now = clock; fun later() { return readLine; }",
+                &[
+                    (Capability::Clock, "clock at line 1, column 11"),
+                    (Capability::Input, "readLine at line 1, column 39"),
+                ],
+            ),
+            (
+                "names a script declares locally",
+                "fun f(clock) { return clock; } { var readFile = 1; print readFile; } print readFile;",
+                &[(Capability::Filesystem, "readFile at line 1, column 76")],
+            ),
+        ];
+        for (name, source, expected) in cases {
+            let expected: Vec<(Capability, String)> = expected
+                .iter()
+                .map(|(capability, at)| (*capability, at.to_string()))
+                .collect();
+            assert_eq!(audit_source(source), expected, "case: {}", name);
+        }
+    }
+
+    #[test]
+    fn every_native_is_classified() {
+        let interpreter = Interpreter::with_output(io::sink());
+        for global in interpreter.globals() {
+            assert!(
+                NATIVES.iter().any(|(name, _)| *name == global.name),
+                "native '{}' is missing from the audit table",
+                global.name
+            );
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+use crate::ast::Program;
+use crate::interpreter::Interpreter;
+use crate::json;
+use crate::parser::parse;
+use crate::scanner::scan;
+use crate::vm::Vm;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Backend {
+    Tree,
+    Vm,
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // `pad` so that the table can line backends up.
+        match self {
+            Backend::Tree => f.pad("tree"),
+            Backend::Vm => f.pad("vm"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    /// Timed runs per program and backend.
+    pub runs: usize,
+    /// Untimed runs before those, to warm caches.
+    pub warmup: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            runs: 10,
+            warmup: 2,
+        }
+    }
+}
+
+/// Summary statistics of a set of timed runs.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Stats {
+    pub mean: Duration,
+    pub median: Duration,
+    /// The population standard deviation.
+    pub stddev: Duration,
+}
+
+impl Stats {
+    pub fn from_samples(samples: &[Duration]) -> Self {
+        assert!(!samples.is_empty(), "no samples to summarize");
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let middle = sorted.len() / 2;
+        let median = if sorted.len().is_multiple_of(2) {
+            (sorted[middle - 1] + sorted[middle]) / 2
+        } else {
+            sorted[middle]
+        };
+
+        let seconds: Vec<f64> = samples.iter().map(Duration::as_secs_f64).collect();
+        let mean = seconds.iter().sum::<f64>() / seconds.len() as f64;
+        let variance =
+            seconds.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / seconds.len() as f64;
+        Self {
+            mean: Duration::from_secs_f64(mean),
+            median,
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+        }
+    }
+}
+
+/// The timings of one program on one backend, or why it could not run.
+#[derive(Debug)]
+pub struct Measurement {
+    pub program: String,
+    pub backend: Backend,
+    pub result: Result<Stats, String>,
+}
+
+/// Times every `.lox` file in `dir`, in name order, on both backends.
+/// Output the programs print is discarded.
+pub fn run_dir(dir: &Path, options: Options) -> Result<Vec<Measurement>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("could not read '{}'", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "lox") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut measurements = Vec::new();
+    for path in paths {
+        let source = fs::read_to_string(&path)
+            .with_context(|| format!("could not read '{}'", path.display()))?;
+        let program = path.file_name().unwrap().to_string_lossy().into_owned();
+        let parsed = scan(&source).and_then(|tokens| parse(&tokens));
+        for backend in [Backend::Tree, Backend::Vm] {
+            let result = match &parsed {
+                Ok(parsed) => measure(parsed, backend, options),
+                Err(error) => Err(anyhow::anyhow!("{}", error)),
+            };
+            measurements.push(Measurement {
+                program: program.clone(),
+                backend,
+                result: result.map_err(|error| format!("{:#}", error)),
+            });
+        }
+    }
+    Ok(measurements)
+}
+
+/// Runs `program` on a fresh `backend` for each warmup and timed run.
+pub fn measure(program: &Program, backend: Backend, options: Options) -> Result<Stats> {
+    let mut samples = Vec::with_capacity(options.runs);
+    for run in 0..options.warmup + options.runs.max(1) {
+        let start = Instant::now();
+        match backend {
+            Backend::Tree => Interpreter::with_output(io::sink()).interpret(program)?,
+            Backend::Vm => Vm::with_output(io::sink()).interpret(program)?,
+        }
+        if run >= options.warmup {
+            samples.push(start.elapsed());
+        }
+    }
+    Ok(Stats::from_samples(&samples))
+}
+
+/// One line per measurement, times in milliseconds.
+pub fn to_table(measurements: &[Measurement]) -> String {
+    let width = measurements
+        .iter()
+        .map(|m| m.program.len())
+        .max()
+        .unwrap_or(0);
+    let mut table = format!(
+        "{:width$}  backend  {:>10}  {:>10}  {:>10}\n",
+        "program", "mean ms", "median ms", "stddev ms"
+    );
+    for m in measurements {
+        let line = match &m.result {
+            Ok(stats) => format!(
+                "{:width$}  {:7}  {:10.3}  {:10.3}  {:10.3}",
+                m.program,
+                m.backend,
+                millis(stats.mean),
+                millis(stats.median),
+                millis(stats.stddev)
+            ),
+            Err(error) => format!(
+                "{:width$}  {:7}  failed: {}",
+                m.program,
+                m.backend,
+                error.lines().next().unwrap_or_default()
+            ),
+        };
+        table += &line;
+        table.push('\n');
+    }
+    table
+}
+
+/// The measurements as a JSON array, times in seconds, for tracking
+/// results across commits.
+pub fn to_json(measurements: &[Measurement]) -> String {
+    let entries: Vec<String> = measurements
+        .iter()
+        .map(|m| {
+            let result = match &m.result {
+                Ok(stats) => format!(
+                    r#""mean": {}, "median": {}, "stddev": {}"#,
+                    stats.mean.as_secs_f64(),
+                    stats.median.as_secs_f64(),
+                    stats.stddev.as_secs_f64()
+                ),
+                Err(error) => format!(r#""error": {}"#, json::string(error)),
+            };
+            format!(
+                r#"  {{"program": {}, "backend": "{}", {}}}"#,
+                json::string(&m.program),
+                m.backend,
+                result
+            )
+        })
+        .collect();
+    format!("[\n{}\n]\n", entries.join(",\n"))
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn summarizes_samples() {
+        let stats = Stats::from_samples(&[ms(4), ms(2), ms(6), ms(8)]);
+        assert_eq!(stats.mean, ms(5));
+        assert_eq!(stats.median, ms(5));
+        // sqrt((1 + 9 + 1 + 9) / 4) = sqrt(5)
+        assert!((stats.stddev.as_secs_f64() - 5f64.sqrt() / 1000.0).abs() < 1e-9);
+
+        let stats = Stats::from_samples(&[ms(3), ms(1), ms(2)]);
+        assert_eq!(stats.median, ms(2));
+        assert_eq!(Stats::from_samples(&[ms(7)]).stddev, Duration::ZERO);
+    }
+
+    #[test]
+    fn measures_both_backends_and_reports_failures() {
+        let dir = std::env::temp_dir().join(format!("rslox-bench-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("b_loop.lox"),
+            "var i = 0; while (i < 10) i = i + 1;",
+        )
+        .unwrap();
+        fs::write(dir.join("a_class.lox"), "class A {}").unwrap();
+        fs::write(dir.join("notes.txt"), "not a benchmark").unwrap();
+
+        let options = Options { runs: 3, warmup: 1 };
+        let measurements = run_dir(&dir, options).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let summary: Vec<(&str, Backend, bool)> = measurements
+            .iter()
+            .map(|m| (m.program.as_str(), m.backend, m.result.is_ok()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("a_class.lox", Backend::Tree, true),
+                ("a_class.lox", Backend::Vm, false),
+                ("b_loop.lox", Backend::Tree, true),
+                ("b_loop.lox", Backend::Vm, true),
+            ]
+        );
+
+        let table = to_table(&measurements);
+        assert!(
+            table.contains(
+                "a_class.lox  vm       failed: compiler: the vm can't compile classes yet"
+            )
+        );
+    }
+
+    #[test]
+    fn writes_json() {
+        let measurements = [
+            Measurement {
+                program: "fib.lox".to_string(),
+                backend: Backend::Vm,
+                result: Ok(Stats {
+                    mean: ms(1500),
+                    median: ms(1250),
+                    stddev: ms(0),
+                }),
+            },
+            Measurement {
+                program: "bad.lox".to_string(),
+                backend: Backend::Tree,
+                result: Err("parser: expected \"x\"\nagain".to_string()),
+            },
+        ];
+        assert_eq!(
+            to_json(&measurements),
+            r#"[
+  {"program": "fib.lox", "backend": "vm", "mean": 1.5, "median": 1.25, "stddev": 0},
+  {"program": "bad.lox", "backend": "tree", "error": "parser: expected \"x\"\nagain"}
+]
+"#
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+
+use crate::ast::{BinaryOperator, Location, UnaryOperator, UpdateOperator};
+use crate::interner::Symbol;
+use crate::value::Value;
+
+/// One VM instruction. Operands are indices into the chunk's constants, a
+/// frame's stack slots, or, for jumps, the chunk's code, or they are the
+/// names of globals.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OpCode {
+    Constant(usize),
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal(usize),
+    SetLocal(usize),
+    /// Operands name the variable by its symbol in the interner the chunk
+    /// was compiled with.
+    GetGlobal(Symbol),
+    DefineGlobal(Symbol),
+    SetGlobal(Symbol),
+    Binary(BinaryOperator),
+    Unary(UnaryOperator),
+    /// Replaces the value on top of the stack with what `++` or `--` makes
+    /// of it.
+    Update(UpdateOperator),
+    /// Replaces that many values on top of the stack with one string of
+    /// them all, each shown as `print` shows it.
+    Interpolate(usize),
+    Print,
+    Jump(usize),
+    /// Jumps if the value on top of the stack is falsey, leaving it there.
+    JumpIfFalse(usize),
+    /// Calls the value below its arguments with that many arguments.
+    Call(usize),
+    Return,
+}
+
+/// A compiled sequence of instructions and the constants they refer to.
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<OpCode>,
+    /// Where in the source each instruction came from, in step with `code`.
+    pub locations: Vec<Location>,
+    pub constants: Vec<Value>,
+}
+
+impl Chunk {
+    /// Appends `op` and returns its index, for patching jumps later.
+    pub fn write(&mut self, op: OpCode, location: Location) -> usize {
+        self.code.push(op);
+        self.locations.push(location);
+        self.code.len() - 1
+    }
+
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+}
+
+/// A function compiled to bytecode, or the top-level script.
+#[derive(Default)]
+pub struct Function {
+    /// `None` for the script.
+    pub(crate) name: Option<String>,
+    pub(crate) arity: usize,
+    pub(crate) chunk: Chunk,
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "<fn {}>", name),
+            None => write!(f, "<script>"),
+        }
+    }
+}
@@ -0,0 +1,734 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use anyhow::{Result, bail};
+
+use crate::ast::{
+    BinaryOperator, ClassDecl, Expr, ExprVisitor, Fixity, FunctionDecl, Identifier, Literal,
+    Location, LogicalOperator, Program, Segment, Stmt, StmtVisitor, UnaryOperator, UpdateOperator,
+};
+use crate::chunk::{Function, OpCode};
+use crate::interner::{Interner, Symbol};
+use crate::token::Span;
+use crate::value::Value;
+
+/// Compiles `program` into the top-level function the VM runs.
+///
+/// The VM does not support everything the tree-walking interpreter does
+/// yet: classes, and functions that use local variables of the functions
+/// around them, fail to compile.
+///
+/// Names and string literals are interned into `interner`, which the
+/// chunks' global instructions refer to and which must be the one they
+/// are run with.
+pub fn compile(program: &Program, interner: &mut Interner) -> Result<Function> {
+    let mut compiler = Compiler {
+        functions: vec![FunctionState::new(None, 0)],
+        location: Location { line: 1, column: 1 },
+        interner,
+    };
+    for stmt in program {
+        stmt.accept(&mut compiler)?;
+    }
+    compiler.emit(OpCode::Nil);
+    compiler.emit(OpCode::Return);
+    Ok(compiler
+        .functions
+        .pop()
+        .expect("the script is never popped")
+        .function)
+}
+
+struct Compiler<'a> {
+    // The functions being compiled, from the script inward.
+    functions: Vec<FunctionState>,
+    // The source position given to the instructions being emitted: that of
+    // the last node visited that has one.
+    location: Location,
+    interner: &'a mut Interner,
+}
+
+struct FunctionState {
+    function: Function,
+    // The names of the stack slots in use, in slot order. Slot 0 holds the
+    // function being called and has no name.
+    locals: Vec<Local>,
+    scope_depth: usize,
+    // The constant holding each string literal used so far, so that a
+    // string used more than once is stored once.
+    strings: HashMap<Symbol, usize>,
+    // The loops around the code being compiled, innermost last.
+    loops: Vec<Loop>,
+}
+
+struct Loop {
+    // The scope depth outside the body. The locals declared deeper are
+    // popped when `break` or `continue` jumps out of them.
+    scope_depth: usize,
+    // The jumps still to be pointed past the loop, and at its increment.
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+struct Local {
+    name: Option<Symbol>,
+    depth: usize,
+}
+
+enum Variable {
+    Local(usize),
+    Global(Symbol),
+}
+
+impl FunctionState {
+    fn new(name: Option<String>, arity: usize) -> Self {
+        Self {
+            function: Function {
+                name,
+                arity,
+                ..Function::default()
+            },
+            locals: vec![Local {
+                name: None,
+                depth: 0,
+            }],
+            scope_depth: 0,
+            strings: HashMap::new(),
+            loops: Vec::new(),
+        }
+    }
+}
+
+impl Compiler<'_> {
+    fn current(&mut self) -> &mut FunctionState {
+        self.functions
+            .last_mut()
+            .expect("the script is never popped")
+    }
+
+    fn emit(&mut self, op: OpCode) -> usize {
+        let location = self.location;
+        self.current().function.chunk.write(op, location)
+    }
+
+    fn emit_constant(&mut self, value: Value) {
+        let index = self.current().function.chunk.add_constant(value);
+        self.emit(OpCode::Constant(index));
+    }
+
+    // Points the jump at `jump` to the next instruction emitted.
+    fn patch_jump(&mut self, jump: usize) {
+        let chunk = &mut self.current().function.chunk;
+        let target = chunk.code.len();
+        match &mut chunk.code[jump] {
+            OpCode::Jump(to) | OpCode::JumpIfFalse(to) => *to = target,
+            op => unreachable!("patched a {:?}, which is not a jump", op),
+        }
+    }
+
+    fn emit_string(&mut self, s: &str) {
+        let symbol = self.interner.intern(s);
+        let state = self.current();
+        let index = match state.strings.get(&symbol) {
+            Some(index) => *index,
+            None => {
+                let index = state
+                    .function
+                    .chunk
+                    .add_constant(Value::String(s.to_string()));
+                state.strings.insert(symbol, index);
+                index
+            }
+        };
+        self.emit(OpCode::Constant(index));
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        let state = self.current();
+        state.scope_depth -= 1;
+        let depth = state.scope_depth;
+        while self
+            .current()
+            .locals
+            .last()
+            .is_some_and(|l| l.depth > depth)
+        {
+            self.current().locals.pop();
+            self.emit(OpCode::Pop);
+        }
+    }
+
+    // Pops the locals declared inside the innermost loop and emits a jump to
+    // be patched. The compiler still knows the locals, since the code after
+    // the jump does too.
+    fn jump_out_of_loop(&mut self) -> usize {
+        let state = self.current();
+        let depth = state
+            .loops
+            .last()
+            .expect("the parser only allows break and continue inside loops")
+            .scope_depth;
+        let locals = state
+            .locals
+            .iter()
+            .rev()
+            .take_while(|l| l.depth > depth)
+            .count();
+        for _ in 0..locals {
+            self.emit(OpCode::Pop);
+        }
+        self.emit(OpCode::Jump(0))
+    }
+
+    // Declares `name` in the current scope. A local's value is whatever the
+    // instructions before this left on top of the stack, so it needs no
+    // instruction of its own.
+    fn declare(&mut self, name: &Identifier) -> Option<Symbol> {
+        let symbol = self.interner.intern(&name.name);
+        let state = self.current();
+        if state.scope_depth == 0 {
+            return Some(symbol);
+        }
+        let depth = state.scope_depth;
+        state.locals.push(Local {
+            name: Some(symbol),
+            depth,
+        });
+        None
+    }
+
+    fn define(&mut self, global: Option<Symbol>) {
+        if let Some(global) = global {
+            self.emit(OpCode::DefineGlobal(global));
+        }
+    }
+
+    fn resolve(&mut self, name: &Identifier) -> Result<Variable> {
+        self.location = name.position();
+        let symbol = self.interner.intern(&name.name);
+        let find =
+            |state: &FunctionState| state.locals.iter().rposition(|l| l.name == Some(symbol));
+        let (current, enclosing) = self
+            .functions
+            .split_last()
+            .expect("the script is never popped");
+        if let Some(slot) = find(current) {
+            return Ok(Variable::Local(slot));
+        }
+        if enclosing.iter().any(|state| find(state).is_some()) {
+            bail!(
+                "compiler: the vm can't compile closures yet, and '{}' is a local variable of an enclosing function {}",
+                name.name,
+                name.location()
+            );
+        }
+        Ok(Variable::Global(symbol))
+    }
+
+    fn function(&mut self, declaration: &FunctionDecl) -> Result<()> {
+        self.functions.push(FunctionState::new(
+            Some(declaration.name.name.clone()),
+            declaration.params.len(),
+        ));
+        self.begin_scope();
+        for param in &declaration.params {
+            self.declare(param);
+        }
+        for stmt in &declaration.body {
+            stmt.accept(self)?;
+        }
+        self.emit(OpCode::Nil);
+        self.emit(OpCode::Return);
+        let state = self.functions.pop().expect("pushed above");
+        self.location = declaration.name.position();
+        self.emit_constant(Value::Compiled(Rc::new(state.function)));
+        Ok(())
+    }
+
+    fn unsupported(&self, what: &str, location: Location) -> Result<()> {
+        bail!("compiler: the vm can't compile {} yet {}", what, location)
+    }
+}
+
+impl StmtVisitor<Result<()>> for Compiler<'_> {
+    fn visit_expression(&mut self, expr: &Expr) -> Result<()> {
+        expr.accept(self)?;
+        self.emit(OpCode::Pop);
+        Ok(())
+    }
+
+    fn visit_print(&mut self, expr: &Expr) -> Result<()> {
+        expr.accept(self)?;
+        self.emit(OpCode::Print);
+        Ok(())
+    }
+
+    fn visit_var(&mut self, name: &Identifier, initializer: Option<&Expr>) -> Result<()> {
+        match initializer {
+            Some(initializer) => initializer.accept(self)?,
+            None => {
+                self.emit(OpCode::Nil);
+            }
+        }
+        // Declared only now, so the initializer sees any outer variable of
+        // the same name, as it does in the interpreter.
+        self.location = name.position();
+        let global = self.declare(name);
+        self.define(global);
+        Ok(())
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) -> Result<()> {
+        self.begin_scope();
+        for stmt in statements {
+            stmt.accept(self)?;
+        }
+        self.end_scope();
+        Ok(())
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: Option<&Stmt>,
+    ) -> Result<()> {
+        condition.accept(self)?;
+        let then_jump = self.emit(OpCode::JumpIfFalse(0));
+        self.emit(OpCode::Pop);
+        then_branch.accept(self)?;
+        let else_jump = self.emit(OpCode::Jump(0));
+        self.patch_jump(then_jump);
+        self.emit(OpCode::Pop);
+        if let Some(else_branch) = else_branch {
+            else_branch.accept(self)?;
+        }
+        self.patch_jump(else_jump);
+        Ok(())
+    }
+
+    fn visit_while(
+        &mut self,
+        condition: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> Result<()> {
+        let loop_start = self.current().function.chunk.code.len();
+        condition.accept(self)?;
+        let exit_jump = self.emit(OpCode::JumpIfFalse(0));
+        self.emit(OpCode::Pop);
+
+        let scope_depth = self.current().scope_depth;
+        self.current().loops.push(Loop {
+            scope_depth,
+            breaks: Vec::new(),
+            continues: Vec::new(),
+        });
+        body.accept(self)?;
+        let this_loop = self.current().loops.pop().expect("pushed above");
+
+        for jump in this_loop.continues {
+            self.patch_jump(jump);
+        }
+        if let Some(increment) = increment {
+            increment.accept(self)?;
+            self.emit(OpCode::Pop);
+        }
+        self.emit(OpCode::Jump(loop_start));
+        self.patch_jump(exit_jump);
+        self.emit(OpCode::Pop);
+        // A break leaves the body with the condition already popped.
+        for jump in this_loop.breaks {
+            self.patch_jump(jump);
+        }
+        Ok(())
+    }
+
+    fn visit_break(&mut self) -> Result<()> {
+        let jump = self.jump_out_of_loop();
+        let this_loop = self.current().loops.last_mut().expect("checked above");
+        this_loop.breaks.push(jump);
+        Ok(())
+    }
+
+    fn visit_continue(&mut self) -> Result<()> {
+        let jump = self.jump_out_of_loop();
+        let this_loop = self.current().loops.last_mut().expect("checked above");
+        this_loop.continues.push(jump);
+        Ok(())
+    }
+
+    fn visit_function(&mut self, declaration: &Rc<FunctionDecl>) -> Result<()> {
+        // Declared before the body is compiled so that a local function
+        // calling itself is reported as a closure rather than taken for a
+        // global.
+        self.location = declaration.name.position();
+        let global = self.declare(&declaration.name);
+        self.function(declaration)?;
+        self.define(global);
+        Ok(())
+    }
+
+    fn visit_return(&mut self, value: Option<&Expr>) -> Result<()> {
+        match value {
+            Some(value) => value.accept(self)?,
+            None => {
+                self.emit(OpCode::Nil);
+            }
+        }
+        self.emit(OpCode::Return);
+        Ok(())
+    }
+
+    fn visit_class(&mut self, declaration: &Rc<ClassDecl>) -> Result<()> {
+        self.unsupported("classes", declaration.name.position())
+    }
+}
+
+impl ExprVisitor<Result<()>> for Compiler<'_> {
+    fn visit_binary(
+        &mut self,
+        left: &Expr,
+        op: BinaryOperator,
+        right: &Expr,
+        location: Location,
+        _span: Span,
+    ) -> Result<()> {
+        left.accept(self)?;
+        right.accept(self)?;
+        self.location = location;
+        self.emit(OpCode::Binary(op));
+        Ok(())
+    }
+
+    // Leaves whichever operand decided the result on the stack.
+    fn visit_logical(&mut self, left: &Expr, op: LogicalOperator, right: &Expr) -> Result<()> {
+        left.accept(self)?;
+        let end_jump = match op {
+            LogicalOperator::And => self.emit(OpCode::JumpIfFalse(0)),
+            LogicalOperator::Or => {
+                let else_jump = self.emit(OpCode::JumpIfFalse(0));
+                let end_jump = self.emit(OpCode::Jump(0));
+                self.patch_jump(else_jump);
+                end_jump
+            }
+        };
+        self.emit(OpCode::Pop);
+        right.accept(self)?;
+        self.patch_jump(end_jump);
+        Ok(())
+    }
+
+    fn visit_comma(&mut self, left: &Expr, right: &Expr) -> Result<()> {
+        left.accept(self)?;
+        self.emit(OpCode::Pop);
+        right.accept(self)
+    }
+
+    fn visit_conditional(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Expr,
+        else_branch: &Expr,
+    ) -> Result<()> {
+        condition.accept(self)?;
+        let then_jump = self.emit(OpCode::JumpIfFalse(0));
+        self.emit(OpCode::Pop);
+        then_branch.accept(self)?;
+        let else_jump = self.emit(OpCode::Jump(0));
+        self.patch_jump(then_jump);
+        self.emit(OpCode::Pop);
+        else_branch.accept(self)?;
+        self.patch_jump(else_jump);
+        Ok(())
+    }
+
+    fn visit_unary(
+        &mut self,
+        op: UnaryOperator,
+        right: &Expr,
+        location: Location,
+        _span: Span,
+    ) -> Result<()> {
+        right.accept(self)?;
+        self.location = location;
+        self.emit(OpCode::Unary(op));
+        Ok(())
+    }
+
+    fn visit_grouping(&mut self, expr: &Expr) -> Result<()> {
+        expr.accept(self)
+    }
+
+    fn visit_literal(&mut self, literal: &Literal) -> Result<()> {
+        match literal {
+            Literal::Number(n) => self.emit_constant(Value::Number(*n)),
+            Literal::String(s) => self.emit_string(s),
+            Literal::Bool(true) => {
+                self.emit(OpCode::True);
+            }
+            Literal::Bool(false) => {
+                self.emit(OpCode::False);
+            }
+            Literal::Nil => {
+                self.emit(OpCode::Nil);
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_interpolation(&mut self, segments: &[Segment]) -> Result<()> {
+        for segment in segments {
+            match segment {
+                Segment::Text(s) => self.emit_string(s),
+                Segment::Value(expr) => expr.accept(self)?,
+            }
+        }
+        self.emit(OpCode::Interpolate(segments.len()));
+        Ok(())
+    }
+
+    fn visit_variable(&mut self, name: &Identifier) -> Result<()> {
+        let op = match self.resolve(name)? {
+            Variable::Local(slot) => OpCode::GetLocal(slot),
+            Variable::Global(global) => OpCode::GetGlobal(global),
+        };
+        self.emit(op);
+        Ok(())
+    }
+
+    fn visit_assign(&mut self, name: &Identifier, value: &Expr) -> Result<()> {
+        value.accept(self)?;
+        let op = match self.resolve(name)? {
+            Variable::Local(slot) => OpCode::SetLocal(slot),
+            Variable::Global(global) => OpCode::SetGlobal(global),
+        };
+        self.emit(op);
+        Ok(())
+    }
+
+    // Only variables can be compiled yet, and they have no object or index
+    // to evaluate twice.
+    fn visit_compound_assign(
+        &mut self,
+        target: &Expr,
+        op: BinaryOperator,
+        value: &Expr,
+        location: Location,
+        _span: Span,
+    ) -> Result<()> {
+        let Expr::Variable(name) = target else {
+            return target.accept(self);
+        };
+        self.visit_variable(name)?;
+        value.accept(self)?;
+        self.location = location;
+        self.emit(OpCode::Binary(op));
+        let op = match self.resolve(name)? {
+            Variable::Local(slot) => OpCode::SetLocal(slot),
+            Variable::Global(global) => OpCode::SetGlobal(global),
+        };
+        self.emit(op);
+        Ok(())
+    }
+
+    // Postfix updates keep a copy of the old value below the new one, which
+    // is popped once it has been stored.
+    fn visit_update(
+        &mut self,
+        target: &Expr,
+        op: UpdateOperator,
+        fixity: Fixity,
+        location: Location,
+        _span: Span,
+    ) -> Result<()> {
+        let Expr::Variable(name) = target else {
+            return target.accept(self);
+        };
+        self.visit_variable(name)?;
+        if fixity == Fixity::Postfix {
+            self.visit_variable(name)?;
+        }
+        self.location = location;
+        self.emit(OpCode::Update(op));
+        let set = match self.resolve(name)? {
+            Variable::Local(slot) => OpCode::SetLocal(slot),
+            Variable::Global(global) => OpCode::SetGlobal(global),
+        };
+        self.emit(set);
+        if fixity == Fixity::Postfix {
+            self.emit(OpCode::Pop);
+        }
+        Ok(())
+    }
+
+    fn visit_call(
+        &mut self,
+        callee: &Expr,
+        arguments: &[Expr],
+        location: Location,
+        _span: Span,
+    ) -> Result<()> {
+        callee.accept(self)?;
+        for argument in arguments {
+            argument.accept(self)?;
+        }
+        self.location = location;
+        self.emit(OpCode::Call(arguments.len()));
+        Ok(())
+    }
+
+    fn visit_get(&mut self, _object: &Expr, name: &Identifier, _span: Span) -> Result<()> {
+        self.unsupported("properties", name.position())
+    }
+
+    fn visit_set(
+        &mut self,
+        _object: &Expr,
+        name: &Identifier,
+        _value: &Expr,
+        _span: Span,
+    ) -> Result<()> {
+        self.unsupported("properties", name.position())
+    }
+
+    fn visit_list(&mut self, _elements: &[Expr], location: Location) -> Result<()> {
+        self.unsupported("lists", location)
+    }
+
+    fn visit_map(&mut self, _entries: &[(Expr, Expr)], location: Location) -> Result<()> {
+        self.unsupported("maps", location)
+    }
+
+    fn visit_index(
+        &mut self,
+        _object: &Expr,
+        _index: &Expr,
+        location: Location,
+        _span: Span,
+    ) -> Result<()> {
+        self.unsupported("lists", location)
+    }
+
+    fn visit_slice(
+        &mut self,
+        _object: &Expr,
+        _start: Option<&Expr>,
+        _end: Option<&Expr>,
+        location: Location,
+        _span: Span,
+    ) -> Result<()> {
+        self.unsupported("slices", location)
+    }
+
+    fn visit_set_index(
+        &mut self,
+        _object: &Expr,
+        _index: &Expr,
+        _value: &Expr,
+        location: Location,
+        _span: Span,
+    ) -> Result<()> {
+        self.unsupported("lists", location)
+    }
+
+    fn visit_this(&mut self, location: Location) -> Result<()> {
+        self.unsupported("'this'", location)
+    }
+
+    fn visit_super(&mut self, location: Location, _method: &Identifier) -> Result<()> {
+        self.unsupported("'super'", location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::scanner::scan;
+    use OpCode::*;
+
+    fn compile_source(source: &str) -> Result<(Function, Interner)> {
+        let mut interner = Interner::default();
+        let script = compile(&parse(&scan(source)?)?, &mut interner)?;
+        Ok((script, interner))
+    }
+
+    #[test]
+    fn compiles_globals_and_locals() {
+        let (script, interner) = compile_source("var a = 1; { var b = a; print b; }").unwrap();
+        let a = interner.get("a").unwrap();
+        assert_eq!(
+            script.chunk.code,
+            vec![
+                Constant(0),
+                DefineGlobal(a),
+                GetGlobal(a),
+                GetLocal(1),
+                Print,
+                Pop,
+                Nil,
+                Return,
+            ]
+        );
+        assert_eq!(script.chunk.constants, vec![Value::Number(1)]);
+    }
+
+    #[test]
+    fn stores_each_string_literal_once() {
+        let (script, _) = compile_source("print \"a\"; print \"b\"; print \"a\";").unwrap();
+        assert_eq!(
+            script.chunk.code[..6],
+            [Constant(0), Print, Constant(1), Print, Constant(0), Print]
+        );
+        assert_eq!(script.chunk.constants.len(), 2);
+    }
+
+    #[test]
+    fn compiles_control_flow_to_jumps() {
+        let (script, interner) = compile_source("while (x) print 1;").unwrap();
+        assert_eq!(
+            script.chunk.code,
+            vec![
+                GetGlobal(interner.get("x").unwrap()),
+                JumpIfFalse(6),
+                Pop,
+                Constant(0),
+                Print,
+                Jump(0),
+                Pop,
+                Nil,
+                Return,
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_what_the_vm_cannot_run_yet() {
+        let cases = [
+            (
+                "classes",
+                "class A {}",
+                "compiler: the vm can't compile classes yet at line 1, column 7",
+            ),
+            (
+                "closures over function locals",
+                "fun f(a) { fun g() { return a; } }",
+                "compiler: the vm can't compile closures yet, and 'a' is a local variable of an enclosing function at line 1, column 29",
+            ),
+            (
+                "closures over block locals in the script",
+                "{ var a = 1; fun g() { return a; } }",
+                "compiler: the vm can't compile closures yet, and 'a' is a local variable of an enclosing function at line 1, column 31",
+            ),
+        ];
+        for (name, source, expected) in cases {
+            let error = compile_source(source).unwrap_err();
+            assert_eq!(error.to_string(), expected, "case: {}", name);
+        }
+    }
+}
@@ -0,0 +1,377 @@
+use std::error::Error;
+use std::fmt::{self, Write};
+
+use crate::json;
+use crate::token::Span;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[non_exhaustive]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn name(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A single problem found in a source file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    /// An optional explanation printed under the message.
+    pub note: Option<String>,
+    /// Other places the problem involves, shown alongside `span`.
+    pub labels: Vec<Label>,
+}
+
+/// A secondary span of a diagnostic, with a few words on its part in it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(message: String, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            message,
+            span,
+            note: None,
+            labels: Vec::new(),
+        }
+    }
+
+    pub fn warning(message: String, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message,
+            span,
+            note: None,
+            labels: Vec::new(),
+        }
+    }
+
+    pub fn with_note(self, note: String) -> Self {
+        Self {
+            note: Some(note),
+            ..self
+        }
+    }
+
+    pub fn with_label(mut self, span: Span, message: String) -> Self {
+        self.labels.push(Label { span, message });
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "{}", self.message)?,
+            Severity::Warning => write!(f, "warning: {}", self.message)?,
+        }
+        match &self.note {
+            Some(note) => write!(f, "\n  note: {}", note),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Every diagnostic from one pass over a file, in source order. Returned as
+/// the error of a pass that found at least one error, so callers can
+/// `downcast_ref` to see them all instead of just the first.
+#[derive(Debug, PartialEq)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<String> = self.0.iter().map(|d| d.to_string()).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+impl Error for Diagnostics {}
+
+impl Error for Diagnostic {}
+
+/// Turns diagnostics into text for one kind of reader.
+pub trait Renderer {
+    /// `source` is the text of the file at `path` that the spans point into.
+    fn render(&self, diagnostics: &[Diagnostic], path: &str, source: &str) -> String;
+}
+
+/// The renderer `--error-format` names: `plain`, `rich`, `json` or
+/// `github`.
+pub fn renderer(format: &str) -> Option<Box<dyn Renderer>> {
+    match format {
+        "plain" => Some(Box::new(Plain)),
+        "rich" => Some(Box::new(Rich)),
+        "json" => Some(Box::new(Json)),
+        "github" => Some(Box::new(GitHub)),
+        _ => None,
+    }
+}
+
+/// Each diagnostic as `Display` writes it, one after another.
+pub struct Plain;
+
+impl Renderer for Plain {
+    fn render(&self, diagnostics: &[Diagnostic], _path: &str, _source: &str) -> String {
+        diagnostics.iter().map(|d| format!("{}\n", d)).collect()
+    }
+}
+
+/// For terminals: colored headings and the offending source line with the
+/// span underlined.
+pub struct Rich;
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+impl Renderer for Rich {
+    fn render(&self, diagnostics: &[Diagnostic], path: &str, source: &str) -> String {
+        let mut rendered = String::new();
+        for d in diagnostics {
+            let color = match d.severity {
+                Severity::Error => RED,
+                Severity::Warning => YELLOW,
+            };
+            let (line, column) = line_column(source, d.span.start);
+            let last_line = d
+                .labels
+                .iter()
+                .map(|l| line_column(source, l.span.start).0)
+                .fold(line, usize::max);
+            let gutter = " ".repeat(last_line.to_string().len());
+
+            let _ = writeln!(
+                rendered,
+                "{color}{}{RESET}{BOLD}: {}{RESET}",
+                d.severity.name(),
+                d.message
+            );
+            let _ = writeln!(rendered, "{gutter}{BLUE}-->{RESET} {path}:{line}:{column}");
+            let _ = writeln!(rendered, "{gutter} {BLUE}|{RESET}");
+            // The main span is underlined with carets and the labels with
+            // dashes, each under its own line of source, in source order.
+            let mut marks: Vec<(Span, char, &str, &str)> = vec![(d.span, '^', color, "")];
+            marks.extend(
+                d.labels
+                    .iter()
+                    .map(|l| (l.span, '-', BLUE, l.message.as_str())),
+            );
+            marks.sort_by_key(|(span, ..)| span.start);
+            let mut previous_line = None;
+            for (span, marker, mark_color, message) in marks {
+                let (line, column) = line_column(source, span.start);
+                if previous_line.is_some_and(|previous| line > previous + 1) {
+                    let _ = writeln!(rendered, "{BLUE}...{RESET}");
+                }
+                previous_line = Some(line);
+                let text = source.lines().nth(line - 1).unwrap_or_default();
+                // Underline to the end of the line at most, and at least one
+                // character so that empty spans still show.
+                let rest = text
+                    .get(span.start - line_start(source, line)..)
+                    .unwrap_or_default();
+                let length = (span.end - span.start).min(rest.len());
+                let width = rest.get(..length).map_or(0, |s| s.chars().count()).max(1);
+                let number = format!("{:>1$}", line, gutter.len());
+                let _ = writeln!(rendered, "{BLUE}{number} |{RESET} {text}");
+                let _ = writeln!(
+                    rendered,
+                    "{gutter} {BLUE}|{RESET} {}{mark_color}{}{}{RESET}",
+                    " ".repeat(column - 1),
+                    marker.to_string().repeat(width),
+                    match message {
+                        "" => String::new(),
+                        message => format!(" {}", message),
+                    }
+                );
+            }
+            if let Some(note) = &d.note {
+                let _ = writeln!(
+                    rendered,
+                    "{gutter} {BLUE}={RESET} {BOLD}note{RESET}: {note}"
+                );
+            }
+        }
+        rendered
+    }
+}
+
+/// A JSON array with one object per diagnostic. Lines and columns count
+/// from 1, columns in characters; `end_line` and `end_column` are just past
+/// the span. Labels are objects with a message and the same positions.
+pub struct Json;
+
+impl Renderer for Json {
+    fn render(&self, diagnostics: &[Diagnostic], path: &str, source: &str) -> String {
+        let entries: Vec<String> = diagnostics
+            .iter()
+            .map(|d| {
+                let (line, column) = line_column(source, d.span.start);
+                let (end_line, end_column) = line_column(source, d.span.end);
+                let note = match &d.note {
+                    Some(note) => json::string(note),
+                    None => "null".to_string(),
+                };
+                let labels: Vec<String> = d
+                    .labels
+                    .iter()
+                    .map(|l| {
+                        let (line, column) = line_column(source, l.span.start);
+                        let (end_line, end_column) = line_column(source, l.span.end);
+                        format!(
+                            r#"{{"message": {}, "line": {}, "column": {}, "end_line": {}, "end_column": {}}}"#,
+                            json::string(&l.message),
+                            line,
+                            column,
+                            end_line,
+                            end_column
+                        )
+                    })
+                    .collect();
+                format!(
+                    r#"  {{"severity": "{}", "message": {}, "file": {}, "line": {}, "column": {}, "end_line": {}, "end_column": {}, "note": {}, "labels": [{}]}}"#,
+                    d.severity.name(),
+                    json::string(&d.message),
+                    json::string(path),
+                    line,
+                    column,
+                    end_line,
+                    end_column,
+                    note,
+                    labels.join(", ")
+                )
+            })
+            .collect();
+        format!("[\n{}\n]\n", entries.join(",\n"))
+    }
+}
+
+/// GitHub Actions workflow commands, which show up as annotations on the
+/// lines of a pull request. An annotation has a single position, so labels
+/// are left out.
+pub struct GitHub;
+
+impl Renderer for GitHub {
+    fn render(&self, diagnostics: &[Diagnostic], path: &str, source: &str) -> String {
+        let mut rendered = String::new();
+        for d in diagnostics {
+            let (line, column) = line_column(source, d.span.start);
+            let (end_line, end_column) = line_column(source, d.span.end);
+            let mut message = d.message.clone();
+            if let Some(note) = &d.note {
+                message = format!("{}\nnote: {}", message, note);
+            }
+            let _ = writeln!(
+                rendered,
+                "::{} file={},line={},col={},endLine={},endColumn={}::{}",
+                d.severity.name(),
+                github_escape(path).replace(',', "%2C").replace(':', "%3A"),
+                line,
+                column,
+                end_line,
+                end_column,
+                github_escape(&message)
+            );
+        }
+        rendered
+    }
+}
+
+// Workflow command data can't contain raw newlines, so GitHub decodes
+// these percent escapes.
+fn github_escape(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+// The 1-based line and character column of `byte` in `source`.
+fn line_column(source: &str, byte: usize) -> (usize, usize) {
+    let before = &source[..byte.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before[line_start(source, line)..].chars().count() + 1;
+    (line, column)
+}
+
+// The byte offset at which 1-based `line` starts.
+fn line_start(source: &str, line: usize) -> usize {
+    source
+        .split_inclusive('\n')
+        .take(line - 1)
+        .map(str::len)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<Diagnostic> {
+        vec![
+            Diagnostic::error(
+                "parser: expected ';' at line 2, column 9".to_string(),
+                Span { start: 18, end: 21 },
+            )
+            .with_label(Span { start: 4, end: 5 }, "declared here".to_string()),
+            Diagnostic::warning("lint: it's 100% odd".to_string(), Span { start: 4, end: 5 })
+                .with_note("first\nsecond".to_string()),
+        ]
+    }
+
+    const SOURCE: &str = "var a;\nprint \"é\" nil;\n";
+
+    #[test]
+    fn renders_plain_and_json() {
+        assert_eq!(
+            Plain.render(&sample(), "x.lox", SOURCE),
+            "parser: expected ';' at line 2, column 9\nwarning: lint: it's 100% odd\n  note: first\nsecond\n"
+        );
+        assert_eq!(
+            Json.render(&sample(), "x.lox", SOURCE),
+            r#"[
+  {"severity": "error", "message": "parser: expected ';' at line 2, column 9", "file": "x.lox", "line": 2, "column": 11, "end_line": 2, "end_column": 14, "note": null, "labels": [{"message": "declared here", "line": 1, "column": 5, "end_line": 1, "end_column": 6}]},
+  {"severity": "warning", "message": "lint: it's 100% odd", "file": "x.lox", "line": 1, "column": 5, "end_line": 1, "end_column": 6, "note": "first\nsecond", "labels": []}
+]
+"#
+        );
+    }
+
+    #[test]
+    fn renders_github_annotations() {
+        assert_eq!(
+            GitHub.render(&sample(), "src/a,b.lox", SOURCE),
+            "::error file=src/a%2Cb.lox,line=2,col=11,endLine=2,endColumn=14::parser: expected ';' at line 2, column 9\n\
+             ::warning file=src/a%2Cb.lox,line=1,col=5,endLine=1,endColumn=6::lint: it's 100%25 odd%0Anote: first%0Asecond\n"
+        );
+    }
+
+    #[test]
+    fn renders_rich_text_with_the_span_underlined() {
+        let rendered = Rich.render(&sample()[..1], "x.lox", SOURCE);
+        let plain = rendered
+            .replace(RED, "")
+            .replace(BLUE, "")
+            .replace(BOLD, "")
+            .replace(RESET, "");
+        assert_eq!(
+            plain,
+            "error: parser: expected ';' at line 2, column 9\n --> x.lox:2:11\n  |\n1 | var a;\n  |     - declared here\n2 | print \"é\" nil;\n  |           ^^^\n"
+        );
+    }
+}
@@ -0,0 +1,170 @@
+use std::fmt::Write;
+
+use anyhow::Result;
+
+use crate::ast::{BinaryOperator, Program, UnaryOperator, UpdateOperator};
+use crate::chunk::{Chunk, Function, OpCode};
+use crate::compiler;
+use crate::interner::Interner;
+use crate::value::Value;
+
+/// Compiles `program` for the VM and lists the instructions of the script
+/// and of every function in it.
+pub fn disassemble_program(program: &Program) -> Result<String> {
+    let mut interner = Interner::default();
+    let script = compiler::compile(program, &mut interner)?;
+    Ok(disassemble(&script, &interner))
+}
+
+/// Lists the instructions of `function` in the style of clox's debug
+/// output, followed by those of every function compiled inside it.
+/// `interner` is the one `function` was compiled with.
+pub(crate) fn disassemble(function: &Function, interner: &Interner) -> String {
+    let mut listing = String::new();
+    disassemble_into(&mut listing, function, interner);
+    listing
+}
+
+fn disassemble_into(listing: &mut String, function: &Function, interner: &Interner) {
+    let chunk = &function.chunk;
+    let _ = writeln!(listing, "== {:?} ==", function);
+    for offset in 0..chunk.code.len() {
+        let _ = writeln!(listing, "{}", instruction(chunk, offset, interner));
+    }
+    for constant in &chunk.constants {
+        if let Value::Compiled(inner) = constant {
+            listing.push('\n');
+            disassemble_into(listing, inner, interner);
+        }
+    }
+}
+
+/// One line for the instruction at `offset`: the offset, the source line
+/// (or `|` if it is the same as the previous instruction's), the opcode,
+/// and its operand.
+pub(crate) fn instruction(chunk: &Chunk, offset: usize, interner: &Interner) -> String {
+    let line = chunk.locations[offset].line;
+    let line = if offset > 0 && chunk.locations[offset - 1].line == line {
+        "   |".to_string()
+    } else {
+        format!("{:4}", line)
+    };
+
+    let op = chunk.code[offset];
+    let constant =
+        |index: usize| format!("{:<16} {:4} '{}'", name(op), index, chunk.constants[index]);
+    let operand = match op {
+        OpCode::Constant(index) => constant(index),
+        OpCode::GetGlobal(symbol) | OpCode::DefineGlobal(symbol) | OpCode::SetGlobal(symbol) => {
+            format!(
+                "{:<16} {:4} '{}'",
+                name(op),
+                symbol.index(),
+                interner.resolve(symbol)
+            )
+        }
+        OpCode::GetLocal(slot) | OpCode::SetLocal(slot) => format!("{:<16} {:4}", name(op), slot),
+        OpCode::Call(count) | OpCode::Interpolate(count) => {
+            format!("{:<16} {:4}", name(op), count)
+        }
+        OpCode::Jump(target) | OpCode::JumpIfFalse(target) => {
+            format!("{:<16} {:4} -> {}", name(op), offset, target)
+        }
+        _ => name(op).to_string(),
+    };
+    format!("{:04} {} {}", offset, line, operand)
+}
+
+fn name(op: OpCode) -> &'static str {
+    match op {
+        OpCode::Constant(_) => "OP_CONSTANT",
+        OpCode::Nil => "OP_NIL",
+        OpCode::True => "OP_TRUE",
+        OpCode::False => "OP_FALSE",
+        OpCode::Pop => "OP_POP",
+        OpCode::GetLocal(_) => "OP_GET_LOCAL",
+        OpCode::SetLocal(_) => "OP_SET_LOCAL",
+        OpCode::GetGlobal(_) => "OP_GET_GLOBAL",
+        OpCode::DefineGlobal(_) => "OP_DEFINE_GLOBAL",
+        OpCode::SetGlobal(_) => "OP_SET_GLOBAL",
+        OpCode::Binary(op) => match op {
+            BinaryOperator::Equal => "OP_EQUAL",
+            BinaryOperator::NotEqual => "OP_NOT_EQUAL",
+            BinaryOperator::Less => "OP_LESS",
+            BinaryOperator::LessEqual => "OP_LESS_EQUAL",
+            BinaryOperator::Greater => "OP_GREATER",
+            BinaryOperator::GreaterEqual => "OP_GREATER_EQUAL",
+            BinaryOperator::Add => "OP_ADD",
+            BinaryOperator::Subtract => "OP_SUBTRACT",
+            BinaryOperator::Multiply => "OP_MULTIPLY",
+            BinaryOperator::Divide => "OP_DIVIDE",
+            BinaryOperator::Modulo => "OP_MODULO",
+            BinaryOperator::Power => "OP_POWER",
+            BinaryOperator::BitAnd => "OP_BIT_AND",
+            BinaryOperator::BitOr => "OP_BIT_OR",
+            BinaryOperator::BitXor => "OP_BIT_XOR",
+            BinaryOperator::ShiftLeft => "OP_SHIFT_LEFT",
+            BinaryOperator::ShiftRight => "OP_SHIFT_RIGHT",
+        },
+        OpCode::Unary(op) => match op {
+            UnaryOperator::Negate => "OP_NEGATE",
+            UnaryOperator::Not => "OP_NOT",
+            UnaryOperator::BitNot => "OP_BIT_NOT",
+        },
+        OpCode::Update(op) => match op {
+            UpdateOperator::Increment => "OP_INCREMENT",
+            UpdateOperator::Decrement => "OP_DECREMENT",
+        },
+        OpCode::Interpolate(_) => "OP_INTERPOLATE",
+        OpCode::Print => "OP_PRINT",
+        OpCode::Jump(_) => "OP_JUMP",
+        OpCode::JumpIfFalse(_) => "OP_JUMP_IF_FALSE",
+        OpCode::Call(_) => "OP_CALL",
+        OpCode::Return => "OP_RETURN",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+    use crate::parser::parse;
+    use crate::scanner::scan;
+
+    #[test]
+    fn lists_every_function() {
+        let source = "fun add(a, b) {\n  return a + b;\n}\nif (add(1, 2) > 2) print \"big\";";
+        let mut interner = Interner::default();
+        let script = compile(&parse(&scan(source).unwrap()).unwrap(), &mut interner).unwrap();
+        assert_eq!(
+            disassemble(&script, &interner),
+            "\
+== <script> ==
+0000    1 OP_CONSTANT         0 '<fn add>'
+0001    | OP_DEFINE_GLOBAL    0 'add'
+0002    4 OP_GET_GLOBAL       0 'add'
+0003    | OP_CONSTANT         1 '1'
+0004    | OP_CONSTANT         2 '2'
+0005    | OP_CALL             2
+0006    | OP_CONSTANT         3 '2'
+0007    | OP_GREATER
+0008    | OP_JUMP_IF_FALSE    8 -> 13
+0009    | OP_POP
+0010    | OP_CONSTANT         4 'big'
+0011    | OP_PRINT
+0012    | OP_JUMP            12 -> 14
+0013    | OP_POP
+0014    | OP_NIL
+0015    | OP_RETURN
+
+== <fn add> ==
+0000    2 OP_GET_LOCAL        1
+0001    | OP_GET_LOCAL        2
+0002    | OP_ADD
+0003    | OP_RETURN
+0004    | OP_NIL
+0005    | OP_RETURN
+"
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use anyhow::{Result, bail};
+
+use crate::ast::{Identifier, Location};
+use crate::runtime_error::RuntimeError;
+use crate::value::Value;
+
+/// A single scope of variable bindings. Lookups and assignments that miss
+/// in this scope continue outward through `enclosing` until the globals.
+#[derive(Default)]
+pub struct Environment {
+    values: HashMap<String, Value>,
+    // Where each binding made by a declaration in the source was declared.
+    definitions: HashMap<String, Location>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            values: HashMap::new(),
+            definitions: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    /// Binds `name` to `value` in this scope, replacing any existing binding
+    /// in this scope and shadowing any in enclosing scopes.
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.definitions.remove(name);
+        self.values.insert(name.to_string(), value);
+    }
+
+    /// Like `define`, but also remembers where in the source `name` was
+    /// declared.
+    pub(crate) fn define_at(&mut self, name: &Identifier, value: Value) {
+        self.values.insert(name.name.clone(), value);
+        let location = Location {
+            line: name.line,
+            column: name.column,
+        };
+        self.definitions.insert(name.name.clone(), location);
+    }
+
+    /// Looks `name` up in this scope only, without consulting enclosing
+    /// scopes.
+    pub fn get_local(&self, name: &str) -> Option<Value> {
+        self.values.get(name).cloned()
+    }
+
+    /// The scope this one is nested in, or `None` for the globals.
+    pub fn enclosing(&self) -> Option<Rc<RefCell<Environment>>> {
+        self.enclosing.clone()
+    }
+
+    /// The bindings made directly in this scope, in no particular order.
+    pub fn bindings(&self) -> impl Iterator<Item = (&str, &Value, Option<Location>)> {
+        self.values.iter().map(|(name, value)| {
+            let location = self.definitions.get(name).copied();
+            (name.as_str(), value, location)
+        })
+    }
+
+    pub(crate) fn get(&self, name: &Identifier) -> Result<Value> {
+        if let Some(value) = self.values.get(&name.name) {
+            return Ok(value.clone());
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().get(name),
+            None => bail!(RuntimeError::new(
+                format!("undefined variable '{}'", name.name),
+                Some(name.position())
+            )),
+        }
+    }
+
+    pub(crate) fn assign(&mut self, name: &Identifier, value: Value) -> Result<()> {
+        if let Some(slot) = self.values.get_mut(&name.name) {
+            *slot = value;
+            return Ok(());
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => bail!(RuntimeError::new(
+                format!("undefined variable '{}'", name.name),
+                Some(name.position())
+            )),
+        }
+    }
+}
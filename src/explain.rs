@@ -0,0 +1,258 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::ast::{Program, Stmt};
+use crate::environment::Environment;
+use crate::hooks::Hooks;
+use crate::interpreter::Interpreter;
+use crate::json;
+use crate::value::Value;
+
+/// A record of a program's run, one step per statement executed.
+#[derive(Debug, PartialEq)]
+pub struct Trace {
+    pub steps: Vec<Step>,
+    /// Everything the program printed.
+    pub output: String,
+    /// The runtime error the program stopped with, if any.
+    pub error: Option<String>,
+}
+
+/// The state of the program just before one statement ran.
+#[derive(Debug, PartialEq)]
+pub struct Step {
+    /// The statement, printed as an S-expression.
+    pub statement: String,
+    /// The variables in scope as (name, value) pairs sorted by name, one
+    /// list per scope from the innermost out to the globals. Built-in
+    /// functions are left out of the globals.
+    pub scopes: Vec<Vec<(String, String)>>,
+    /// The functions being called, outermost first.
+    pub calls: Vec<String>,
+}
+
+/// Runs `program` on a fresh interpreter, recording every step.
+pub fn explain(program: &Program) -> Trace {
+    let output = Buffer::default();
+    let steps = Rc::new(RefCell::new(Vec::new()));
+    let mut interpreter = Interpreter::with_output(output.clone());
+    interpreter.set_hooks(Recorder {
+        steps: Rc::clone(&steps),
+        calls: Vec::new(),
+    });
+    let error = interpreter.interpret(program).err();
+
+    let output = String::from_utf8_lossy(&output.0.borrow()).into_owned();
+    Trace {
+        steps: steps.take(),
+        output,
+        error: error.map(|e| e.to_string()),
+    }
+}
+
+#[derive(Clone, Default)]
+struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct Recorder {
+    steps: Rc<RefCell<Vec<Step>>>,
+    calls: Vec<String>,
+}
+
+impl Hooks for Recorder {
+    fn inspect_scope(&mut self, stmt: &Stmt, scope: &Environment) {
+        let mut scopes = vec![bindings(scope)];
+        let mut enclosing = scope.enclosing();
+        while let Some(outer) = enclosing {
+            scopes.push(bindings(&outer.borrow()));
+            enclosing = outer.borrow().enclosing();
+        }
+        self.steps.borrow_mut().push(Step {
+            statement: stmt.to_string(),
+            scopes,
+            calls: self.calls.clone(),
+        });
+    }
+
+    fn on_call(&mut self, function: &Value, _arguments: &[Value]) {
+        self.calls.push(function.to_string());
+    }
+
+    fn on_return(&mut self, _function: &Value, _value: &Value) {
+        self.calls.pop();
+    }
+}
+
+fn bindings(scope: &Environment) -> Vec<(String, String)> {
+    let mut bindings: Vec<(String, String)> = scope
+        .bindings()
+        .filter(|(_, value, _)| !matches!(value, Value::Native(_)))
+        .map(|(name, value, _)| (name.to_string(), value.to_string()))
+        .collect();
+    bindings.sort();
+    bindings
+}
+
+impl Trace {
+    pub fn to_json(&self) -> String {
+        let steps: Vec<String> = self
+            .steps
+            .iter()
+            .map(|step| {
+                let scopes: Vec<String> = step
+                    .scopes
+                    .iter()
+                    .map(|scope| {
+                        let bindings: Vec<String> = scope
+                            .iter()
+                            .map(|(name, value)| {
+                                format!("{}: {}", json::string(name), json::string(value))
+                            })
+                            .collect();
+                        format!("{{{}}}", bindings.join(", "))
+                    })
+                    .collect();
+                let calls: Vec<String> = step.calls.iter().map(|c| json::string(c)).collect();
+                format!(
+                    r#"    {{"statement": {}, "scopes": [{}], "calls": [{}]}}"#,
+                    json::string(&step.statement),
+                    scopes.join(", "),
+                    calls.join(", ")
+                )
+            })
+            .collect();
+        let error = match &self.error {
+            Some(error) => json::string(error),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\n  \"steps\": [\n{}\n  ],\n  \"output\": {},\n  \"error\": {}\n}}\n",
+            steps.join(",\n"),
+            json::string(&self.output),
+            error
+        )
+    }
+
+    /// A standalone page listing each step with its scopes and calls.
+    pub fn to_html(&self) -> String {
+        let mut html = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>rslox run</title>\n</head>\n<body>\n<ol>\n",
+        );
+        for step in &self.steps {
+            html += &format!("<li>\n<pre>{}</pre>\n", escape(&step.statement));
+            if !step.calls.is_empty() {
+                let calls: Vec<String> = step.calls.iter().map(|c| escape(c)).collect();
+                html += &format!("<p>calls: {}</p>\n", calls.join(" &rarr; "));
+            }
+            html += "<table>\n";
+            for (depth, scope) in step.scopes.iter().enumerate() {
+                for (name, value) in scope {
+                    html += &format!(
+                        "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                        depth,
+                        escape(name),
+                        escape(value)
+                    );
+                }
+            }
+            html += "</table>\n</li>\n";
+        }
+        html += "</ol>\n";
+        html += &format!("<h2>output</h2>\n<pre>{}</pre>\n", escape(&self.output));
+        if let Some(error) = &self.error {
+            html += &format!("<h2>error</h2>\n<pre>{}</pre>\n", escape(error));
+        }
+        html += "</body>\n</html>\n";
+        html
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::scanner::scan;
+
+    fn explain_source(source: &str) -> Trace {
+        explain(&parse(&scan(source).unwrap()).unwrap())
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn records_each_statement_with_its_scopes_and_calls() {
+        let trace = explain_source("var a = 1;\nfun f(x) { print x + a; }\nf(2);");
+        let statements: Vec<&str> = trace.steps.iter().map(|s| s.statement.as_str()).collect();
+        assert_eq!(
+            statements,
+            [
+                "(var a 1)",
+                "(fun f (x) (print (+ x a)))",
+                "(expr (call f 2))",
+                "(print (+ x a))",
+            ]
+        );
+
+        assert_eq!(trace.steps[0].scopes, [pairs(&[])]);
+        assert_eq!(trace.steps[1].scopes, [pairs(&[("a", "1")])]);
+        let in_f = &trace.steps[3];
+        assert_eq!(
+            in_f.scopes,
+            [pairs(&[("x", "2")]), pairs(&[("a", "1"), ("f", "<fn f>")])]
+        );
+        assert_eq!(in_f.calls, ["<fn f>"]);
+        assert_eq!(trace.output, "3\n");
+        assert_eq!(trace.error, None);
+    }
+
+    #[test]
+    fn keeps_the_steps_before_an_error() {
+        let trace = explain_source("print 1;\nprint missing;");
+        assert_eq!(trace.steps.len(), 2);
+        assert_eq!(trace.output, "1\n");
+        assert_eq!(
+            trace.error.as_deref(),
+            Some("interpreter: undefined variable 'missing' at line 2, column 7")
+        );
+    }
+
+    #[test]
+    fn writes_json_and_html() {
+        let trace = explain_source("var s = \"<b>\";");
+        assert_eq!(
+            trace.to_json(),
+            r#"{
+  "steps": [
+    {"statement": "(var s \"<b>\")", "scopes": [{}], "calls": []}
+  ],
+  "output": "",
+  "error": null
+}
+"#
+        );
+        let html = trace.to_html();
+        assert!(html.contains("<pre>(var s &quot;&lt;b&gt;&quot;)</pre>"));
+    }
+}
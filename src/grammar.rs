@@ -0,0 +1,220 @@
+use std::fmt::Write;
+
+/// One production of the grammar the parser accepts.
+#[derive(Debug)]
+pub struct Rule {
+    pub name: &'static str,
+    pub expansion: Expansion,
+}
+
+#[derive(Debug)]
+pub enum Expansion {
+    /// Source text that must appear exactly, such as a keyword.
+    Literal(&'static str),
+    /// A kind of token the scanner produces, such as `IDENTIFIER`.
+    Token(&'static str),
+    /// Another rule, by name.
+    Rule(&'static str),
+    Sequence(&'static [Expansion]),
+    Choice(&'static [Expansion]),
+    Optional(&'static Expansion),
+    /// Zero or more repetitions.
+    Repeat(&'static Expansion),
+}
+
+/// `grammar` in W3C EBNF notation, one rule per line, which railroad
+/// diagram generators accept as input.
+pub fn to_ebnf(grammar: &[Rule]) -> String {
+    let width = grammar.iter().map(|r| r.name.len()).max().unwrap_or(0);
+    let mut ebnf = String::new();
+    for rule in grammar {
+        let _ = writeln!(
+            ebnf,
+            "{:width$} ::= {}",
+            rule.name,
+            ebnf_expansion(&rule.expansion, false)
+        );
+    }
+    ebnf
+}
+
+// `nested` is whether the expansion is an operand of a sequence or a
+// postfix operator, where a choice or sequence needs parentheses.
+fn ebnf_expansion(expansion: &Expansion, nested: bool) -> String {
+    let group = |text: String| match nested {
+        true => format!("( {} )", text),
+        false => text,
+    };
+    match expansion {
+        Expansion::Literal(text) if text.contains('"') => format!("'{}'", text),
+        Expansion::Literal(text) => format!("\"{}\"", text),
+        Expansion::Token(name) | Expansion::Rule(name) => name.to_string(),
+        Expansion::Sequence(items) => {
+            let items: Vec<String> = items.iter().map(|i| ebnf_expansion(i, true)).collect();
+            group(items.join(" "))
+        }
+        Expansion::Choice(items) => {
+            let items: Vec<String> = items.iter().map(|i| ebnf_expansion(i, false)).collect();
+            group(items.join(" | "))
+        }
+        Expansion::Optional(item) => format!("{}?", ebnf_expansion(item, true)),
+        Expansion::Repeat(item) => format!("{}*", ebnf_expansion(item, true)),
+    }
+}
+
+const STYLE: &str = "\
+body { font-family: sans-serif; }
+.rule { margin: 1em 0; }
+.track { display: inline-flex; align-items: center; }
+.choice { display: inline-flex; flex-direction: column; border-left: 2px solid #888; border-right: 2px solid #888; padding: 0 0.5em; }
+.choice > * { margin: 0.2em 0; }
+.repeat { display: inline-flex; align-items: center; border-bottom: 2px solid #888; padding: 0 0.5em 0.3em; }
+.repeat::after { content: \"\\21BA\"; color: #888; margin-left: 0.3em; }
+.skip { border-top: 2px solid #888; min-width: 1.5em; }
+.literal, .token, .ref { border: 1px solid #444; margin: 0 0.3em; padding: 0.1em 0.5em; }
+.literal { border-radius: 1em; background: #eef; }
+.token { border-radius: 1em; background: #efe; }
+.ref { background: #fff; text-decoration: none; color: inherit; }
+";
+
+/// A standalone page drawing each rule of `grammar` as a railroad diagram
+/// made of nested HTML boxes; rule references link to their diagrams.
+pub fn to_railroad_html(grammar: &[Rule]) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Lox grammar</title>\n<style>\n{}</style>\n</head>\n<body>\n",
+        STYLE
+    );
+    for rule in grammar {
+        let _ = writeln!(
+            html,
+            "<div class=\"rule\" id=\"{0}\"><h3>{0}</h3>{1}</div>",
+            rule.name,
+            railroad_expansion(&rule.expansion)
+        );
+    }
+    html += "</body>\n</html>\n";
+    html
+}
+
+fn railroad_expansion(expansion: &Expansion) -> String {
+    match expansion {
+        Expansion::Literal(text) => format!("<span class=\"literal\">{}</span>", escape(text)),
+        Expansion::Token(name) => format!("<span class=\"token\">{}</span>", name),
+        Expansion::Rule(name) => format!("<a class=\"ref\" href=\"#{0}\">{0}</a>", name),
+        Expansion::Sequence(items) => {
+            let items: String = items.iter().map(railroad_expansion).collect();
+            format!("<span class=\"track\">{}</span>", items)
+        }
+        Expansion::Choice(items) => {
+            let items: String = items.iter().map(railroad_expansion).collect();
+            format!("<span class=\"choice\">{}</span>", items)
+        }
+        Expansion::Optional(item) => format!(
+            "<span class=\"choice\"><span class=\"skip\"></span>{}</span>",
+            railroad_expansion(item)
+        ),
+        Expansion::Repeat(item) => format!(
+            "<span class=\"choice\"><span class=\"skip\"></span><span class=\"repeat\">{}</span></span>",
+            railroad_expansion(item)
+        ),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::parser::GRAMMAR;
+    use crate::scanner::KEYWORDS;
+
+    fn walk<'a>(expansion: &'a Expansion, visit: &mut impl FnMut(&'a Expansion)) {
+        visit(expansion);
+        match expansion {
+            Expansion::Sequence(items) | Expansion::Choice(items) => {
+                for item in *items {
+                    walk(item, visit);
+                }
+            }
+            Expansion::Optional(item) | Expansion::Repeat(item) => walk(item, visit),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn the_parser_grammar_is_complete() {
+        let defined: HashSet<&str> = GRAMMAR.iter().map(|r| r.name).collect();
+        let mut referenced = HashSet::new();
+        let mut literals = HashSet::new();
+        for rule in GRAMMAR {
+            walk(&rule.expansion, &mut |e| match e {
+                Expansion::Rule(name) => {
+                    referenced.insert(*name);
+                }
+                Expansion::Literal(text) => {
+                    literals.insert(*text);
+                }
+                _ => {}
+            });
+        }
+
+        assert_eq!(GRAMMAR[0].name, "program");
+        for name in &referenced {
+            assert!(defined.contains(name), "'{}' is used but not defined", name);
+        }
+        for name in &defined {
+            assert!(
+                *name == "program" || referenced.contains(name),
+                "'{}' is defined but never used",
+                name
+            );
+        }
+        for (keyword, _) in KEYWORDS {
+            assert!(
+                literals.contains(keyword),
+                "keyword '{}' is missing from the grammar",
+                keyword
+            );
+        }
+    }
+
+    #[test]
+    fn writes_ebnf() {
+        const SAMPLE: &[Rule] = &[
+            Rule {
+                name: "call",
+                expansion: Expansion::Sequence(&[
+                    Expansion::Rule("primary"),
+                    Expansion::Repeat(&Expansion::Choice(&[
+                        Expansion::Sequence(&[
+                            Expansion::Literal("("),
+                            Expansion::Optional(&Expansion::Rule("arguments")),
+                            Expansion::Literal(")"),
+                        ]),
+                        Expansion::Sequence(&[
+                            Expansion::Literal("."),
+                            Expansion::Token("IDENTIFIER"),
+                        ]),
+                    ])),
+                ]),
+            },
+            Rule {
+                name: "quote",
+                expansion: Expansion::Choice(&[Expansion::Literal("\""), Expansion::Rule("call")]),
+            },
+        ];
+        assert_eq!(
+            to_ebnf(SAMPLE),
+            "call  ::= primary ( \"(\" arguments? \")\" | \".\" IDENTIFIER )*\nquote ::= '\"' | call\n"
+        );
+        let html = to_railroad_html(SAMPLE);
+        assert!(html.contains("<a class=\"ref\" href=\"#arguments\">arguments</a>"));
+        assert!(html.contains("<div class=\"rule\" id=\"quote\">"));
+    }
+}
@@ -0,0 +1,56 @@
+use std::io::{self, Write};
+
+use crate::ast::Stmt;
+use crate::environment::Environment;
+use crate::value::Value;
+
+/// Callbacks the interpreter invokes as it runs a program, for tools such as
+/// coverage, tracing, or debuggers that need to observe execution without
+/// patching the interpreter itself.
+///
+/// Every method has an empty default, so implementors only override the
+/// events they care about.
+pub trait Hooks {
+    /// Called before each statement executes, including statements nested in
+    /// blocks, loop bodies, and function bodies.
+    fn before_statement(&mut self, _stmt: &Stmt) {}
+
+    /// Called right after `before_statement` with the scope the statement
+    /// is about to run in; `Environment::enclosing` leads out to the globals.
+    fn inspect_scope(&mut self, _stmt: &Stmt, _scope: &Environment) {}
+
+    /// Called after a statement completes, whether it finished normally or
+    /// by `return`. Not called if the statement fails with a runtime error.
+    fn after_statement(&mut self, _stmt: &Stmt) {}
+
+    /// Called after arguments are evaluated and arity is checked, right
+    /// before a function's body runs.
+    fn on_call(&mut self, _function: &Value, _arguments: &[Value]) {}
+
+    /// Called when a function returns normally. Not called if the call fails
+    /// with a runtime error.
+    fn on_return(&mut self, _function: &Value, _value: &Value) {}
+}
+
+/// Prints every statement and call to stderr as it runs.
+pub struct Tracer;
+
+impl Hooks for Tracer {
+    fn before_statement(&mut self, stmt: &Stmt) {
+        let _ = writeln!(io::stderr(), "[trace] {}", stmt);
+    }
+
+    fn on_call(&mut self, function: &Value, arguments: &[Value]) {
+        let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+        let _ = writeln!(
+            io::stderr(),
+            "[trace] call {}({})",
+            function,
+            arguments.join(", ")
+        );
+    }
+
+    fn on_return(&mut self, function: &Value, value: &Value) {
+        let _ = writeln!(io::stderr(), "[trace] return {} -> {}", function, value);
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// A string stored in an `Interner`. Symbols from the same interner are
+/// equal exactly when their strings are.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// The symbol's position in the order strings were first interned.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Stores each distinct string once and hands out a `Symbol` for it, so
+/// that names can be compared and hashed as integers.
+#[derive(Debug, Default)]
+pub struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    strings: Vec<Rc<str>>,
+}
+
+impl Interner {
+    pub fn intern(&mut self, s: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(s) {
+            return *symbol;
+        }
+        let symbol = Symbol(self.strings.len() as u32);
+        let s: Rc<str> = Rc::from(s);
+        self.strings.push(Rc::clone(&s));
+        self.symbols.insert(s, symbol);
+        symbol
+    }
+
+    /// The symbol for `s` if it has been interned, without interning it.
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        self.symbols.get(s).copied()
+    }
+
+    /// The string `symbol` stands for. Panics if `symbol` came from another
+    /// interner.
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.strings[symbol.index()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interns_each_string_once() {
+        let mut interner = Interner::default();
+        let a = interner.intern("count");
+        let b = interner.intern("total");
+        assert_eq!(interner.intern("count"), a);
+        assert_ne!(a, b);
+        assert_eq!(interner.resolve(b), "total");
+        assert_eq!(interner.get("total"), Some(b));
+        assert_eq!(interner.get("missing"), None);
+        assert_eq!(interner.strings.len(), 2);
+    }
+}
@@ -0,0 +1,2506 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::rc::Rc;
+
+use anyhow::{Context, Result, bail};
+
+use crate::ast::{
+    BinaryOperator, ClassDecl, Expr, ExprVisitor, Fixity, FunctionDecl, Identifier, Literal,
+    Location, LogicalOperator, Program, Segment, Stmt, StmtVisitor, UnaryOperator, UpdateOperator,
+};
+use crate::environment::Environment;
+use crate::hooks::Hooks;
+use crate::runtime_error::{Frame, RuntimeError};
+use crate::stdlib::{self, Host};
+use crate::token::Span;
+use crate::value::{
+    Callable, LoxClass, LoxFunction, LoxInstance, LoxMap, MapKey, NativeFunction, Value,
+};
+
+/// How control leaves a statement. Anything other than `Next` unwinds
+/// through enclosing statements until something handles it.
+#[derive(Debug)]
+pub(crate) enum Flow {
+    Next,
+    Return(Value),
+    /// Handled by the innermost loop, which stops.
+    Break,
+    /// Handled by the innermost loop, which goes on to its increment.
+    Continue,
+}
+
+/// A global binding as seen from the host.
+#[derive(Debug, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub value: Value,
+    /// Where the script declared it, or `None` if the host defined it.
+    pub defined_at: Option<Location>,
+}
+
+impl Global {
+    pub fn kind(&self) -> &'static str {
+        self.value.type_name()
+    }
+}
+
+/// Where `print` writes to, shared with the `flush` native.
+pub(crate) type Output = Rc<RefCell<BufWriter<Box<dyn Write>>>>;
+
+/// Caps on how much `print` writes, which keep a script that prints in a
+/// loop by mistake from filling a host's log. A cap that is `None` does
+/// not apply.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct OutputLimit {
+    /// Bytes across every `print`, newlines included.
+    pub total_bytes: Option<usize>,
+    /// Bytes in what one `print` writes, not counting its newline.
+    pub line_bytes: Option<usize>,
+    pub on_exceed: OnExceed,
+}
+
+/// How deep calls may nest before either backend gives up with a stack
+/// overflow. The script itself counts as the outermost call.
+pub(crate) const MAX_FRAMES: usize = 256;
+
+/// What `print` does when it would go over an `OutputLimit`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[non_exhaustive]
+pub enum OnExceed {
+    /// Cuts a long line short, with a note of how much was left out. Once
+    /// the total is reached, a notice is written in place of the `print`
+    /// that would go over, later ones write nothing, and the script runs
+    /// on.
+    #[default]
+    Truncate,
+    /// Fails the `print` with a runtime error, before it writes anything.
+    Error,
+}
+
+pub struct Interpreter {
+    out: Output,
+    // Whether `print` may leave output in the buffer until the next flush.
+    buffered: bool,
+    globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    hooks: Option<Box<dyn Hooks>>,
+    output_limit: OutputLimit,
+    // Bytes `print` has written, and whether it has stopped writing because
+    // that reached the limit.
+    written: usize,
+    output_cut: bool,
+    // Calls to functions and classes in progress. Natives are left out, as
+    // the VM gives them no frame.
+    calls: usize,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::with_output(io::stdout())
+    }
+
+    /// An interpreter whose `print` writes to `out`. Output is buffered and
+    /// written through whenever `interpret` returns, the script calls
+    /// `flush()`, or the host calls `flush`.
+    pub fn with_output(out: impl Write + 'static) -> Self {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        let out: Box<dyn Write> = Box::new(out);
+        let mut interpreter = Self {
+            out: Rc::new(RefCell::new(BufWriter::new(out))),
+            buffered: true,
+            environment: Rc::clone(&globals),
+            globals,
+            hooks: None,
+            output_limit: OutputLimit::default(),
+            written: 0,
+            output_cut: false,
+            calls: 0,
+        };
+        stdlib::register(&mut interpreter);
+        interpreter
+    }
+
+    /// Defines a global function `name` implemented by `function`, which is
+    /// only ever called with exactly `arity` arguments.
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value> + 'static,
+    ) {
+        let native = NativeFunction::new(name, arity, function);
+        self.set_global(name, Value::Native(Rc::new(native)));
+    }
+
+    /// Every global binding, sorted by name.
+    pub fn globals(&self) -> Vec<Global> {
+        let mut globals: Vec<Global> = self
+            .globals
+            .borrow()
+            .bindings()
+            .map(|(name, value, defined_at)| Global {
+                name: name.to_string(),
+                value: value.clone(),
+                defined_at,
+            })
+            .collect();
+        globals.sort_by(|a, b| a.name.cmp(&b.name));
+        globals
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals.borrow().get_local(name)
+    }
+
+    /// Defines or replaces the global `name`, as if the script had declared
+    /// it before running.
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.globals.borrow_mut().define(name, value);
+    }
+
+    /// Turns buffering of `print` output on or off. When it is off, every
+    /// `print` is written through before the next statement runs.
+    pub fn set_buffered(&mut self, buffered: bool) -> Result<()> {
+        self.buffered = buffered;
+        self.flush()
+    }
+
+    /// Writes through any buffered `print` output.
+    pub fn flush(&mut self) -> Result<()> {
+        self.out
+            .borrow_mut()
+            .flush()
+            .context("interpreter: could not write output")
+    }
+
+    /// Caps what `print` writes from now on. Bytes written before count
+    /// towards the total.
+    pub fn set_output_limit(&mut self, limit: OutputLimit) {
+        self.output_limit = limit;
+    }
+
+    /// Installs `hooks` to observe execution, replacing any previous hooks.
+    pub fn set_hooks(&mut self, hooks: impl Hooks + 'static) {
+        self.hooks = Some(Box::new(hooks));
+    }
+
+    /// Runs `program`, then flushes its output. Any error it fails with is
+    /// a `RuntimeError`.
+    pub fn interpret(&mut self, program: &Program) -> Result<()> {
+        let result = program
+            .iter()
+            .try_for_each(|stmt| self.execute(stmt).map(drop));
+        // Flush even on failure so the output leading up to it is seen.
+        let flushed = self.flush();
+        result.map_err(RuntimeError::from_error)?;
+        flushed
+    }
+
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Value> {
+        expr.accept(self)
+    }
+
+    // Evaluates `expr` in `environment`, restoring the current environment
+    // afterwards even if it fails.
+    pub(crate) fn evaluate_in(
+        &mut self,
+        expr: &Expr,
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<Value> {
+        let previous = mem::replace(&mut self.environment, environment);
+        let result = self.evaluate(expr);
+        self.environment = previous;
+        result
+    }
+
+    // Evaluates the object and index of `target`, a variable, property or
+    // element, so that it can be read and then written without running them
+    // again.
+    fn place<'a>(&mut self, target: &'a Expr) -> Result<Place<'a>> {
+        match target {
+            Expr::Variable(name) => Ok(Place::Variable(name)),
+            Expr::Get(object, name, span) => {
+                let object = self.evaluate(object)?;
+                let instance = instance(object, "properties", name, *span)?;
+                Ok(Place::Property(instance, name, *span))
+            }
+            Expr::Index(object, index, location, span) => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                let slot = slot(&object, &index, *location, *span)?;
+                Ok(Place::Element(slot, *location, *span))
+            }
+            _ => unreachable!(
+                "the parser only builds places from variables, properties and elements"
+            ),
+        }
+    }
+
+    fn read(&self, place: &Place) -> Result<Value> {
+        match place {
+            Place::Variable(name) => self.environment.borrow().get(name),
+            Place::Property(instance, name, span) => LoxInstance::get(instance, name)
+                .map_err(|error| RuntimeError::from_error(error).with_span(*span).into()),
+            Place::Element(slot, location, span) => read_slot(slot, *location, *span),
+        }
+    }
+
+    fn write(&mut self, place: &Place, value: Value) -> Result<()> {
+        match place {
+            Place::Variable(name) => self.environment.borrow_mut().assign(name, value),
+            Place::Property(instance, name, _) => {
+                instance.borrow_mut().set(name, value);
+                Ok(())
+            }
+            Place::Element(slot, location, span) => write_slot(slot, value, *location, *span),
+        }
+    }
+
+    // Runs `statements` in `environment`, restoring the current environment
+    // afterwards even if one of them fails.
+    pub(crate) fn execute_block(
+        &mut self,
+        statements: &[Stmt],
+        environment: Environment,
+    ) -> Result<Flow> {
+        let previous = mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+        let result = self.execute_all(statements);
+        self.environment = previous;
+        result
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> Result<Flow> {
+        if let Some(hooks) = &mut self.hooks {
+            hooks.before_statement(stmt);
+            hooks.inspect_scope(stmt, &self.environment.borrow());
+        }
+        let flow = stmt.accept(self)?;
+        if let Some(hooks) = &mut self.hooks {
+            hooks.after_statement(stmt);
+        }
+        Ok(flow)
+    }
+
+    fn execute_all(&mut self, statements: &[Stmt]) -> Result<Flow> {
+        for stmt in statements {
+            match self.execute(stmt)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    // Applies the output limit to `line`, which `print` is about to write.
+    // Returns what to write in its place, if anything.
+    fn limit_output(&mut self, mut line: String) -> Result<Option<String>> {
+        let OutputLimit {
+            total_bytes,
+            line_bytes,
+            on_exceed,
+        } = self.output_limit;
+        if self.output_cut {
+            return Ok(None);
+        }
+        if let Some(max) = line_bytes
+            && line.len() > max
+        {
+            if on_exceed == OnExceed::Error {
+                bail!(
+                    "interpreter: a print of {} bytes is over the line limit of {} bytes",
+                    line.len(),
+                    max
+                );
+            }
+            let cut = (0..=max)
+                .rev()
+                .find(|&i| line.is_char_boundary(i))
+                .unwrap_or(0);
+            let left_out = line.len() - cut;
+            line.truncate(cut);
+            line += &format!("... ({} more bytes)", left_out);
+        }
+        if let Some(max) = total_bytes
+            && self.written + line.len() + 1 > max
+        {
+            if on_exceed == OnExceed::Error {
+                bail!("interpreter: output is over the limit of {} bytes", max);
+            }
+            self.output_cut = true;
+            return Ok(Some(format!(
+                "[output stopped at the limit of {} bytes]",
+                max
+            )));
+        }
+        self.written += line.len() + 1;
+        Ok(Some(line))
+    }
+}
+
+impl Host for Interpreter {
+    fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value> + 'static,
+    ) {
+        Interpreter::define_native(self, name, arity, function);
+    }
+
+    fn output(&self) -> Output {
+        Rc::clone(&self.out)
+    }
+}
+
+impl StmtVisitor<Result<Flow>> for Interpreter {
+    fn visit_expression(&mut self, expr: &Expr) -> Result<Flow> {
+        self.evaluate(expr)?;
+        Ok(Flow::Next)
+    }
+
+    fn visit_print(&mut self, expr: &Expr) -> Result<Flow> {
+        let value = self.evaluate(expr)?;
+        let Some(line) = self.limit_output(value.to_string())? else {
+            return Ok(Flow::Next);
+        };
+        let mut out = self.out.borrow_mut();
+        writeln!(out, "{}", line)?;
+        if !self.buffered {
+            out.flush()?;
+        }
+        Ok(Flow::Next)
+    }
+
+    fn visit_var(&mut self, name: &Identifier, initializer: Option<&Expr>) -> Result<Flow> {
+        let value = match initializer {
+            Some(initializer) => self.evaluate(initializer)?,
+            None => Value::Nil,
+        };
+        self.environment.borrow_mut().define_at(name, value);
+        Ok(Flow::Next)
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) -> Result<Flow> {
+        let environment = Environment::with_enclosing(Rc::clone(&self.environment));
+        self.execute_block(statements, environment)
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Stmt,
+        else_branch: Option<&Stmt>,
+    ) -> Result<Flow> {
+        if self.evaluate(condition)?.is_truthy() {
+            self.execute(then_branch)
+        } else if let Some(else_branch) = else_branch {
+            self.execute(else_branch)
+        } else {
+            Ok(Flow::Next)
+        }
+    }
+
+    fn visit_while(
+        &mut self,
+        condition: &Expr,
+        body: &Stmt,
+        increment: Option<&Expr>,
+    ) -> Result<Flow> {
+        while self.evaluate(condition)?.is_truthy() {
+            match self.execute(body)? {
+                Flow::Next | Flow::Continue => {}
+                Flow::Break => break,
+                flow => return Ok(flow),
+            }
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn visit_break(&mut self) -> Result<Flow> {
+        Ok(Flow::Break)
+    }
+
+    fn visit_continue(&mut self) -> Result<Flow> {
+        Ok(Flow::Continue)
+    }
+
+    fn visit_function(&mut self, declaration: &Rc<FunctionDecl>) -> Result<Flow> {
+        let function =
+            LoxFunction::new(Rc::clone(declaration), Rc::clone(&self.environment), false);
+        self.environment
+            .borrow_mut()
+            .define_at(&declaration.name, Value::Function(Rc::new(function)));
+        Ok(Flow::Next)
+    }
+
+    fn visit_return(&mut self, value: Option<&Expr>) -> Result<Flow> {
+        let value = match value {
+            Some(value) => self.evaluate(value)?,
+            None => Value::Nil,
+        };
+        Ok(Flow::Return(value))
+    }
+
+    fn visit_class(&mut self, declaration: &Rc<ClassDecl>) -> Result<Flow> {
+        let superclass = match &declaration.superclass {
+            Some(name) => match self.environment.borrow().get(name)? {
+                Value::Class(superclass) => Some(superclass),
+                value => bail!(RuntimeError::new(
+                    format!("superclass must be a class, found {}", value.type_name()),
+                    Some(name.position())
+                )),
+            },
+            None => None,
+        };
+
+        // Methods of a subclass close over a scope binding `super`, so it
+        // keeps naming this class's superclass even in inherited methods.
+        let closure = match &superclass {
+            Some(superclass) => {
+                let mut environment = Environment::with_enclosing(Rc::clone(&self.environment));
+                environment.define("super", Value::Class(Rc::clone(superclass)));
+                Rc::new(RefCell::new(environment))
+            }
+            None => Rc::clone(&self.environment),
+        };
+        let methods: HashMap<String, Rc<LoxFunction>> = declaration
+            .methods
+            .iter()
+            .map(|method| {
+                let is_initializer = method.name.name == "init";
+                let function =
+                    LoxFunction::new(Rc::clone(method), Rc::clone(&closure), is_initializer);
+                (method.name.name.clone(), Rc::new(function))
+            })
+            .collect();
+        let class = LoxClass::new(
+            declaration.name.name.clone(),
+            superclass,
+            methods,
+            declaration.fields.clone(),
+            closure,
+        );
+        self.environment
+            .borrow_mut()
+            .define_at(&declaration.name, Value::Class(Rc::new(class)));
+        Ok(Flow::Next)
+    }
+}
+
+// A variable, property or element with its object and index evaluated.
+enum Place<'a> {
+    Variable(&'a Identifier),
+    Property(Rc<RefCell<LoxInstance>>, &'a Identifier, Span),
+    Element(Slot, Location, Span),
+}
+
+// The instance `value` is, for a property access that needs one to have
+// `what`, "properties" or "fields".
+fn instance(
+    value: Value,
+    what: &str,
+    name: &Identifier,
+    span: Span,
+) -> Result<Rc<RefCell<LoxInstance>>> {
+    match value {
+        Value::Instance(instance) => Ok(instance),
+        value => bail!(
+            RuntimeError::new(
+                format!("only instances have {}, found {}", what, value.type_name()),
+                Some(name.position())
+            )
+            .with_span(span)
+        ),
+    }
+}
+
+// `result`, with any error it holds placed at the operator at `location`,
+// which covers `span`.
+fn at_operator(result: Result<Value>, location: Location, span: Span) -> Result<Value> {
+    result.map_err(|error| {
+        let error = RuntimeError::from_error(error);
+        RuntimeError {
+            location: Some(location),
+            ..error
+        }
+        .with_span(span)
+        .into()
+    })
+}
+
+// What `object[index]` refers to.
+enum Slot {
+    List(Rc<RefCell<Vec<Value>>>, usize),
+    /// The key may not be in the map yet.
+    Map(Rc<RefCell<LoxMap>>, MapKey),
+    /// Strings can't be changed, so this is only ever read.
+    Character(char),
+}
+
+// The number of elements in a list or characters in a string, or `None`
+// for other values. Strings are indexed and sliced by character, the way
+// `len` and `substr` count them, so positions never fall inside one.
+fn sequence_length(value: &Value) -> Option<usize> {
+    match value {
+        Value::List(list) => Some(list.borrow().len()),
+        Value::String(s) => Some(s.chars().count()),
+        _ => None,
+    }
+}
+
+// The slot `index` names in `object`, for an index expression at
+// `location`.
+fn slot(object: &Value, index: &Value, location: Location, span: Span) -> Result<Slot> {
+    let error = |message: String| RuntimeError::new(message, Some(location)).with_span(span);
+    let length = match object {
+        Value::Map(map) => {
+            return match MapKey::from_value(index) {
+                Some(key) => Ok(Slot::Map(Rc::clone(map), key)),
+                None => bail!(error(format!(
+                    "map key must be a number or string, found {}",
+                    index.type_name()
+                ))),
+            };
+        }
+        _ => sequence_length(object).ok_or_else(|| {
+            error(format!(
+                "can only index lists, maps and strings, found {}",
+                object.type_name()
+            ))
+        })?,
+    };
+    let Value::Number(n) = index else {
+        bail!(error(format!(
+            "{} index must be a number, found {}",
+            object.type_name(),
+            index.type_name()
+        )));
+    };
+    let Some(position) = usize::try_from(*n).ok().filter(|&slot| slot < length) else {
+        bail!(error(format!(
+            "index {} is out of bounds for a {} of length {}",
+            n,
+            object.type_name(),
+            length
+        )));
+    };
+    match object {
+        Value::List(list) => Ok(Slot::List(Rc::clone(list), position)),
+        Value::String(s) => Ok(Slot::Character(s.chars().nth(position).unwrap())),
+        _ => unreachable!("only lists and strings have a length"),
+    }
+}
+
+fn read_slot(slot: &Slot, location: Location, span: Span) -> Result<Value> {
+    match slot {
+        Slot::List(list, slot) => Ok(list.borrow()[*slot].clone()),
+        Slot::Character(c) => Ok(Value::String(c.to_string())),
+        Slot::Map(map, key) => match map.borrow().get(key) {
+            Some(value) => Ok(value.clone()),
+            None => bail!(
+                RuntimeError::new(format!("map has no key {}", key), Some(location))
+                    .with_span(span)
+            ),
+        },
+    }
+}
+
+fn write_slot(slot: &Slot, value: Value, location: Location, span: Span) -> Result<()> {
+    match slot {
+        Slot::List(list, slot) => {
+            let mut list = list.borrow_mut();
+            let length = list.len();
+            // The value may have come from code that shrank the list after
+            // the slot was found in it.
+            match list.get_mut(*slot) {
+                Some(element) => *element = value,
+                None => bail!(
+                    RuntimeError::new(
+                        format!(
+                            "index {} is out of bounds for a list of length {}",
+                            slot, length
+                        ),
+                        Some(location)
+                    )
+                    .with_span(span)
+                ),
+            }
+        }
+        Slot::Map(map, key) => {
+            map.borrow_mut().insert(key.clone(), value);
+        }
+        Slot::Character(_) => bail!(
+            RuntimeError::new(
+                "strings can't be changed, so can't assign to an index of one".to_string(),
+                Some(location)
+            )
+            .with_span(span)
+        ),
+    }
+    Ok(())
+}
+
+impl ExprVisitor<Result<Value>> for Interpreter {
+    fn visit_binary(
+        &mut self,
+        left: &Expr,
+        op: BinaryOperator,
+        right: &Expr,
+        location: Location,
+        span: Span,
+    ) -> Result<Value> {
+        let left = self.evaluate(left)?;
+        let right = self.evaluate(right)?;
+
+        at_operator(binary(op, left, right), location, span)
+    }
+
+    // `and` and `or` short-circuit and yield whichever operand decided the
+    // result, not a bool.
+    fn visit_logical(&mut self, left: &Expr, op: LogicalOperator, right: &Expr) -> Result<Value> {
+        let left = self.evaluate(left)?;
+        let short_circuits = match op {
+            LogicalOperator::And => !left.is_truthy(),
+            LogicalOperator::Or => left.is_truthy(),
+        };
+        if short_circuits {
+            return Ok(left);
+        }
+        self.evaluate(right)
+    }
+
+    fn visit_comma(&mut self, left: &Expr, right: &Expr) -> Result<Value> {
+        self.evaluate(left)?;
+        self.evaluate(right)
+    }
+
+    fn visit_conditional(
+        &mut self,
+        condition: &Expr,
+        then_branch: &Expr,
+        else_branch: &Expr,
+    ) -> Result<Value> {
+        if self.evaluate(condition)?.is_truthy() {
+            self.evaluate(then_branch)
+        } else {
+            self.evaluate(else_branch)
+        }
+    }
+
+    fn visit_unary(
+        &mut self,
+        op: UnaryOperator,
+        right: &Expr,
+        location: Location,
+        span: Span,
+    ) -> Result<Value> {
+        let right = self.evaluate(right)?;
+        at_operator(unary(op, right), location, span)
+    }
+
+    fn visit_grouping(&mut self, expr: &Expr) -> Result<Value> {
+        self.evaluate(expr)
+    }
+
+    fn visit_literal(&mut self, literal: &Literal) -> Result<Value> {
+        let value = match literal {
+            Literal::Number(n) => Value::Number(*n),
+            Literal::String(s) => Value::String(s.clone()),
+            Literal::Bool(b) => Value::Bool(*b),
+            Literal::Nil => Value::Nil,
+        };
+        Ok(value)
+    }
+
+    fn visit_interpolation(&mut self, segments: &[Segment]) -> Result<Value> {
+        let mut text = String::new();
+        for segment in segments {
+            match segment {
+                Segment::Text(s) => text.push_str(s),
+                Segment::Value(expr) => text += &self.evaluate(expr)?.to_string(),
+            }
+        }
+        Ok(Value::String(text))
+    }
+
+    fn visit_variable(&mut self, name: &Identifier) -> Result<Value> {
+        self.environment.borrow().get(name)
+    }
+
+    fn visit_assign(&mut self, name: &Identifier, value: &Expr) -> Result<Value> {
+        let value = self.evaluate(value)?;
+        self.environment.borrow_mut().assign(name, value.clone())?;
+        Ok(value)
+    }
+
+    fn visit_compound_assign(
+        &mut self,
+        target: &Expr,
+        op: BinaryOperator,
+        value: &Expr,
+        location: Location,
+        span: Span,
+    ) -> Result<Value> {
+        let place = self.place(target)?;
+        let current = self.read(&place)?;
+        let value = self.evaluate(value)?;
+        let value = at_operator(binary(op, current, value), location, span)?;
+        self.write(&place, value.clone())?;
+        Ok(value)
+    }
+
+    fn visit_update(
+        &mut self,
+        target: &Expr,
+        op: UpdateOperator,
+        fixity: Fixity,
+        location: Location,
+        span: Span,
+    ) -> Result<Value> {
+        let place = self.place(target)?;
+        let old = self.read(&place)?;
+        let new = at_operator(update(op, old.clone()), location, span)?;
+        self.write(&place, new.clone())?;
+        match fixity {
+            Fixity::Prefix => Ok(new),
+            Fixity::Postfix => Ok(old),
+        }
+    }
+
+    fn visit_call(
+        &mut self,
+        callee: &Expr,
+        arguments: &[Expr],
+        location: Location,
+        span: Span,
+    ) -> Result<Value> {
+        let callee = self.evaluate(callee)?;
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.evaluate(argument))
+            .collect::<Result<Vec<_>>>()?;
+
+        let function: &dyn Callable = match &callee {
+            Value::Function(function) => function.as_ref(),
+            Value::Native(native) => native.as_ref(),
+            Value::Class(class) => class,
+            value => bail!(
+                RuntimeError::new(
+                    format!(
+                        "can only call functions and classes, found {}",
+                        value.type_name()
+                    ),
+                    Some(location)
+                )
+                .with_span(span)
+            ),
+        };
+        if arguments.len() != function.arity() {
+            bail!(
+                RuntimeError::new(
+                    format!(
+                        "{} expected {} arguments but got {}",
+                        callee,
+                        function.arity(),
+                        arguments.len()
+                    ),
+                    Some(location)
+                )
+                .with_span(span)
+            );
+        }
+
+        let native = matches!(callee, Value::Native(_));
+        if !native && self.calls + 1 == MAX_FRAMES {
+            bail!(RuntimeError::new("stack overflow".to_string(), Some(location)).with_span(span));
+        }
+
+        if let Some(hooks) = &mut self.hooks {
+            hooks.on_call(&callee, &arguments);
+        }
+        let calls = self.calls;
+        if !native {
+            self.calls += 1;
+        }
+        let result = function.call(self, arguments);
+        self.calls = calls;
+        let value = result.map_err(|error| {
+            let mut error = RuntimeError::from_error(error);
+            error.trace.push(Frame {
+                function: callee.to_string(),
+                location,
+            });
+            error
+        })?;
+        if let Some(hooks) = &mut self.hooks {
+            hooks.on_return(&callee, &value);
+        }
+        Ok(value)
+    }
+
+    fn visit_get(&mut self, object: &Expr, name: &Identifier, span: Span) -> Result<Value> {
+        let object = self.evaluate(object)?;
+        let instance = instance(object, "properties", name, span)?;
+        LoxInstance::get(&instance, name)
+            .map_err(|error| RuntimeError::from_error(error).with_span(span).into())
+    }
+
+    fn visit_set(
+        &mut self,
+        object: &Expr,
+        name: &Identifier,
+        value: &Expr,
+        span: Span,
+    ) -> Result<Value> {
+        let object = self.evaluate(object)?;
+        let instance = instance(object, "fields", name, span)?;
+        let value = self.evaluate(value)?;
+        instance.borrow_mut().set(name, value.clone());
+        Ok(value)
+    }
+
+    fn visit_list(&mut self, elements: &[Expr], _location: Location) -> Result<Value> {
+        let elements = elements
+            .iter()
+            .map(|element| self.evaluate(element))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Value::List(Rc::new(RefCell::new(elements))))
+    }
+
+    fn visit_map(&mut self, entries: &[(Expr, Expr)], location: Location) -> Result<Value> {
+        let mut map = LoxMap::default();
+        for (key, value) in entries {
+            let key = self.evaluate(key)?;
+            let Some(key) = MapKey::from_value(&key) else {
+                bail!(RuntimeError::new(
+                    format!(
+                        "map key must be a number or string, found {}",
+                        key.type_name()
+                    ),
+                    Some(location)
+                ));
+            };
+            map.insert(key, self.evaluate(value)?);
+        }
+        Ok(Value::Map(Rc::new(RefCell::new(map))))
+    }
+
+    fn visit_index(
+        &mut self,
+        object: &Expr,
+        index: &Expr,
+        location: Location,
+        span: Span,
+    ) -> Result<Value> {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        read_slot(&slot(&object, &index, location, span)?, location, span)
+    }
+
+    fn visit_slice(
+        &mut self,
+        object: &Expr,
+        start: Option<&Expr>,
+        end: Option<&Expr>,
+        location: Location,
+        span: Span,
+    ) -> Result<Value> {
+        let object = self.evaluate(object)?;
+        let start = start.map(|start| self.evaluate(start)).transpose()?;
+        let end = end.map(|end| self.evaluate(end)).transpose()?;
+        let error = |message: String| RuntimeError::new(message, Some(location)).with_span(span);
+        let Some(length) = sequence_length(&object) else {
+            bail!(error(format!(
+                "can only slice lists and strings, found {}",
+                object.type_name()
+            )));
+        };
+        // Bounds are compared as i64 so that neither a negative bound nor a
+        // length past i32::MAX needs special handling.
+        let bound = |value: Option<Value>, default: i64| match value {
+            None => Ok(default),
+            Some(Value::Number(n)) => Ok(i64::from(n)),
+            Some(value) => bail!(error(format!(
+                "slice bounds must be numbers, found {}",
+                value.type_name()
+            ))),
+        };
+        let start = bound(start, 0)?;
+        let end = bound(end, length as i64)?;
+        if start < 0 || end < start || end > length as i64 {
+            bail!(error(format!(
+                "slice {}:{} is out of bounds for a {} of length {}",
+                start,
+                end,
+                object.type_name(),
+                length
+            )));
+        }
+        let (start, end) = (start as usize, end as usize);
+        match object {
+            Value::List(list) => Ok(Value::List(Rc::new(RefCell::new(
+                list.borrow()[start..end].to_vec(),
+            )))),
+            Value::String(s) => Ok(Value::String(
+                s.chars().skip(start).take(end - start).collect(),
+            )),
+            _ => unreachable!("only lists and strings have a length"),
+        }
+    }
+
+    fn visit_set_index(
+        &mut self,
+        object: &Expr,
+        index: &Expr,
+        value: &Expr,
+        location: Location,
+        span: Span,
+    ) -> Result<Value> {
+        let object = self.evaluate(object)?;
+        let index = self.evaluate(index)?;
+        let value = self.evaluate(value)?;
+        let slot = slot(&object, &index, location, span)?;
+        write_slot(&slot, value.clone(), location, span)?;
+        Ok(value)
+    }
+
+    fn visit_this(&mut self, location: Location) -> Result<Value> {
+        self.environment.borrow().get(&Identifier {
+            name: "this".to_string(),
+            line: location.line,
+            column: location.column,
+        })
+    }
+
+    fn visit_super(&mut self, location: Location, method: &Identifier) -> Result<Value> {
+        let keyword = |name: &str| Identifier {
+            name: name.to_string(),
+            line: location.line,
+            column: location.column,
+        };
+        let superclass = match self.environment.borrow().get(&keyword("super"))? {
+            Value::Class(superclass) => superclass,
+            value => unreachable!("'super' is always bound to a class, found {}", value),
+        };
+        let instance = self.environment.borrow().get(&keyword("this"))?;
+
+        match superclass.find_method(&method.name) {
+            Some(found) => Ok(Value::Function(Rc::new(found.bind(instance)))),
+            None => bail!(RuntimeError::new(
+                format!(
+                    "undefined property '{}' on superclass {:?}",
+                    method.name, superclass
+                ),
+                Some(method.position())
+            )),
+        }
+    }
+}
+
+// Shared by both backends so that they agree on what every operator does.
+pub(crate) fn binary(op: BinaryOperator, left: Value, right: Value) -> Result<Value> {
+    let value = match (op, left, right) {
+        (BinaryOperator::Equal, l, r) => Value::Bool(l == r),
+        (BinaryOperator::NotEqual, l, r) => Value::Bool(l != r),
+        (BinaryOperator::Add, Value::String(l), Value::String(r)) => Value::String(l + &r),
+        (op, Value::Number(l), Value::Number(r)) => arithmetic(op, l, r)?,
+        (BinaryOperator::Add, l, r) => bail!(RuntimeError::new(
+            format!(
+                "operands of '+' must be two numbers or two strings, found {} and {}",
+                l.type_name(),
+                r.type_name()
+            ),
+            None
+        )),
+        (op, l, r) => bail!(RuntimeError::new(
+            format!(
+                "operands of '{}' must be numbers, found {} and {}",
+                op,
+                l.type_name(),
+                r.type_name()
+            ),
+            None
+        )),
+    };
+    Ok(value)
+}
+
+pub(crate) fn unary(op: UnaryOperator, right: Value) -> Result<Value> {
+    match (op, right) {
+        (UnaryOperator::Not, value) => Ok(Value::Bool(!value.is_truthy())),
+        (UnaryOperator::Negate, Value::Number(n)) => match n.checked_neg() {
+            Some(n) => Ok(Value::Number(n)),
+            None => bail!(RuntimeError::new(
+                format!("integer overflow in '-{}'", n),
+                None
+            )),
+        },
+        (UnaryOperator::BitNot, Value::Number(n)) => Ok(Value::Number(!n)),
+        (op, value) => bail!(RuntimeError::new(
+            format!(
+                "operand of '{}' must be a number, found {}",
+                op,
+                value.type_name()
+            ),
+            None
+        )),
+    }
+}
+
+// The value `++` or `--` changes `value` to.
+pub(crate) fn update(op: UpdateOperator, value: Value) -> Result<Value> {
+    let Value::Number(n) = value else {
+        bail!(RuntimeError::new(
+            format!(
+                "operand of '{}' must be a number, found {}",
+                op,
+                value.type_name()
+            ),
+            None
+        ));
+    };
+    match op {
+        UpdateOperator::Increment => arithmetic(BinaryOperator::Add, n, 1),
+        UpdateOperator::Decrement => arithmetic(BinaryOperator::Subtract, n, 1),
+    }
+}
+
+fn arithmetic(op: BinaryOperator, l: i32, r: i32) -> Result<Value> {
+    let result = match op {
+        BinaryOperator::Less => return Ok(Value::Bool(l < r)),
+        BinaryOperator::LessEqual => return Ok(Value::Bool(l <= r)),
+        BinaryOperator::Greater => return Ok(Value::Bool(l > r)),
+        BinaryOperator::GreaterEqual => return Ok(Value::Bool(l >= r)),
+        BinaryOperator::Add => l.checked_add(r),
+        BinaryOperator::Subtract => l.checked_sub(r),
+        BinaryOperator::Multiply => l.checked_mul(r),
+        BinaryOperator::Divide if r == 0 => {
+            bail!(RuntimeError::new("division by zero".to_string(), None))
+        }
+        BinaryOperator::Divide => l.checked_div(r),
+        // The remainder of the division above, which rounds towards zero, so
+        // it takes the sign of `l` and `l == l / r * r + l % r` always.
+        BinaryOperator::Modulo if r == 0 => {
+            bail!(RuntimeError::new("modulo by zero".to_string(), None))
+        }
+        BinaryOperator::Modulo => l.checked_rem(r),
+        BinaryOperator::Power => match u32::try_from(r) {
+            Ok(r) => l.checked_pow(r),
+            Err(_) => bail!(RuntimeError::new(
+                format!("negative exponent in '{} ** {}'", l, r),
+                None
+            )),
+        },
+        // Numbers are already 32-bit two's complement integers, so these
+        // work on their bits directly. `>>` copies the sign bit in, and
+        // bits `<<` moves past the top are dropped rather than overflowing.
+        BinaryOperator::BitAnd => Some(l & r),
+        BinaryOperator::BitOr => Some(l | r),
+        BinaryOperator::BitXor => Some(l ^ r),
+        BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight if !(0..32).contains(&r) => {
+            bail!(RuntimeError::new(
+                format!(
+                    "shift amount out of range in '{} {} {}', it must be from 0 to 31",
+                    l, op, r
+                ),
+                None
+            ))
+        }
+        BinaryOperator::ShiftLeft => Some(l << r),
+        BinaryOperator::ShiftRight => Some(l >> r),
+        BinaryOperator::Equal | BinaryOperator::NotEqual => unreachable!("handled by caller"),
+    };
+    match result {
+        Some(n) => Ok(Value::Number(n)),
+        None => bail!(RuntimeError::new(
+            format!("integer overflow in '{} {} {}'", l, op, r),
+            None
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::parser::parse;
+    use crate::scanner::scan;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct TestCase {
+        name: &'static str,
+        input: &'static str,
+        assertion: Box<dyn Matcher>,
+    }
+
+    trait Matcher {
+        fn check(&self, result: &Result<String>) -> Result<()>;
+    }
+
+    struct OutputMatcher {
+        expected: String,
+    }
+
+    impl Matcher for OutputMatcher {
+        fn check(&self, result: &Result<String>) -> Result<()> {
+            match result {
+                Ok(output) if *output == self.expected => Ok(()),
+                Ok(output) => bail!(
+                    "Output did not match.\nExpected: {:?}\n  Actual: {:?}",
+                    self.expected,
+                    output
+                ),
+                Err(e) => bail!("Expected success, but the run failed with: {}", e),
+            }
+        }
+    }
+
+    struct ErrorMsgMatcher {
+        expected: String,
+    }
+
+    impl Matcher for ErrorMsgMatcher {
+        fn check(&self, result: &Result<String>) -> Result<()> {
+            match result {
+                Ok(_) => bail!("Expected a runtime error, but the operation succeeded."),
+                Err(e) => {
+                    let actual_msg = e.to_string();
+                    if actual_msg.contains(&self.expected) {
+                        Ok(())
+                    } else {
+                        bail!(
+                            "Error message did not match.\nExpected to contain: \"{}\"\n           Actual: \"{}\"",
+                            self.expected,
+                            actual_msg
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    macro_rules! output_eq {
+        ($($line:expr),* $(,)?) => {
+            Box::new(OutputMatcher {
+                expected: [$(format!("{}\n", $line)),*].concat(),
+            })
+        };
+    }
+
+    macro_rules! error_msg_eq {
+        ($msg:expr) => {
+            Box::new(ErrorMsgMatcher {
+                expected: $msg.to_string(),
+            })
+        };
+    }
+
+    fn run(input: &str) -> Result<String> {
+        let buffer = SharedBuffer::default();
+        let program = parse(&scan(input)?)?;
+        Interpreter::with_output(buffer.clone()).interpret(&program)?;
+        let output = buffer.0.borrow().clone();
+        Ok(String::from_utf8(output)?)
+    }
+
+    fn run_test_internal(test_cases: &[TestCase]) {
+        for tc in test_cases {
+            let run_result = run(tc.input);
+
+            let check_result = tc.assertion.check(&run_result);
+
+            if let Err(error_message) = check_result {
+                panic!(
+                    "\n\n- Test Case Failed: '{}'\n- Input: '{}'\n- Reason: {}\n\n",
+                    tc.name, tc.input, error_message
+                );
+            }
+        }
+    }
+
+    macro_rules! run_tests {
+        ($($test_case:expr),* $(,)?) => {
+            run_test_internal(&[$($test_case),*])
+        };
+    }
+
+    #[test]
+    fn arithmetic() {
+        run_tests!(
+            TestCase {
+                name: "success - precedence",
+                input: "print 1 + 2 * 3;",
+                assertion: output_eq!(7),
+            },
+            TestCase {
+                name: "success - grouping and negation",
+                input: "print -(1 + 2) * 3 - 4 / 2;",
+                assertion: output_eq!(-11),
+            },
+            TestCase {
+                name: "success - integer division truncates",
+                input: "print 7 / 2;",
+                assertion: output_eq!(3),
+            },
+            TestCase {
+                name: "failure - division by zero",
+                input: "print 1 / 0;",
+                assertion: error_msg_eq!("division by zero at line 1, column 9"),
+            },
+            TestCase {
+                name: "failure - overflow",
+                input: "print 2147483647 + 1;",
+                assertion: error_msg_eq!(
+                    "integer overflow in '2147483647 + 1' at line 1, column 18"
+                ),
+            },
+            TestCase {
+                name: "success - modulo takes the sign of the dividend",
+                input: "print 7 % 3; print -7 % 3; print 7 % -3; print -7 / 3 * 3 + -7 % 3;",
+                assertion: output_eq!(1, -1, 1, -7),
+            },
+            TestCase {
+                name: "failure - modulo by zero",
+                input: "print 1 % 0;",
+                assertion: error_msg_eq!("modulo by zero"),
+            },
+            TestCase {
+                name: "success - exponent",
+                input: "print 2 ** 10; print -2 ** 2; print (-2) ** 3; print 2 ** 3 ** 2; print 5 ** 0;",
+                assertion: output_eq!(1024, -4, -8, 512, 1),
+            },
+            TestCase {
+                name: "failure - negative exponent",
+                input: "print 2 ** -1;",
+                assertion: error_msg_eq!("negative exponent in '2 ** -1'"),
+            },
+            TestCase {
+                name: "failure - exponent overflow",
+                input: "print 2 ** 31;",
+                assertion: error_msg_eq!("integer overflow in '2 ** 31'"),
+            },
+            TestCase {
+                name: "success - bitwise operators",
+                input: "print 12 & 10; print 12 | 10; print 12 ^ 10; print ~5; print ~-1;",
+                assertion: output_eq!(8, 14, 6, -6, 0),
+            },
+            TestCase {
+                name: "success - shifts",
+                input: "print 1 << 4; print 1 << 31; print 3 << 31; print -16 >> 2; print 7 >> 0;",
+                assertion: output_eq!(16, -2147483648, -2147483648, -4, 7),
+            },
+            TestCase {
+                name: "success - bitwise operators bind tighter than comparisons",
+                input: "print 6 & 1 == 0; print 1 | 2 ^ 3 & 4 << 1; print 1 << 2 + 1;",
+                assertion: output_eq!("true", 3, 8),
+            },
+            TestCase {
+                name: "failure - shift out of range",
+                input: "print 1 << 32;",
+                assertion: error_msg_eq!(
+                    "shift amount out of range in '1 << 32', it must be from 0 to 31"
+                ),
+            },
+            TestCase {
+                name: "failure - negative shift",
+                input: "print 1 >> -1;",
+                assertion: error_msg_eq!(
+                    "shift amount out of range in '1 >> -1', it must be from 0 to 31"
+                ),
+            },
+            TestCase {
+                name: "failure - bitwise operators need numbers",
+                input: "print ~true;",
+                assertion: error_msg_eq!("operand of '~' must be a number, found bool"),
+            },
+            TestCase {
+                name: "failure - negate a string",
+                input: r#"print -"a";"#,
+                assertion: error_msg_eq!("operand of '-' must be a number, found string"),
+            },
+            TestCase {
+                name: "failure - multiply mixed types",
+                input: r#"print 2 * "a";"#,
+                assertion: error_msg_eq!(
+                    "operands of '*' must be numbers, found number and string"
+                ),
+            },
+        )
+    }
+
+    #[test]
+    fn strings() {
+        run_tests!(
+            TestCase {
+                name: "success - concatenation",
+                input: r#"print "foo" + "bar";"#,
+                assertion: output_eq!("foobar"),
+            },
+            TestCase {
+                name: "failure - string plus number",
+                input: r#"print "a" + 1;"#,
+                assertion: error_msg_eq!(
+                    "operands of '+' must be two numbers or two strings, found string and number"
+                ),
+            },
+            TestCase {
+                name: "success - interpolation",
+                input: r#"var x = 41; print "total: ${x + 1}!"; print "${x}"; print "${[nil, "a"]} ${x > 1 ? "big" : "small"}";"#,
+                assertion: output_eq!("total: 42!", 41, r#"[nil, "a"] big"#),
+            },
+            TestCase {
+                name: "success - interpolated values are evaluated in order",
+                input: r#"var log = ""; fun f(s) { log = log + s; return s; } print "${f("a")}${f("b")}"; print log;"#,
+                assertion: output_eq!("ab", "ab"),
+            },
+            TestCase {
+                name: "success - interpolation works when 'str' is shadowed",
+                input: r#"var str = "s"; var x = 1; print "v ${x}"; fun f(str) { return "${str}!"; } print f(2);"#,
+                assertion: output_eq!("v 1", "2!"),
+            },
+            TestCase {
+                name: "success - an escaped '${' is printed as written",
+                input: r#"var x = 1; print "\${x} is ${x}"; print "cost: $5";"#,
+                assertion: output_eq!("${x} is 1", "cost: $5"),
+            },
+            TestCase {
+                name: "failure - an error in an interpolated expression",
+                input: r#"print "a ${1 + nil}";"#,
+                assertion: error_msg_eq!(
+                    "operands of '+' must be two numbers or two strings, found number and nil"
+                ),
+            },
+        )
+    }
+
+    #[test]
+    fn comparison_and_equality() {
+        run_tests!(
+            TestCase {
+                name: "success - comparisons",
+                input: "print 1 < 2; print 2 <= 1; print 3 > 2; print 2 >= 2;",
+                assertion: output_eq!(true, false, true, true),
+            },
+            TestCase {
+                name: "success - equality",
+                input: r#"print 1 == 1; print "a" != "a"; print nil == nil; print 1 == "1";"#,
+                assertion: output_eq!(true, false, true, false),
+            },
+            TestCase {
+                name: "failure - compare strings",
+                input: r#"print "a" < "b";"#,
+                assertion: error_msg_eq!("operands of '<' must be numbers"),
+            },
+        )
+    }
+
+    #[test]
+    fn truthiness() {
+        run_tests!(
+            TestCase {
+                name: "success - nil and false are falsey",
+                input: "print !nil; print !false;",
+                assertion: output_eq!(true, true),
+            },
+            TestCase {
+                name: "success - everything else is truthy",
+                input: r#"print !0; print !""; print !true;"#,
+                assertion: output_eq!(false, false, false),
+            },
+        )
+    }
+
+    #[test]
+    fn variables() {
+        run_tests!(
+            TestCase {
+                name: "success - declare and read",
+                input: "var a = 1; var b = a + 2; print b;",
+                assertion: output_eq!(3),
+            },
+            TestCase {
+                name: "success - uninitialized is nil",
+                input: "var a; print a;",
+                assertion: output_eq!("nil"),
+            },
+            TestCase {
+                name: "success - redeclaration replaces the binding",
+                input: r#"var a = 1; var a = "two"; print a;"#,
+                assertion: output_eq!("two"),
+            },
+            TestCase {
+                name: "success - assignment yields its value",
+                input: "var a; var b; a = b = 4; print a; print b;",
+                assertion: output_eq!(4, 4),
+            },
+            TestCase {
+                name: "success - compound assignment",
+                input: "var a = 10; a += 5; a -= 3; a *= 2 + 1; print a /= 4; var s = \"a\"; s += \"b\"; print s;",
+                assertion: output_eq!(9, "ab"),
+            },
+            TestCase {
+                name: "success - increment and decrement",
+                input: "var a = 1; print a++; print a; print ++a; print a--; print --a; print a;",
+                assertion: output_eq!(1, 2, 3, 3, 1, 1),
+            },
+            TestCase {
+                name: "failure - incrementing a string",
+                input: "var s = \"a\"; s++;",
+                assertion: error_msg_eq!(
+                    "operand of '++' must be a number, found string at line 1, column 15"
+                ),
+            },
+            TestCase {
+                name: "failure - read undefined variable",
+                input: "print 1;\nprint missing;",
+                assertion: error_msg_eq!("undefined variable 'missing' at line 2, column 7"),
+            },
+            TestCase {
+                name: "failure - assign undefined variable",
+                input: "missing = 1;",
+                assertion: error_msg_eq!("undefined variable 'missing' at line 1, column 1"),
+            },
+        )
+    }
+
+    #[test]
+    fn blocks() {
+        run_tests!(
+            TestCase {
+                name: "success - inner block reads outer variable",
+                input: "var a = 1; { print a; }",
+                assertion: output_eq!(1),
+            },
+            TestCase {
+                name: "success - inner block assigns outer variable",
+                input: "var a = 1; { a = 2; } print a;",
+                assertion: output_eq!(2),
+            },
+            TestCase {
+                name: "success - shadowing",
+                input: r#"
+                    var a = "outer";
+                    {
+                        var a = "inner";
+                        print a;
+                        { var a = "innermost"; print a; }
+                        print a;
+                    }
+                    print a;
+                "#,
+                assertion: output_eq!("inner", "innermost", "inner", "outer"),
+            },
+            TestCase {
+                name: "success - initializer reads shadowed outer value",
+                input: "var a = 1; { var a = a + 1; print a; } print a;",
+                assertion: output_eq!(2, 1),
+            },
+            TestCase {
+                name: "failure - outer scope can't see inner declarations",
+                input: "{ var inner = 1; }\nprint inner;",
+                assertion: error_msg_eq!("undefined variable 'inner' at line 2, column 7"),
+            },
+        )
+    }
+
+    #[test]
+    fn scope_is_restored_after_an_error() {
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::with_output(buffer.clone());
+
+        let failing = parse(&scan("var a = 1; { var a = 2; print missing; }").unwrap()).unwrap();
+        assert!(interpreter.interpret(&failing).is_err());
+
+        let program = parse(&scan("print a;").unwrap()).unwrap();
+        interpreter.interpret(&program).unwrap();
+        assert_eq!(*buffer.0.borrow(), b"1\n");
+    }
+
+    #[test]
+    fn runtime_errors_carry_a_trace() {
+        let source =
+            "fun inner() { return missing; }\nfun outer() { return inner(); }\nprint 1;\nouter();";
+        let buffer = SharedBuffer::default();
+        let program = parse(&scan(source).unwrap()).unwrap();
+        let error = Interpreter::with_output(buffer.clone())
+            .interpret(&program)
+            .unwrap_err();
+
+        let error = error.downcast::<RuntimeError>().unwrap();
+        assert_eq!(error.message, "undefined variable 'missing'");
+        assert_eq!(
+            error.location,
+            Some(Location {
+                line: 1,
+                column: 22
+            })
+        );
+        assert_eq!(
+            error.trace,
+            vec![
+                Frame {
+                    function: "<fn inner>".to_string(),
+                    location: Location {
+                        line: 2,
+                        column: 27
+                    },
+                },
+                Frame {
+                    function: "<fn outer>".to_string(),
+                    location: Location { line: 4, column: 6 },
+                },
+            ]
+        );
+        assert_eq!(
+            error.to_string(),
+            "interpreter: undefined variable 'missing' at line 1, column 22\n  in <fn inner> called at line 2, column 27\n  in <fn outer> called at line 4, column 6"
+        );
+    }
+
+    #[test]
+    fn errors_in_a_chain_point_at_the_failing_link() {
+        let class = "class A { b(x) { return this; } n() { return nil; } }\n";
+        let cases = [
+            ("undefined property", "A().b(1).c(2).d;", ".c"),
+            ("calling a non-function", "A().n()(3);", "(3)"),
+            ("arity", "A().b(1).b(1, 2).n();", "(1, 2)"),
+            ("property of a non-instance", "A().b(1).n().d;", ".d"),
+            ("field of a non-instance", "A().n().d = 1;", ".d"),
+        ];
+        for (name, chain, link) in cases {
+            let source = format!("{}{}", class, chain);
+            let program = parse(&scan(&source).unwrap()).unwrap();
+            let error = Interpreter::with_output(SharedBuffer::default())
+                .interpret(&program)
+                .unwrap_err();
+            let error = error.downcast::<RuntimeError>().unwrap();
+            let span = error.span.expect(name);
+            assert_eq!(&source[span.start..span.end], link, "case: {}", name);
+        }
+    }
+
+    #[test]
+    fn errors_from_outside_the_interpreter_become_runtime_errors() {
+        let mut interpreter = Interpreter::with_output(SharedBuffer::default());
+        interpreter.define_native("fail", 0, |_| bail!("interpreter: fail: gave up"));
+        let program = parse(&scan("fail();").unwrap()).unwrap();
+        let error = interpreter.interpret(&program).unwrap_err();
+        let error = error.downcast::<RuntimeError>().unwrap();
+        assert_eq!(error.message, "fail: gave up");
+        assert_eq!(error.location, None);
+        assert_eq!(error.trace.len(), 1);
+    }
+
+    #[test]
+    fn print_is_buffered_until_flushed() {
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::with_output(buffer.clone());
+        let seen = buffer.clone();
+        interpreter.define_native("written", 0, move |_| {
+            Ok(Value::String(String::from_utf8(seen.0.borrow().clone())?))
+        });
+
+        let source = "print 1; var before = written(); flush(); var after = written(); print 2;";
+        let program = parse(&scan(source).unwrap()).unwrap();
+        interpreter.interpret(&program).unwrap();
+        assert_eq!(
+            interpreter.get_global("before"),
+            Some(Value::String(String::new()))
+        );
+        assert_eq!(
+            interpreter.get_global("after"),
+            Some(Value::String("1\n".to_string()))
+        );
+        assert_eq!(*buffer.0.borrow(), b"1\n2\n");
+
+        interpreter.set_buffered(false).unwrap();
+        let program = parse(&scan("print 3; var unbuffered = written();").unwrap()).unwrap();
+        interpreter.interpret(&program).unwrap();
+        assert_eq!(
+            interpreter.get_global("unbuffered"),
+            Some(Value::String("1\n2\n3\n".to_string()))
+        );
+    }
+
+    #[test]
+    fn natives() {
+        run_tests!(
+            TestCase {
+                name: "success - clock is built in",
+                input: "print clock; print clock() > 0;",
+                assertion: output_eq!("<native fn clock>", true),
+            },
+            TestCase {
+                name: "failure - native arity",
+                input: "clock(1);",
+                assertion: error_msg_eq!("<native fn clock> expected 0 arguments but got 1"),
+            },
+        )
+    }
+
+    #[test]
+    fn host_defines_natives() {
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::with_output(buffer.clone());
+        interpreter.define_native("twice", 1, |arguments| match &arguments[0] {
+            Value::Number(n) => Ok(Value::Number(n * 2)),
+            value => bail!(
+                "interpreter: twice expects a number, found {}",
+                value.type_name()
+            ),
+        });
+
+        let program = parse(&scan("print twice(21); twice(nil);").unwrap()).unwrap();
+        let error = interpreter.interpret(&program).unwrap_err();
+        assert_eq!(*buffer.0.borrow(), b"42\n");
+        assert_eq!(
+            error.to_string(),
+            "interpreter: twice expects a number, found nil\n  in <native fn twice> called at line 1, column 23"
+        );
+    }
+
+    #[test]
+    fn host_reads_and_writes_globals() {
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::with_output(buffer.clone());
+        interpreter.set_global("limit", Value::Number(3));
+
+        let source = "var total = limit * 2;\nfun f() { var local = 1; }\nprint total;";
+        let program = parse(&scan(source).unwrap()).unwrap();
+        interpreter.interpret(&program).unwrap();
+
+        assert_eq!(*buffer.0.borrow(), b"6\n");
+        assert_eq!(interpreter.get_global("total"), Some(Value::Number(6)));
+        assert_eq!(interpreter.get_global("local"), None);
+
+        let globals: Vec<_> = interpreter
+            .globals()
+            .iter()
+            .filter(|global| !matches!(global.value, Value::Native(_)))
+            .map(|global| (global.name.clone(), global.kind(), global.defined_at))
+            .collect();
+        assert_eq!(
+            globals,
+            vec![
+                (
+                    "f".to_string(),
+                    "function",
+                    Some(Location { line: 2, column: 5 })
+                ),
+                ("limit".to_string(), "number", None),
+                (
+                    "total".to_string(),
+                    "number",
+                    Some(Location { line: 1, column: 5 })
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn conditionals() {
+        run_tests!(
+            TestCase {
+                name: "success - then branch",
+                input: "if (1 < 2) print 1; else print 2;",
+                assertion: output_eq!(1),
+            },
+            TestCase {
+                name: "success - else branch",
+                input: "if (nil) print 1; else print 2;",
+                assertion: output_eq!(2),
+            },
+            TestCase {
+                name: "success - no else branch",
+                input: "if (false) print 1; print 3;",
+                assertion: output_eq!(3),
+            },
+            TestCase {
+                name: "success - block branches",
+                input: "var a = 0; if (a == 0) { a = 1; print a; } else { print 0; }",
+                assertion: output_eq!(1),
+            },
+        )
+    }
+
+    #[test]
+    fn logical_operators() {
+        run_tests!(
+            TestCase {
+                name: "success - operators return an operand",
+                input: r#"print nil or "yes"; print 0 and "b"; print false and 1; print "a" or 2;"#,
+                assertion: output_eq!("yes", "b", "false", "a"),
+            },
+            TestCase {
+                name: "success - and short-circuits",
+                input: "var a = 1; false and (a = 2); print a;",
+                assertion: output_eq!(1),
+            },
+            TestCase {
+                name: "success - or short-circuits",
+                input: "var a = 1; true or (a = 2); print a;",
+                assertion: output_eq!(1),
+            },
+            TestCase {
+                name: "success - right operand is evaluated when needed",
+                input: "var a = 1; nil or (a = 2); print a;",
+                assertion: output_eq!(2),
+            },
+            TestCase {
+                name: "success - conditional evaluates one branch",
+                input: "var a = 0; print true ? \"yes\" : (a = 1); print nil ? 1 : 0 ? 2 : 3; print a;",
+                assertion: output_eq!("yes", 2, 0),
+            },
+            TestCase {
+                name: "success - comma evaluates both operands and yields the right",
+                input: "var a = 0; var b = (a = a + 1, a + 10); print a; print b; print len(\"ab\"), 3;",
+                assertion: output_eq!(1, 11, 3),
+            },
+            TestCase {
+                name: "success - short-circuit skips runtime errors",
+                input: "print false and missing;",
+                assertion: output_eq!("false"),
+            },
+        )
+    }
+
+    #[test]
+    fn loops() {
+        run_tests!(
+            TestCase {
+                name: "success - while loop",
+                input: "var i = 0; while (i < 3) { print i; i = i + 1; }",
+                assertion: output_eq!(0, 1, 2),
+            },
+            TestCase {
+                name: "success - while loop that never runs",
+                input: "while (false) print 1; print 2;",
+                assertion: output_eq!(2),
+            },
+            TestCase {
+                name: "success - for loop",
+                input: "for (var i = 0; i < 3; i = i + 1) print i;",
+                assertion: output_eq!(0, 1, 2),
+            },
+            TestCase {
+                name: "success - for loop that never runs",
+                input: "for (var i = 10; i < 3; i = i + 1) print i; print 0;",
+                assertion: output_eq!(0),
+            },
+            TestCase {
+                name: "success - for loop variable is scoped to the loop",
+                input: "var i = 100; for (var i = 0; i < 2; i = i + 1) {} print i;",
+                assertion: output_eq!(100),
+            },
+            TestCase {
+                name: "success - loop bounded by a counter",
+                input: "var n = 0; while (true and n < 1000) n = n + 1; print n;",
+                assertion: output_eq!(1000),
+            },
+            TestCase {
+                name: "success - for loop with only a condition",
+                input: "var n = 1; for (; n < 100;) n = n * 2; print n;",
+                assertion: output_eq!(128),
+            },
+            TestCase {
+                name: "success - continue still runs the increment",
+                input: "for (var i = 0; i < 5; i = i + 1) { if (i == 1 or i == 3) continue; print i; }",
+                assertion: output_eq!(0, 2, 4),
+            },
+            TestCase {
+                name: "success - break leaves only the innermost loop",
+                input: "for (var i = 0; i < 2; i = i + 1) { while (true) { print i; break; print 9; } } print \"done\";",
+                assertion: output_eq!(0, 1, "done"),
+            },
+            TestCase {
+                name: "success - break inside a function call's loop returns normally",
+                input: "fun first() { var n = 0; while (true) { n = n + 1; if (n > 2) break; } return n; } print first();",
+                assertion: output_eq!(3),
+            },
+            TestCase {
+                name: "success - fibonacci",
+                input: r#"
+                    var a = 0;
+                    var b = 1;
+                    for (var i = 0; i < 10; i = i + 1) {
+                        print a;
+                        var next = a + b;
+                        a = b;
+                        b = next;
+                    }
+                "#,
+                assertion: output_eq!(0, 1, 1, 2, 3, 5, 8, 13, 21, 34),
+            },
+        )
+    }
+
+    #[test]
+    fn functions() {
+        run_tests!(
+            TestCase {
+                name: "success - call with arguments",
+                input: "fun add(a, b) { return a + b; } print add(1, 2);",
+                assertion: output_eq!(3),
+            },
+            TestCase {
+                name: "success - implicit nil return",
+                input: "fun f() { 1 + 1; } print f();",
+                assertion: output_eq!("nil"),
+            },
+            TestCase {
+                name: "success - bare return",
+                input: "fun f() { print 1; return; print 2; } print f();",
+                assertion: output_eq!(1, "nil"),
+            },
+            TestCase {
+                name: "success - return unwinds loops and blocks",
+                input: r#"
+                    fun firstOver(limit) {
+                        for (var i = 0; ; i = i + 1) {
+                            { if (i > limit) return i; }
+                        }
+                    }
+                    print firstOver(5);
+                "#,
+                assertion: output_eq!(6),
+            },
+            TestCase {
+                name: "success - recursion",
+                input: "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); } print fib(15);",
+                assertion: output_eq!(610),
+            },
+            TestCase {
+                name: "failure - unbounded recursion overflows the stack",
+                input: "fun f(n) { return f(n + 1); }\nf(0);",
+                assertion: error_msg_eq!("stack overflow at line 1, column 20"),
+            },
+            TestCase {
+                name: "success - closures capture their environment",
+                input: r#"
+                    fun makeCounter() {
+                        var count = 0;
+                        fun counter() {
+                            count = count + 1;
+                            return count;
+                        }
+                        return counter;
+                    }
+                    var counter = makeCounter();
+                    counter();
+                    print counter();
+                    print makeCounter()();
+                "#,
+                assertion: output_eq!(2, 1),
+            },
+            TestCase {
+                name: "success - parameters shadow globals",
+                input: "var a = 1; fun f(a) { print a; } f(2); print a;",
+                assertion: output_eq!(2, 1),
+            },
+            TestCase {
+                name: "success - functions are values",
+                input: "fun f() {} var g = f; print g; print f == g;",
+                assertion: output_eq!("<fn f>", true),
+            },
+            TestCase {
+                name: "failure - arity mismatch",
+                input: "fun f(a) {}\nf(1, 2);",
+                assertion: error_msg_eq!(
+                    "<fn f> expected 1 arguments but got 2 at line 2, column 2"
+                ),
+            },
+            TestCase {
+                name: "failure - call a non-function",
+                input: r#""not a function"();"#,
+                assertion: error_msg_eq!("can only call functions and classes, found string"),
+            },
+        )
+    }
+
+    #[test]
+    fn lists() {
+        run_tests!(
+            TestCase {
+                name: "success - literals print their elements",
+                input: "print []; print [1, \"two\", nil, [true]];",
+                assertion: output_eq!("[]", r#"[1, "two", nil, [true]]"#),
+            },
+            TestCase {
+                name: "success - strings in a list print quoted",
+                input: "print [\"1\", 1, [\"a\"], {\"k\": [\"v\"]}];",
+                assertion: output_eq!(r#"["1", 1, ["a"], {"k": ["v"]}]"#),
+            },
+            TestCase {
+                name: "success - indexing and assigning elements",
+                input: "var a = [1, 2, 3]; a[1] = a[0] + a[2]; print a; print a[1] = 7; print a[1];",
+                assertion: output_eq!("[1, 4, 3]", 7, 7),
+            },
+            TestCase {
+                name: "success - compound assignment to elements",
+                input: "var a = [1, 2]; var i = 0; a[i] += 10; a[1] *= a[0]; print a;",
+                assertion: output_eq!("[11, 22]"),
+            },
+            TestCase {
+                name: "success - compound assignment evaluates the list and index once",
+                input: "var a = [1, 2]; var calls = 0; fun list() { calls += 1; return a; } fun idx() { calls += 10; return 1; } list()[idx()] += 10; print a; print calls;",
+                assertion: output_eq!("[1, 12]", 11),
+            },
+            TestCase {
+                name: "failure - compound assignment whose value shrinks the list",
+                input: "var a = [1, 2]; a[1] += pop(a);",
+                assertion: error_msg_eq!(
+                    "index 1 is out of bounds for a list of length 1 at line 1, column 18"
+                ),
+            },
+            TestCase {
+                name: "success - incrementing elements",
+                input: "var a = [1, 5]; a[0]++; print --a[1] + a[0]; print a;",
+                assertion: output_eq!(6, "[2, 4]"),
+            },
+            TestCase {
+                name: "success - incrementing evaluates the list and index once",
+                input: "var a = [1, 5]; var calls = 0; fun idx() { calls += 1; return 1; } print a[idx()]++; print ++a[idx()]; print a; print calls;",
+                assertion: output_eq!(5, 7, "[1, 7]", 2),
+            },
+            TestCase {
+                name: "success - variables share a list",
+                input: "var a = [1]; var b = a; push(b, 2); print a; print len(a); print pop(a); print b; print a == b; print [] == [];",
+                assertion: output_eq!("[1, 2]", 2, 2, "[1]", "true", "false"),
+            },
+            TestCase {
+                name: "success - a list that contains itself",
+                input: "var a = [1]; push(a, a); print a; print str([a]); var b = [2]; print [b, b];",
+                assertion: output_eq!("[1, [...]]", "[[1, [...]]]", "[[2], [2]]"),
+            },
+            TestCase {
+                name: "success - elements are evaluated in order",
+                input: "var log = []; fun f(x) { push(log, x); return x; } [f(1), f(2)][f(0)] = f(3); print log;",
+                assertion: output_eq!("[1, 2, 0, 3]"),
+            },
+            TestCase {
+                name: "failure - index out of bounds",
+                input: "var a = [1, 2]; print a[2];",
+                assertion: error_msg_eq!("index 2 is out of bounds for a list of length 2"),
+            },
+            TestCase {
+                name: "failure - negative index",
+                input: "var a = [1]; a[-1] = 0;",
+                assertion: error_msg_eq!("index -1 is out of bounds for a list of length 1"),
+            },
+            TestCase {
+                name: "failure - index that is not a number",
+                input: "print [1][\"0\"];",
+                assertion: error_msg_eq!("list index must be a number, found string"),
+            },
+            TestCase {
+                name: "failure - indexing something that is not a list",
+                input: "var n = 1; print n[0];",
+                assertion: error_msg_eq!("can only index lists, maps and strings, found number"),
+            },
+        )
+    }
+
+    #[test]
+    fn slices() {
+        run_tests!(
+            TestCase {
+                name: "success - slicing a list makes a new list",
+                input: "var a = [1, 2, 3, 4]; var b = a[1:3]; b[0] = 0; print b; print a; print a[:1]; print a[3:]; print a[2:2];",
+                assertion: output_eq!("[0, 3]", "[1, 2, 3, 4]", "[1]", "[4]", "[]"),
+            },
+            TestCase {
+                name: "success - from the start or to the end",
+                input: "var a = [1, 2]; print a[:] == a; print a[:];",
+                assertion: output_eq!("false", "[1, 2]"),
+            },
+            TestCase {
+                name: "failure - bounds out of order",
+                input: "print [1, 2, 3][2:1];",
+                assertion: error_msg_eq!("slice 2:1 is out of bounds for a list of length 3"),
+            },
+            TestCase {
+                name: "failure - bound past the end",
+                input: "print [1][:2];",
+                assertion: error_msg_eq!("slice 0:2 is out of bounds for a list of length 1"),
+            },
+            TestCase {
+                name: "failure - bound that is not a number",
+                input: "print [1][nil:];",
+                assertion: error_msg_eq!("slice bounds must be numbers, found nil"),
+            },
+            TestCase {
+                name: "failure - slicing a map",
+                input: "print {}[0:1];",
+                assertion: error_msg_eq!("can only slice lists and strings, found map"),
+            },
+        )
+    }
+
+    #[test]
+    fn strings_as_collections() {
+        run_tests!(
+            TestCase {
+                name: "success - indexing gives one-character strings",
+                input: "var s = \"lox\"; print s[0]; print s[2] + s[1]; print s[0] == \"l\";",
+                assertion: output_eq!("l", "xo", "true"),
+            },
+            TestCase {
+                name: "success - positions count characters, not bytes",
+                input: "var s = \"h\u{e9}llo \u{4e16}\u{754c}\"; print s[1]; print s[6]; print s[1:4]; print s[6:]; print len(s);",
+                assertion: output_eq!("\u{e9}", "\u{4e16}", "\u{e9}ll", "\u{4e16}\u{754c}", 8),
+            },
+            TestCase {
+                name: "success - iterating in a for loop",
+                input: "var s = \"ab\u{e7}\"; for (var i = 0; i < len(s); i = i + 1) print s[i];",
+                assertion: output_eq!("a", "b", "\u{e7}"),
+            },
+            TestCase {
+                name: "success - the empty slice of a string",
+                input: "print \"abc\"[1:1] == \"\";",
+                assertion: output_eq!("true"),
+            },
+            TestCase {
+                name: "failure - index past the last character",
+                input: "print \"\u{e9}\"[1];",
+                assertion: error_msg_eq!("index 1 is out of bounds for a string of length 1"),
+            },
+            TestCase {
+                name: "failure - index that is not a number",
+                input: "print \"abc\"[\"a\"];",
+                assertion: error_msg_eq!("string index must be a number, found string"),
+            },
+            TestCase {
+                name: "failure - strings can't be changed",
+                input: "var s = \"abc\"; s[0] = \"x\";",
+                assertion: error_msg_eq!(
+                    "strings can't be changed, so can't assign to an index of one at line 1, column 17"
+                ),
+            },
+        )
+    }
+
+    #[test]
+    fn maps() {
+        run_tests!(
+            TestCase {
+                name: "success - literals print their entries in order",
+                input: "print {}; print {\"b\": 1, \"a\": [2], 3: nil};",
+                assertion: output_eq!("{}", r#"{"b": 1, "a": [2], 3: nil}"#),
+            },
+            TestCase {
+                name: "success - getting and setting entries",
+                input: "var m = {\"a\": 1}; m[\"b\"] = m[\"a\"] + 1; m[\"a\"] = 0; print m; print len(m);",
+                assertion: output_eq!(r#"{"a": 0, "b": 2}"#, 2),
+            },
+            TestCase {
+                name: "success - numbers and strings are different keys",
+                input: "var m = {1: \"number\", \"1\": \"string\"}; print m[1]; print m[\"1\"]; print has(m, 2 - 1);",
+                assertion: output_eq!("number", "string", "true"),
+            },
+            TestCase {
+                name: "success - strings in a map print quoted",
+                input: "print {1: \"x\", \"1\": \"y\", 2: 1, 3: \"1\"};",
+                assertion: output_eq!(r#"{1: "x", "1": "y", 2: 1, 3: "1"}"#),
+            },
+            TestCase {
+                name: "success - a map that contains itself",
+                input: "var m = {}; m[\"x\"] = m; m[\"l\"] = [m]; print m; print str(m);",
+                assertion: output_eq!(
+                    r#"{"x": {...}, "l": [{...}]}"#,
+                    r#"{"x": {...}, "l": [{...}]}"#
+                ),
+            },
+            TestCase {
+                name: "success - a repeated key keeps its first place",
+                input: "var m = {\"a\": 1, \"b\": 2, \"a\": 3}; print keys(m); print values(m);",
+                assertion: output_eq!(r#"["a", "b"]"#, "[3, 2]"),
+            },
+            TestCase {
+                name: "success - variables share a map",
+                input: "var a = {}; var b = a; b[\"x\"] = 1; print has(a, \"x\"); print a == b; print {} == {};",
+                assertion: output_eq!("true", "true", "false"),
+            },
+            TestCase {
+                name: "failure - missing key",
+                input: "var m = {\"a\": 1}; print m[\"b\"];",
+                assertion: error_msg_eq!("map has no key \"b\" at line 1, column 26"),
+            },
+            TestCase {
+                name: "failure - key that can't be a key",
+                input: "var m = {}; m[nil] = 1;",
+                assertion: error_msg_eq!("map key must be a number or string, found nil"),
+            },
+            TestCase {
+                name: "failure - literal key that can't be a key",
+                input: "var m = {true: 1};",
+                assertion: error_msg_eq!(
+                    "map key must be a number or string, found bool at line 1, column 9"
+                ),
+            },
+        )
+    }
+
+    #[test]
+    fn field_declarations() {
+        run_tests!(
+            TestCase {
+                name: "success - defaults without an initializer",
+                input: "class P { x = 0; y = 1 + 1; } var p = P(); print p.x; print p.y;",
+                assertion: output_eq!(0, 2),
+            },
+            TestCase {
+                name: "success - fields are set before init runs",
+                input: "class P { x = 1; init(y) { print this.x; this.x = this.x + y; } } print P(2).x;",
+                assertion: output_eq!(1, 3),
+            },
+            TestCase {
+                name: "success - each instance gets its own default",
+                input: "class Bag { items = []; } var a = Bag(); var b = Bag(); push(a.items, 1); print a.items; print b.items;",
+                assertion: output_eq!("[1]", "[]"),
+            },
+            TestCase {
+                name: "success - initializers run in order and see this",
+                input: "var n = 0; fun next() { n = n + 1; return n; } class P { a = next(); b = this.a * 10; } var p = P(); print p.a; print p.b; print P().a;",
+                assertion: output_eq!(1, 10, 2),
+            },
+            TestCase {
+                name: "success - a subclass's defaults come after its superclass's",
+                input: "class A { x = \"a\"; y = \"a\"; } class B < A { y = \"b\"; z = this.x; } var b = B(); print b.x; print b.y; print b.z;",
+                assertion: output_eq!("a", "b", "a"),
+            },
+            TestCase {
+                name: "failure - an error in an initializer",
+                input: "class P { x = missing; } P();",
+                assertion: error_msg_eq!("undefined variable 'missing' at line 1, column 15"),
+            },
+        )
+    }
+
+    #[test]
+    fn classes() {
+        run_tests!(
+            TestCase {
+                name: "success - print class and instance",
+                input: "class A {} print A; print A();",
+                assertion: output_eq!("<class A>", "<A instance>"),
+            },
+            TestCase {
+                name: "success - fields",
+                input: "class A {} var a = A(); a.x = 1; a.x = a.x + 1; print a.x;",
+                assertion: output_eq!(2),
+            },
+            TestCase {
+                name: "success - compound assignment to a field",
+                input: "class A {} var a = A(); a.x = 1; a.x += 2; print a.x *= 3;",
+                assertion: output_eq!(9),
+            },
+            TestCase {
+                name: "success - compound assignment evaluates the object once",
+                input: "class A {} var a = A(); a.x = 1; var calls = 0; fun obj() { calls += 1; return a; } obj().x += 2; print a.x; print calls;",
+                assertion: output_eq!(3, 1),
+            },
+            TestCase {
+                name: "failure - compound assignment of the wrong type",
+                input: "var a = [\"s\"];\na[0] -= 1;",
+                assertion: error_msg_eq!(
+                    "operands of '-' must be numbers, found string and number at line 2, column 6"
+                ),
+            },
+            TestCase {
+                name: "success - incrementing a field",
+                input: "class A {} var a = A(); a.n = 5; print a.n++ + ++a.n; print a.n;",
+                assertion: output_eq!(12, 7),
+            },
+            TestCase {
+                name: "success - methods see this",
+                input: r#"
+                    class Counter {
+                        bump() { this.count = this.count + 1; return this.count; }
+                    }
+                    var c = Counter();
+                    c.count = 0;
+                    c.bump();
+                    print c.bump();
+                "#,
+                assertion: output_eq!(2),
+            },
+            TestCase {
+                name: "success - initializer takes arguments",
+                input: r#"
+                    class Point {
+                        init(x, y) { this.x = x; this.y = y; }
+                        sum() { return this.x + this.y; }
+                    }
+                    print Point(1, 2).sum();
+                "#,
+                assertion: output_eq!(3),
+            },
+            TestCase {
+                name: "success - calling init again returns the instance",
+                input: r#"
+                    class A { init() { this.n = 1; return; } }
+                    var a = A();
+                    print a.init() == a;
+                "#,
+                assertion: output_eq!(true),
+            },
+            TestCase {
+                name: "success - bound methods remember their instance",
+                input: r#"
+                    class A { name() { return this.label; } }
+                    var a = A();
+                    a.label = "a";
+                    var m = a.name;
+                    print m();
+                "#,
+                assertion: output_eq!("a"),
+            },
+            TestCase {
+                name: "success - fields shadow methods",
+                input: "class A { m() { return 1; } } var a = A(); a.m = 2; print a.m;",
+                assertion: output_eq!(2),
+            },
+            TestCase {
+                name: "failure - initializer arity",
+                input: "class A { init(a) {} }\nA();",
+                assertion: error_msg_eq!(
+                    "<class A> expected 1 arguments but got 0 at line 2, column 2"
+                ),
+            },
+            TestCase {
+                name: "failure - undefined property",
+                input: "class A {} A().missing;",
+                assertion: error_msg_eq!(
+                    "undefined property 'missing' on <A instance> at line 1, column 16"
+                ),
+            },
+            TestCase {
+                name: "failure - property on a non-instance",
+                input: "var a = 1; a.x = 2;",
+                assertion: error_msg_eq!("only instances have fields, found number"),
+            },
+        )
+    }
+
+    #[test]
+    fn inheritance() {
+        run_tests!(
+            TestCase {
+                name: "success - methods are inherited",
+                input: "class A { f() { return 1; } } class B < A {} print B().f();",
+                assertion: output_eq!(1),
+            },
+            TestCase {
+                name: "success - subclasses override and call super",
+                input: r#"
+                    class A { name() { return "A"; } }
+                    class B < A { name() { return "B" + super.name(); } }
+                    print B().name();
+                "#,
+                assertion: output_eq!("BA"),
+            },
+            TestCase {
+                name: "success - super binds this",
+                input: r#"
+                    class A { init(n) { this.n = n; } }
+                    class B < A { init(n) { super.init(n + 1); } }
+                    print B(1).n;
+                "#,
+                assertion: output_eq!(2),
+            },
+            TestCase {
+                name: "success - super in an inherited method names its own superclass",
+                input: r#"
+                    class A { f() { return "A"; } }
+                    class B < A { f() { return "B" + super.f(); } }
+                    class C < B {}
+                    print C().f();
+                "#,
+                assertion: output_eq!("BA"),
+            },
+            TestCase {
+                name: "failure - superclass is not a class",
+                input: "var A = 1; class B < A {}",
+                assertion: error_msg_eq!(
+                    "superclass must be a class, found number at line 1, column 22"
+                ),
+            },
+            TestCase {
+                name: "failure - undefined super method",
+                input: "class A {} class B < A { f() { super.g(); } } B().f();",
+                assertion: error_msg_eq!(
+                    "undefined property 'g' on superclass <class A> at line 1, column 38"
+                ),
+            },
+        )
+    }
+
+    #[derive(Clone, Default)]
+    struct RecordingHooks(Rc<RefCell<Vec<String>>>);
+
+    impl Hooks for RecordingHooks {
+        fn before_statement(&mut self, stmt: &Stmt) {
+            self.0.borrow_mut().push(format!("before {}", stmt));
+        }
+
+        fn after_statement(&mut self, stmt: &Stmt) {
+            self.0.borrow_mut().push(format!("after {}", stmt));
+        }
+
+        fn on_call(&mut self, function: &Value, arguments: &[Value]) {
+            let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+            self.0
+                .borrow_mut()
+                .push(format!("call {}({})", function, arguments.join(", ")));
+        }
+
+        fn on_return(&mut self, function: &Value, value: &Value) {
+            self.0
+                .borrow_mut()
+                .push(format!("return {} -> {}", function, value));
+        }
+    }
+
+    #[test]
+    fn hooks_observe_execution() {
+        let hooks = RecordingHooks::default();
+        let mut interpreter = Interpreter::with_output(SharedBuffer::default());
+        interpreter.set_hooks(hooks.clone());
+
+        let program = parse(&scan("fun f(a) { return a; } f(1);").unwrap()).unwrap();
+        interpreter.interpret(&program).unwrap();
+
+        assert_eq!(
+            *hooks.0.borrow(),
+            vec![
+                "before (fun f (a) (return a))",
+                "after (fun f (a) (return a))",
+                "before (expr (call f 1))",
+                "call <fn f>(1)",
+                "before (return a)",
+                "after (return a)",
+                "return <fn f> -> 1",
+                "after (expr (call f 1))",
+            ]
+        );
+    }
+
+    #[test]
+    fn hooks_are_not_told_about_failed_statements() {
+        let hooks = RecordingHooks::default();
+        let mut interpreter = Interpreter::with_output(SharedBuffer::default());
+        interpreter.set_hooks(hooks.clone());
+
+        let program = parse(&scan("print missing;").unwrap()).unwrap();
+        assert!(interpreter.interpret(&program).is_err());
+
+        assert_eq!(*hooks.0.borrow(), vec!["before (print missing)"]);
+    }
+
+    #[test]
+    fn calls_can_nest_as_deep_again_after_a_stack_overflow() {
+        let mut interpreter = Interpreter::with_output(SharedBuffer::default());
+        let overflow = parse(&scan("fun f(n) { return f(n + 1); } f(0);").unwrap()).unwrap();
+        assert!(interpreter.interpret(&overflow).is_err());
+
+        let source = "fun g(n) { if (n == 0) return 0; return g(n - 1); } g(254);";
+        let deep = parse(&scan(source).unwrap()).unwrap();
+        interpreter.interpret(&deep).unwrap();
+    }
+
+    #[test]
+    fn output_limits() {
+        let total = |on_exceed| OutputLimit {
+            total_bytes: Some(8),
+            line_bytes: None,
+            on_exceed,
+        };
+        let line = |on_exceed| OutputLimit {
+            total_bytes: None,
+            line_bytes: Some(5),
+            on_exceed,
+        };
+        let cases = [
+            (
+                "a total that is not reached",
+                total(OnExceed::Error),
+                "print 123; print 456;",
+                Ok("123\n456\n"),
+            ),
+            (
+                "truncating at the total",
+                total(OnExceed::Truncate),
+                "for (var i = 0; i < 100; i = i + 1) print i;",
+                Ok("0\n1\n2\n3\n[output stopped at the limit of 8 bytes]\n"),
+            ),
+            (
+                "an error at the total",
+                total(OnExceed::Error),
+                "print 1234; print 5678;",
+                Err("interpreter: output is over the limit of 8 bytes"),
+            ),
+            (
+                "truncating a long line",
+                line(OnExceed::Truncate),
+                "print \"short\"; print \"much longer\";",
+                Ok("short\nmuch ... (6 more bytes)\n"),
+            ),
+            (
+                "truncating between characters",
+                line(OnExceed::Truncate),
+                "print \"abcd\u{e9}\";",
+                Ok("abcd... (2 more bytes)\n"),
+            ),
+            (
+                "an error at a long line",
+                line(OnExceed::Error),
+                "print \"much longer\";",
+                Err("interpreter: a print of 11 bytes is over the line limit of 5 bytes"),
+            ),
+        ];
+        for (name, limit, source, expected) in cases {
+            let buffer = SharedBuffer::default();
+            let mut interpreter = Interpreter::with_output(buffer.clone());
+            interpreter.set_output_limit(limit);
+            let program = parse(&scan(source).unwrap()).unwrap();
+            let result = interpreter
+                .interpret(&program)
+                .map(|()| String::from_utf8(buffer.0.borrow().clone()).unwrap())
+                .map_err(|e| {
+                    assert!(e.is::<RuntimeError>(), "case: {}", name);
+                    e.to_string()
+                });
+            assert_eq!(
+                result,
+                expected.map(str::to_string).map_err(str::to_string),
+                "case: {}",
+                name
+            );
+        }
+    }
+}
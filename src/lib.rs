@@ -0,0 +1,102 @@
+// Every type the public API reaches must be nameable from outside, through
+// the re-exports below.
+#![warn(unnameable_types)]
+
+pub mod ast;
+pub mod ast_printer;
+pub mod audit;
+pub mod bench;
+pub(crate) mod chunk;
+pub(crate) mod compiler;
+pub mod diagnostic;
+pub mod disassembler;
+pub(crate) mod environment;
+pub mod explain;
+pub mod grammar;
+pub mod hooks;
+pub(crate) mod interner;
+pub mod interpreter;
+mod json;
+pub mod lint;
+mod lox;
+pub mod minify;
+pub mod parser;
+pub mod profile;
+pub mod runtime_error;
+pub mod scanner;
+pub(crate) mod stdlib;
+#[cfg(feature = "threaded")]
+pub mod threaded;
+pub mod token;
+pub mod token_diff;
+pub(crate) mod value;
+pub mod vm;
+
+pub use interpreter::Interpreter;
+pub use lox::Lox;
+pub use parser::parse;
+pub use scanner::scan;
+
+// The modules below are the supported way in. Everything they re-export
+// keeps its path and meaning across minor versions; `tests/api.rs` holds
+// them to that. The enums among them are `#[non_exhaustive]`, so a new
+// variant is not a breaking change.
+
+/// What embedding Lox usually takes, for `use rslox::prelude::*`.
+pub mod prelude {
+    pub use crate::Lox;
+    pub use crate::interpreter::Interpreter;
+    pub use crate::runtime_error::RuntimeError;
+    pub use crate::value::Value;
+}
+
+/// Turning source text into a syntax tree.
+pub mod frontend {
+    pub use crate::ast::{Expr, Identifier, Location, Program, Stmt};
+    pub use crate::diagnostic::{Diagnostic, Diagnostics, Severity};
+    pub use crate::parser::{parse, parse_expression};
+    pub use crate::scanner::{scan, tokens};
+    pub use crate::token::{Span, Token, TokenKind};
+}
+
+/// Running a syntax tree on either backend.
+pub mod runtime {
+    pub use crate::chunk::Function as CompiledFunction;
+    pub use crate::environment::Environment;
+    pub use crate::hooks::Hooks;
+    pub use crate::interpreter::{Global, Interpreter, OnExceed, OutputLimit};
+    pub use crate::runtime_error::{Frame, RuntimeError};
+    pub use crate::value::{
+        LoxClass, LoxFunction, LoxInstance, LoxMap, MapKey, NativeFunction, Value,
+    };
+    pub use crate::vm::Vm;
+}
+
+/// The modules behind the re-exports stay private; each of these fails to
+/// compile.
+///
+/// ```compile_fail
+/// use rslox::chunk;
+/// ```
+///
+/// ```compile_fail
+/// use rslox::compiler;
+/// ```
+///
+/// ```compile_fail
+/// use rslox::environment;
+/// ```
+///
+/// ```compile_fail
+/// use rslox::interner;
+/// ```
+///
+/// ```compile_fail
+/// use rslox::stdlib;
+/// ```
+///
+/// ```compile_fail
+/// use rslox::value;
+/// ```
+#[cfg(doctest)]
+pub struct InternalModulesArePrivate;
@@ -0,0 +1,966 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::ast::{
+    BinaryOperator, ClassDecl, Expr, ExprVisitor, Fixity, FunctionDecl, Identifier, Literal,
+    Location, LogicalOperator, Program, Segment, Stmt, StmtVisitor, UnaryOperator, UpdateOperator,
+};
+use crate::diagnostic::Diagnostic;
+use crate::token::Span;
+
+/// Looks for code that is valid but probably not what the author meant,
+/// returning one warning per finding in the order they were found.
+///
+/// `source` is the text `program` was parsed from; it is only used to turn
+/// line and column positions back into spans.
+pub fn lint(program: &Program, source: &str) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        source,
+        diagnostics: Vec::new(),
+        scopes: vec![HashMap::new()],
+        function_starts: vec![0],
+        closures: Vec::new(),
+        loops: Vec::new(),
+        maybe_nil: HashMap::new(),
+        escaped: HashSet::new(),
+        assigned_undeclared: HashSet::new(),
+    };
+    for stmt in program {
+        stmt.accept(&mut linter);
+    }
+    linter.diagnostics
+}
+
+struct Linter<'a> {
+    source: &'a str,
+    diagnostics: Vec<Diagnostic>,
+    // Block scopes from the globals inward, mapping each name to where it
+    // was declared.
+    scopes: Vec<HashMap<String, Identifier>>,
+    // The index into `scopes` where each enclosing function's scopes begin;
+    // the script itself is the first entry.
+    function_starts: Vec<usize>,
+    // The names of the functions being linted, innermost last. Lines up with
+    // `function_starts[1..]`.
+    closures: Vec<Identifier>,
+    loops: Vec<Loop>,
+    // Variables of the function being linted that may hold nil at this
+    // point, by where they were declared.
+    maybe_nil: NilState,
+    // Variables a closure assigns to. A call can change them, so they are
+    // not tracked.
+    escaped: HashSet<(usize, usize)>,
+    // Names assigned where no declaration of them was in scope, such as a
+    // global that a function sets before the script declares it.
+    assigned_undeclared: HashSet<String>,
+}
+
+type NilState = HashMap<(usize, usize), NilSource>;
+
+// Where a variable that may be nil got its value.
+#[derive(Clone)]
+struct NilSource {
+    name: Identifier,
+    label: String,
+}
+
+// The natives that return nil when they have nothing else to give.
+const NIL_NATIVES: &[&str] = &["readLine", "parseNumber"];
+
+struct Loop {
+    scope_depth: usize,
+    function_depth: usize,
+    // Variables from outside the loop that the loop assigns to, by where
+    // they were declared.
+    assigned: HashSet<(usize, usize)>,
+    // Uses of such variables from closures created inside the loop, as
+    // (closure, declaration, use).
+    captures: Vec<(Identifier, Identifier, Identifier)>,
+}
+
+fn position(name: &Identifier) -> (usize, usize) {
+    (name.line, name.column)
+}
+
+// Whether control never reaches the statement after `stmt`.
+fn diverges(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Return(_) | Stmt::Break | Stmt::Continue => true,
+        Stmt::Block(statements) => statements.iter().any(diverges),
+        Stmt::If(_, then_branch, Some(else_branch)) => {
+            diverges(then_branch) && diverges(else_branch)
+        }
+        _ => false,
+    }
+}
+
+impl Linter<'_> {
+    fn warn(&mut self, message: String, name: &Identifier, note: String) {
+        let span = self.span(name);
+        self.diagnostics
+            .push(Diagnostic::warning(message, span).with_note(note));
+    }
+
+    // Warns about `name`, which repeats the declaration of `original`.
+    fn duplicate(
+        &mut self,
+        message: String,
+        name: &Identifier,
+        original: &Identifier,
+        note: String,
+    ) {
+        let span = self.span(name);
+        let first = self.span(original);
+        self.diagnostics.push(
+            Diagnostic::warning(message, span)
+                .with_label(first, format!("the first '{}' is here", original.name))
+                .with_note(note),
+        );
+    }
+
+    // Identifier columns count characters, so walk the line to find bytes.
+    fn span(&self, name: &Identifier) -> Span {
+        let mut start = 0;
+        for line in self.source.split_inclusive('\n').take(name.line - 1) {
+            start += line.len();
+        }
+        let line = &self.source[start..];
+        start += line
+            .char_indices()
+            .nth(name.column - 1)
+            .map_or(line.len(), |(offset, _)| offset);
+        Span {
+            start,
+            end: start + name.name.len(),
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Identifier) {
+        // Globals are left alone: shadowing them in a block is common and
+        // rarely a mistake.
+        let function_start = (*self.function_starts.last().unwrap()).max(1);
+        let current = self.scopes.len() - 1;
+        if current > 0
+            && let Some(original) = self.scopes[current].get(&name.name).cloned()
+        {
+            self.duplicate(
+                format!(
+                    "lint: '{}' is already declared in this scope {}",
+                    name.name,
+                    name.location()
+                ),
+                name,
+                &original,
+                "the second declaration replaces the first; rename one of them, or assign instead of declaring again".to_string(),
+            );
+            self.scopes[current].insert(name.name.clone(), name.clone());
+            return;
+        }
+        let shadowed = (function_start..current)
+            .rev()
+            .find_map(|scope| self.scopes[scope].get(&name.name))
+            .cloned();
+        if let Some(shadowed) = shadowed {
+            self.warn(
+                format!(
+                    "lint: '{}' shadows a variable from an enclosing block {}",
+                    name.name,
+                    name.location()
+                ),
+                name,
+                format!(
+                    "the outer '{}' was declared {}; renaming one of them avoids confusing the two",
+                    shadowed.name,
+                    shadowed.location()
+                ),
+            );
+        }
+        self.scopes[current].insert(name.name.clone(), name.clone());
+    }
+
+    // The scope `name` refers to and its declaration there.
+    fn lookup(&self, name: &Identifier) -> Option<(usize, Identifier)> {
+        self.scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(scope, names)| names.get(&name.name).map(|decl| (scope, decl.clone())))
+    }
+
+    fn resolve(&mut self, name: &Identifier, is_assignment: bool) {
+        let Some((scope, declaration)) = self.lookup(name) else {
+            return;
+        };
+
+        let function_depth = self.function_starts.len();
+        for lp in &mut self.loops {
+            let loop_function_start = self.function_starts[lp.function_depth - 1].max(1);
+            if scope >= lp.scope_depth || scope < loop_function_start {
+                continue;
+            }
+            if is_assignment {
+                lp.assigned.insert(position(&declaration));
+            }
+            if function_depth > lp.function_depth {
+                let closure = self.closures[lp.function_depth - 1].clone();
+                lp.captures
+                    .push((closure, declaration.clone(), name.clone()));
+            }
+        }
+    }
+
+    // Where the variable `name` refers to was declared, if it belongs to the
+    // function being linted and nil is tracked for it.
+    fn tracked(&self, name: &Identifier) -> Option<(usize, usize)> {
+        let (scope, declaration) = self.lookup(name)?;
+        let declared = position(&declaration);
+        (scope >= *self.function_starts.last().unwrap() && !self.escaped.contains(&declared))
+            .then_some(declared)
+    }
+
+    fn may_be_nil(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Literal(Literal::Nil) => true,
+            Expr::Grouping(expr) | Expr::Assign(_, expr) => self.may_be_nil(expr),
+            Expr::Conditional(_, then_branch, else_branch) => {
+                self.may_be_nil(then_branch) || self.may_be_nil(else_branch)
+            }
+            Expr::Variable(name) => self
+                .tracked(name)
+                .is_some_and(|declared| self.maybe_nil.contains_key(&declared)),
+            Expr::Call(callee, ..) => match callee.as_ref() {
+                Expr::Variable(name) => {
+                    self.lookup(name).is_none() && NIL_NATIVES.contains(&name.name.as_str())
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn assign(&mut self, name: &Identifier, may_be_nil: bool) {
+        self.resolve(name, true);
+        match self.lookup(name) {
+            None => {
+                self.assigned_undeclared.insert(name.name.clone());
+            }
+            Some((scope, declaration)) if scope < *self.function_starts.last().unwrap() => {
+                self.escaped.insert(position(&declaration));
+            }
+            Some(_) => {
+                let label = may_be_nil.then(|| format!("'{}' may be given nil here", name.name));
+                self.set_nil(name, label);
+            }
+        }
+    }
+
+    // Records whether `name` may now be nil; `label` says why it may be.
+    fn set_nil(&mut self, name: &Identifier, label: Option<String>) {
+        let Some(declared) = self.tracked(name) else {
+            return;
+        };
+        match label {
+            Some(label) => {
+                let source = NilSource {
+                    name: name.clone(),
+                    label,
+                };
+                self.maybe_nil.insert(declared, source);
+            }
+            None => {
+                self.maybe_nil.remove(&declared);
+            }
+        }
+    }
+
+    // The tracked variables that can't be nil when `condition` is `when`.
+    fn nil_checks(&self, condition: &Expr, when: bool) -> Vec<(usize, usize)> {
+        match condition {
+            Expr::Grouping(expr) => self.nil_checks(expr, when),
+            Expr::Unary(UnaryOperator::Not, expr, ..) => self.nil_checks(expr, !when),
+            Expr::Variable(name) if when => self.tracked(name).into_iter().collect(),
+            Expr::Binary(
+                left,
+                op @ (BinaryOperator::Equal | BinaryOperator::NotEqual),
+                right,
+                ..,
+            ) => {
+                let name = match (left.as_ref(), right.as_ref()) {
+                    (Expr::Variable(name), Expr::Literal(Literal::Nil))
+                    | (Expr::Literal(Literal::Nil), Expr::Variable(name)) => name,
+                    _ => return Vec::new(),
+                };
+                match (*op == BinaryOperator::NotEqual) == when {
+                    true => self.tracked(name).into_iter().collect(),
+                    false => Vec::new(),
+                }
+            }
+            Expr::Logical(left, LogicalOperator::And, right) if when => {
+                let mut checks = self.nil_checks(left, true);
+                checks.extend(self.nil_checks(right, true));
+                checks
+            }
+            Expr::Logical(left, LogicalOperator::Or, right) if !when => {
+                let mut checks = self.nil_checks(left, false);
+                checks.extend(self.nil_checks(right, false));
+                checks
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn narrow(&mut self, condition: &Expr, when: bool) {
+        for declared in self.nil_checks(condition, when) {
+            self.maybe_nil.remove(&declared);
+        }
+    }
+
+    // Merges the state at the end of another path into this one.
+    fn join(&mut self, other: NilState) {
+        for (declared, source) in other {
+            self.maybe_nil.entry(declared).or_insert(source);
+        }
+    }
+
+    // Code that runs later, like a function body, starts knowing nothing
+    // about nil, and leaves the state as it was.
+    fn deferred(&mut self, lint: impl FnOnce(&mut Self)) {
+        let outer = std::mem::take(&mut self.maybe_nil);
+        lint(self);
+        self.maybe_nil = outer;
+        let escaped = &self.escaped;
+        self.maybe_nil
+            .retain(|declared, _| !escaped.contains(declared));
+    }
+
+    // Warns if `object` is a variable that may be nil, where `what` says how
+    // it is about to be used.
+    fn check_nil(&mut self, object: &Expr, what: impl FnOnce() -> String) {
+        let Expr::Variable(name) = object else {
+            return;
+        };
+        // Once a use has run, the variable is known not to be nil.
+        let Some(source) = self
+            .tracked(name)
+            .and_then(|declared| self.maybe_nil.remove(&declared))
+        else {
+            return;
+        };
+        let span = self.span(name);
+        let origin = self.span(&source.name);
+        self.diagnostics.push(
+            Diagnostic::warning(
+                format!(
+                    "lint: '{}' may be nil where {} {}",
+                    name.name,
+                    what(),
+                    name.location()
+                ),
+                span,
+            )
+            .with_label(origin, source.label)
+            .with_note(format!(
+                "check for nil first, for example with 'if ({} != nil)'",
+                name.name
+            )),
+        );
+    }
+
+    fn function(&mut self, declaration: &FunctionDecl) {
+        self.deferred(|linter| linter.function_body(declaration));
+    }
+
+    fn function_body(&mut self, declaration: &FunctionDecl) {
+        self.function_starts.push(self.scopes.len());
+        self.closures.push(declaration.name.clone());
+        self.begin_scope();
+        for param in &declaration.params {
+            self.declare(param);
+        }
+        for stmt in &declaration.body {
+            stmt.accept(self);
+        }
+        self.end_scope();
+        self.closures.pop();
+        self.function_starts.pop();
+    }
+
+    fn end_loop(&mut self) {
+        let lp = self.loops.pop().expect("loops are balanced");
+        let mut reported = HashSet::new();
+        for (closure, declaration, used) in lp.captures {
+            if !lp.assigned.contains(&position(&declaration))
+                || !reported.insert((position(&closure), position(&declaration)))
+            {
+                continue;
+            }
+            self.warn(
+                format!(
+                    "lint: '{}' captures loop variable '{}' {}",
+                    closure.name,
+                    used.name,
+                    used.location()
+                ),
+                &used,
+                format!(
+                    "every closure created by this loop shares one '{}', so each sees its latest value; copy it into a variable declared inside the loop body",
+                    used.name
+                ),
+            );
+        }
+    }
+}
+
+impl StmtVisitor<()> for Linter<'_> {
+    fn visit_expression(&mut self, expr: &Expr) {
+        expr.accept(self);
+    }
+
+    fn visit_print(&mut self, expr: &Expr) {
+        expr.accept(self);
+    }
+
+    fn visit_var(&mut self, name: &Identifier, initializer: Option<&Expr>) {
+        if let Some(initializer) = initializer {
+            initializer.accept(self);
+        }
+        let label = match initializer {
+            None => Some(format!("'{}' is declared without a value here", name.name)),
+            Some(value) => self
+                .may_be_nil(value)
+                .then(|| format!("'{}' may be given nil here", name.name)),
+        };
+        self.declare(name);
+        if !self.assigned_undeclared.contains(&name.name) {
+            self.set_nil(name, label);
+        }
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) {
+        self.begin_scope();
+        for stmt in statements {
+            stmt.accept(self);
+        }
+        self.end_scope();
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) {
+        condition.accept(self);
+        let before = self.maybe_nil.clone();
+        self.narrow(condition, true);
+        then_branch.accept(self);
+        let after_then = std::mem::replace(&mut self.maybe_nil, before);
+        self.narrow(condition, false);
+        if let Some(else_branch) = else_branch {
+            else_branch.accept(self);
+        }
+        // A branch that returns or leaves the loop does not reach the code
+        // after the if, so `if (a == nil) return;` leaves `a` checked.
+        match (diverges(then_branch), else_branch.is_some_and(diverges)) {
+            (true, true) => self.maybe_nil.clear(),
+            (true, false) => {}
+            (false, true) => self.maybe_nil = after_then,
+            (false, false) => self.join(after_then),
+        }
+    }
+
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt, increment: Option<&Expr>) {
+        self.loops.push(Loop {
+            scope_depth: self.scopes.len(),
+            function_depth: self.function_starts.len(),
+            assigned: HashSet::new(),
+            captures: Vec::new(),
+        });
+        condition.accept(self);
+        // A nil assigned late in the body is not carried back to its start.
+        let before = self.maybe_nil.clone();
+        self.narrow(condition, true);
+        body.accept(self);
+        if let Some(increment) = increment {
+            increment.accept(self);
+        }
+        self.join(before);
+        self.narrow(condition, false);
+        self.end_loop();
+    }
+
+    fn visit_break(&mut self) {}
+
+    fn visit_continue(&mut self) {}
+
+    fn visit_function(&mut self, declaration: &Rc<FunctionDecl>) {
+        self.declare(&declaration.name);
+        self.function(declaration);
+    }
+
+    fn visit_return(&mut self, value: Option<&Expr>) {
+        if let Some(value) = value {
+            value.accept(self);
+        }
+    }
+
+    fn visit_class(&mut self, declaration: &Rc<ClassDecl>) {
+        self.declare(&declaration.name);
+        if let Some(superclass) = &declaration.superclass {
+            self.resolve(superclass, false);
+        }
+        let mut fields: HashMap<&str, &Identifier> = HashMap::new();
+        for field in &declaration.fields {
+            // Initializers run when an instance is made, not here.
+            self.deferred(|linter| field.initializer.accept(linter));
+            if let Some(original) = fields.insert(&field.name.name, &field.name) {
+                self.duplicate(
+                    format!(
+                        "lint: class '{}' declares field '{}' twice {}",
+                        declaration.name.name,
+                        field.name.name,
+                        field.name.location()
+                    ),
+                    &field.name,
+                    original,
+                    "only the last initializer's value is kept".to_string(),
+                );
+            }
+        }
+        let mut methods: HashMap<&str, &Identifier> = HashMap::new();
+        for method in &declaration.methods {
+            if let Some(original) = methods.insert(&method.name.name, &method.name) {
+                self.duplicate(
+                    format!(
+                        "lint: class '{}' defines method '{}' twice {}",
+                        declaration.name.name,
+                        method.name.name,
+                        method.name.location()
+                    ),
+                    &method.name,
+                    original,
+                    "only the last definition is used".to_string(),
+                );
+            }
+            self.function(method);
+        }
+    }
+}
+
+impl ExprVisitor<()> for Linter<'_> {
+    fn visit_binary(
+        &mut self,
+        left: &Expr,
+        _op: BinaryOperator,
+        right: &Expr,
+        _location: Location,
+        _span: Span,
+    ) {
+        left.accept(self);
+        right.accept(self);
+    }
+
+    fn visit_logical(&mut self, left: &Expr, op: LogicalOperator, right: &Expr) {
+        left.accept(self);
+        let before = self.maybe_nil.clone();
+        self.narrow(left, op == LogicalOperator::And);
+        right.accept(self);
+        self.join(before);
+    }
+
+    fn visit_comma(&mut self, left: &Expr, right: &Expr) {
+        left.accept(self);
+        right.accept(self);
+    }
+
+    fn visit_conditional(&mut self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) {
+        condition.accept(self);
+        let before = self.maybe_nil.clone();
+        self.narrow(condition, true);
+        then_branch.accept(self);
+        let after_then = std::mem::replace(&mut self.maybe_nil, before);
+        self.narrow(condition, false);
+        else_branch.accept(self);
+        self.join(after_then);
+    }
+
+    fn visit_unary(&mut self, _op: UnaryOperator, right: &Expr, _location: Location, _span: Span) {
+        right.accept(self);
+    }
+
+    fn visit_grouping(&mut self, expr: &Expr) {
+        expr.accept(self);
+    }
+
+    fn visit_literal(&mut self, _literal: &Literal) {}
+
+    fn visit_interpolation(&mut self, segments: &[Segment]) {
+        for segment in segments {
+            if let Segment::Value(expr) = segment {
+                expr.accept(self);
+            }
+        }
+    }
+
+    fn visit_variable(&mut self, name: &Identifier) {
+        self.resolve(name, false);
+    }
+
+    fn visit_assign(&mut self, name: &Identifier, value: &Expr) {
+        value.accept(self);
+        let may_be_nil = self.may_be_nil(value);
+        self.assign(name, may_be_nil);
+    }
+
+    // The result of an operator is never nil.
+    fn visit_compound_assign(
+        &mut self,
+        target: &Expr,
+        _op: BinaryOperator,
+        value: &Expr,
+        _location: Location,
+        _span: Span,
+    ) {
+        match target {
+            Expr::Variable(name) => {
+                value.accept(self);
+                self.assign(name, false);
+            }
+            target => {
+                target.accept(self);
+                value.accept(self);
+            }
+        }
+    }
+
+    fn visit_update(
+        &mut self,
+        target: &Expr,
+        _op: UpdateOperator,
+        _fixity: Fixity,
+        _location: Location,
+        _span: Span,
+    ) {
+        match target {
+            Expr::Variable(name) => self.assign(name, false),
+            target => target.accept(self),
+        }
+    }
+
+    fn visit_call(&mut self, callee: &Expr, arguments: &[Expr], _location: Location, _span: Span) {
+        self.check_nil(callee, || "it is called".to_string());
+        callee.accept(self);
+        for argument in arguments {
+            argument.accept(self);
+        }
+    }
+
+    fn visit_get(&mut self, object: &Expr, name: &Identifier, _span: Span) {
+        self.check_nil(object, || format!("its property '{}' is used", name.name));
+        object.accept(self);
+    }
+
+    fn visit_set(&mut self, object: &Expr, name: &Identifier, value: &Expr, _span: Span) {
+        object.accept(self);
+        value.accept(self);
+        self.check_nil(object, || format!("its property '{}' is set", name.name));
+    }
+
+    fn visit_list(&mut self, elements: &[Expr], _location: Location) {
+        for element in elements {
+            element.accept(self);
+        }
+    }
+
+    fn visit_map(&mut self, entries: &[(Expr, Expr)], _location: Location) {
+        for (key, value) in entries {
+            key.accept(self);
+            value.accept(self);
+        }
+    }
+
+    fn visit_index(&mut self, object: &Expr, index: &Expr, _location: Location, _span: Span) {
+        object.accept(self);
+        index.accept(self);
+        self.check_nil(object, || "it is indexed".to_string());
+    }
+
+    fn visit_slice(
+        &mut self,
+        object: &Expr,
+        start: Option<&Expr>,
+        end: Option<&Expr>,
+        _location: Location,
+        _span: Span,
+    ) {
+        object.accept(self);
+        start
+            .into_iter()
+            .chain(end)
+            .for_each(|bound| bound.accept(self));
+        self.check_nil(object, || "it is sliced".to_string());
+    }
+
+    fn visit_set_index(
+        &mut self,
+        object: &Expr,
+        index: &Expr,
+        value: &Expr,
+        _location: Location,
+        _span: Span,
+    ) {
+        object.accept(self);
+        index.accept(self);
+        value.accept(self);
+        self.check_nil(object, || "it is indexed".to_string());
+    }
+
+    fn visit_this(&mut self, _location: Location) {}
+
+    fn visit_super(&mut self, _location: Location, _method: &Identifier) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Severity;
+    use crate::parser::parse;
+    use crate::scanner::scan;
+
+    fn warnings(source: &str) -> Vec<String> {
+        let program = parse(&scan(source).unwrap()).unwrap();
+        lint(&program, source)
+            .iter()
+            .inspect(|d| assert_eq!(d.severity, Severity::Warning))
+            .map(|d| d.message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn shadowing_in_the_same_function() {
+        let cases: &[(&str, &str, &[&str])] = &[
+            (
+                "nested loop reuses the loop variable",
+                "fun f() {\n  for (var i = 0; i < 2; i = i + 1) {\n    for (var i = 0; i < 2; i = i + 1) {}\n  }\n}",
+                &["lint: 'i' shadows a variable from an enclosing block at line 3, column 14"],
+            ),
+            (
+                "block shadows a parameter",
+                "fun f(a) { { var a = 1; } }",
+                &["lint: 'a' shadows a variable from an enclosing block at line 1, column 18"],
+            ),
+            (
+                "shadowing a global is allowed",
+                "var a = 1; { var a = 2; }",
+                &[],
+            ),
+            (
+                "a nested function starts afresh",
+                "fun f(a) { fun g() { var a = 1; } }",
+                &[],
+            ),
+            (
+                "sibling blocks do not shadow each other",
+                "{ var a = 1; } { var a = 2; }",
+                &[],
+            ),
+        ];
+        for (name, source, expected) in cases {
+            assert_eq!(warnings(source), *expected, "case: {}", name);
+        }
+    }
+
+    #[test]
+    fn closures_capturing_loop_variables() {
+        let cases: &[(&str, &str, &[&str])] = &[
+            (
+                "closure in a for loop",
+                "for (var i = 0; i < 3; i = i + 1) {\n  fun show() { print i; }\n}",
+                &["lint: 'show' captures loop variable 'i' at line 2, column 22"],
+            ),
+            (
+                "closure in a while loop over a counter",
+                "{\n  var n = 0;\n  while (n < 3) { fun f() { return n + n; } n = n + 1; }\n}",
+                &["lint: 'f' captures loop variable 'n' at line 3, column 36"],
+            ),
+            (
+                "copying into the loop body is fine",
+                "for (var i = 0; i < 3; i = i + 1) { var j = i; fun show() { print j; } }",
+                &[],
+            ),
+            (
+                "variables the loop never assigns are fine",
+                "{ var limit = 3; for (var i = 0; i < limit; i = i + 1) { fun f() { print limit; } } }",
+                &[],
+            ),
+        ];
+        for (name, source, expected) in cases {
+            assert_eq!(warnings(source), *expected, "case: {}", name);
+        }
+    }
+
+    #[test]
+    fn warnings_point_at_the_source() {
+        let source = "fun f(a) {\n  { var a = 1; }\n}";
+        let program = parse(&scan(source).unwrap()).unwrap();
+        let diagnostics = lint(&program, source);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            &source[diagnostics[0].span.start..diagnostics[0].span.end],
+            "a"
+        );
+        assert_eq!(diagnostics[0].span.start, 19);
+        assert_eq!(
+            diagnostics[0].note.as_deref(),
+            Some(
+                "the outer 'a' was declared at line 1, column 7; renaming one of them avoids confusing the two"
+            )
+        );
+    }
+
+    #[test]
+    fn duplicate_declarations() {
+        let cases: &[(&str, &str, &[&str])] = &[
+            (
+                "duplicate parameters",
+                "fun f(a, b, a) {}",
+                &["lint: 'a' is already declared in this scope at line 1, column 13"],
+            ),
+            (
+                "a local that repeats a parameter",
+                "fun f(a) { var a = 1; }",
+                &["lint: 'a' is already declared in this scope at line 1, column 16"],
+            ),
+            (
+                "redeclaring in a block",
+                "{ var a = 1;\n  fun a() {} }",
+                &["lint: 'a' is already declared in this scope at line 2, column 7"],
+            ),
+            (
+                "duplicate methods",
+                "class A { m() {} n() {} m() {} }",
+                &["lint: class 'A' defines method 'm' twice at line 1, column 25"],
+            ),
+            (
+                "duplicate fields",
+                "class A { x = 1; x = 2; }",
+                &["lint: class 'A' declares field 'x' twice at line 1, column 18"],
+            ),
+            (
+                "redeclaring a global is allowed",
+                "var a = 1; var a = 2;",
+                &[],
+            ),
+            (
+                "a method may share a name with a variable",
+                "{ var m = 1; class A { m() {} } }",
+                &[],
+            ),
+        ];
+        for (name, source, expected) in cases {
+            assert_eq!(warnings(source), *expected, "case: {}", name);
+        }
+    }
+
+    #[test]
+    fn duplicates_point_at_both_declarations() {
+        let source = "fun f(a, a) {}";
+        let program = parse(&scan(source).unwrap()).unwrap();
+        let diagnostics = lint(&program, source);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span.start, 9);
+        let labels: Vec<(usize, &str)> = diagnostics[0]
+            .labels
+            .iter()
+            .map(|label| (label.span.start, label.message.as_str()))
+            .collect();
+        assert_eq!(labels, [(6, "the first 'a' is here")]);
+    }
+
+    #[test]
+    fn possible_nil_dereferences() {
+        let cases: &[(&str, &str, &[&str])] = &[
+            (
+                "a variable declared without a value",
+                "var a; print a.b;",
+                &["lint: 'a' may be nil where its property 'b' is used at line 1, column 14"],
+            ),
+            (
+                "calling nil",
+                "var f = nil; f();",
+                &["lint: 'f' may be nil where it is called at line 1, column 14"],
+            ),
+            (
+                "a native that can return nil",
+                "var line = readLine(); print line[0];",
+                &["lint: 'line' may be nil where it is indexed at line 1, column 30"],
+            ),
+            (
+                "nil on one branch",
+                "fun f(c) { var a = A(); if (c) a = nil; a.b = 1; }",
+                &["lint: 'a' may be nil where its property 'b' is set at line 1, column 41"],
+            ),
+            (
+                "only the first use warns",
+                "var a; a.b(); a.c();",
+                &["lint: 'a' may be nil where its property 'b' is used at line 1, column 8"],
+            ),
+            (
+                "nil checks guard the use",
+                "var a; if (a != nil) a.b(); if (a) print a.c; print a and a.d; print a == nil ? 0 : a.e;",
+                &[],
+            ),
+            (
+                "an early return",
+                "fun f(a) { var b = a ? a : nil; if (!b) return; b.c(); }",
+                &[],
+            ),
+            (
+                "a loop until it is not nil",
+                "var line = readLine(); while (line == nil) line = readLine(); print line[0];",
+                &[],
+            ),
+            ("assigning a value", "var a; a = A(); a.b;", &[]),
+            (
+                "a closure may assign it",
+                "var a; fun init() { a = A(); } init(); a.b;",
+                &[],
+            ),
+            (
+                "a function that sets a global declared later",
+                "fun init() { a = A(); } var a; init(); a.b;",
+                &[],
+            ),
+            (
+                "closures are called later",
+                "var a; fun f() { return a.b; } class C { x = a.y; } a = A(); f();",
+                &[],
+            ),
+        ];
+        for (name, source, expected) in cases {
+            assert_eq!(warnings(source), *expected, "case: {}", name);
+        }
+    }
+
+    #[test]
+    fn nil_warnings_point_at_where_the_nil_came_from() {
+        let source = "var a;\na.b();";
+        let program = parse(&scan(source).unwrap()).unwrap();
+        let diagnostics = lint(&program, source);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span.start, 7);
+        let labels: Vec<(usize, &str)> = diagnostics[0]
+            .labels
+            .iter()
+            .map(|label| (label.span.start, label.message.as_str()))
+            .collect();
+        assert_eq!(labels, [(4, "'a' is declared without a value here")]);
+        assert_eq!(
+            diagnostics[0].note.as_deref(),
+            Some("check for nil first, for example with 'if (a != nil)'")
+        );
+    }
+}
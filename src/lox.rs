@@ -0,0 +1,95 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::interpreter::Interpreter;
+use crate::parser::{parse, parse_expression};
+use crate::scanner::scan;
+use crate::value::Value;
+
+/// The simplest way to embed Lox: one interpreter whose globals persist
+/// across every script and expression given to it.
+pub struct Lox {
+    interpreter: Interpreter,
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lox {
+    /// A `Lox` whose scripts print to stdout.
+    pub fn new() -> Self {
+        Self {
+            interpreter: Interpreter::new(),
+        }
+    }
+
+    pub fn with_output(out: impl Write + 'static) -> Self {
+        Self {
+            interpreter: Interpreter::with_output(out),
+        }
+    }
+
+    /// Scans, parses and runs `source`, flushing what it prints.
+    pub fn run_source(&mut self, source: &str) -> Result<()> {
+        let program = parse(&scan(source)?)?;
+        self.interpreter.interpret(&program)
+    }
+
+    pub fn run_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .with_context(|| format!("could not read '{}'", path.display()))?;
+        self.run_source(&source)
+    }
+
+    /// Evaluates `source`, which must be a single expression, and returns
+    /// its value.
+    pub fn eval_expr(&mut self, source: &str) -> Result<Value> {
+        let expr = parse_expression(&scan(source)?)?;
+        let value = self.interpreter.evaluate(&expr);
+        self.interpreter.flush()?;
+        value
+    }
+
+    /// The interpreter underneath, for defining natives, reading globals
+    /// and anything else the facade does not cover.
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_state_between_calls() {
+        let mut lox = Lox::with_output(std::io::sink());
+        lox.interpreter()
+            .define_native("twice", 1, |arguments| match arguments {
+                [Value::Number(n)] => Ok(Value::Number(n * 2)),
+                _ => anyhow::bail!("twice: expected a number"),
+            });
+        lox.run_source("var base = 20;").unwrap();
+        assert_eq!(lox.eval_expr("twice(base) + 2").unwrap(), Value::Number(42));
+        assert_eq!(lox.eval_expr("base = 1").unwrap(), Value::Number(1));
+        assert_eq!(lox.interpreter().get_global("base"), Some(Value::Number(1)));
+    }
+
+    #[test]
+    fn rejects_more_than_an_expression() {
+        let mut lox = Lox::with_output(std::io::sink());
+        assert_eq!(
+            lox.eval_expr("1 + 2; 3").unwrap_err().to_string(),
+            "parser: expected end of expression, found ';' at line 1, column 6"
+        );
+        assert!(lox.eval_expr("missing").is_err());
+        assert!(lox.run_file("does/not/exist.lox").is_err());
+    }
+}
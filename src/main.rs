@@ -1,16 +1,314 @@
-use std::fs;
+use std::io::{self, BufRead, Write};
+use std::{env, fs, process};
 
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
 
-mod scanner;
-mod token;
+use rslox::ast::Stmt;
+use rslox::ast_printer::{self, Style};
+use rslox::diagnostic::{self, Diagnostic, Diagnostics};
+use rslox::runtime_error::RuntimeError;
+use rslox::{
+    audit, bench, disassembler, explain, grammar, hooks, interpreter, lint, minify, parser,
+    profile, scanner, token_diff, vm,
+};
+
+const USAGE: &str = "usage:
+    rslox                          start a REPL
+    rslox run <file> [flags]       run a script
+        --trace                    log each statement and call to stderr
+        --profile-summary          print calls and time per function to stderr
+                                   at exit
+        --unbuffered               write each print as soon as it runs
+        --max-output=<bytes>       fail once prints would go over this many
+                                   bytes
+        --backend=<tree|vm>        run with the tree-walking interpreter
+                                   (the default) or the bytecode VM
+        --dump-ast[=tree]          print the syntax tree before running
+        --error-format=<plain|rich|json|github>
+                                   how to print syntax errors and warnings
+    rslox tokens <file>            print the tokens of a script
+    rslox tokens --diff <old> <new>
+                                   print the lexical changes between two scripts
+    rslox ast <file> [--tree]      print the syntax tree of a script
+    rslox explain-run <file> [--format=json|html]
+                                   record each step of a run, with the variables
+                                   in scope and the calls in progress
+    rslox disasm <file>            print the bytecode the vm would run for a script
+    rslox audit <file>             list the capabilities a script's natives need
+    rslox minify <file>            print a script without comments or whitespace
+                                   and with short local variable names
+    rslox grammar [--format=ebnf|railroad-html]
+                                   print the grammar this build accepts
+    rslox bench <dir> [flags]      time every .lox file in a directory on each backend
+        --runs=<n>                 timed runs per program (default 10)
+        --warmup=<n>               untimed runs first (default 2)
+        --json                     print the results as JSON";
 
 fn main() -> Result<()> {
-    let input = fs::read_to_string("examples/simple.lox")?;
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        [] => repl(),
+        ["run", path, flags @ ..] => run(path, flags),
+        ["tokens", "--diff", old, new] => diff_tokens(old, new),
+        ["tokens", path] => print_tokens(path),
+        ["ast", path] => print_ast(path, Style::SExpr),
+        ["ast", path, "--tree"] => print_ast(path, Style::Tree),
+        ["explain-run", path] => explain_run(path, "json"),
+        ["explain-run", path, format] => match format.strip_prefix("--format=") {
+            Some(format) => explain_run(path, format),
+            None => bail!(USAGE),
+        },
+        ["disasm", path] => disassemble(path),
+        ["minify", path] => print_minified(path),
+        ["audit", path] => print_audit(path),
+        ["bench", dir, flags @ ..] => run_bench(dir, flags),
+        ["grammar"] => print_grammar("ebnf"),
+        ["grammar", format] => match format.strip_prefix("--format=") {
+            Some(format) => print_grammar(format),
+            None => bail!(USAGE),
+        },
+        _ => bail!(USAGE),
+    }
+}
+
+fn read_source(path: &str) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("could not read '{}'", path))
+}
+
+fn run(path: &str, flags: &[&str]) -> Result<()> {
+    let mut use_vm = false;
+    let mut trace = false;
+    let mut profile = false;
+    let mut unbuffered = false;
+    let mut max_output = None;
+    let mut dump_ast = None;
+    let mut renderer = diagnostic::renderer("plain").expect("plain is a format");
+    for flag in flags {
+        if let Some(format) = flag.strip_prefix("--error-format=") {
+            renderer = diagnostic::renderer(format)
+                .with_context(|| format!("unknown error format '{}'\n{}", format, USAGE))?;
+            continue;
+        }
+        if let Some(bytes) = flag.strip_prefix("--max-output=") {
+            let bytes = bytes.parse().with_context(|| {
+                format!("--max-output expects a number of bytes, found '{}'", bytes)
+            })?;
+            max_output = Some(bytes);
+            continue;
+        }
+        match *flag {
+            "--trace" => trace = true,
+            "--profile-summary" => profile = true,
+            "--unbuffered" => unbuffered = true,
+            "--dump-ast" => dump_ast = Some(Style::SExpr),
+            "--dump-ast=tree" => dump_ast = Some(Style::Tree),
+            "--backend=tree" => use_vm = false,
+            "--backend=vm" => use_vm = true,
+            _ => bail!("unknown flag '{}'\n{}", flag, USAGE),
+        }
+    }
+    if use_vm && (trace || unbuffered || profile || max_output.is_some()) {
+        bail!(
+            "--trace, --unbuffered, --max-output and --profile-summary are not supported by the vm backend yet"
+        );
+    }
+    if trace && profile {
+        bail!("--trace and --profile-summary can't be used together");
+    }
+
+    let source = read_source(path)?;
+    let program = match scanner::scan(&source).and_then(|tokens| parser::parse(&tokens)) {
+        Ok(program) => program,
+        Err(error) => match error.downcast_ref::<Diagnostics>() {
+            Some(Diagnostics(errors)) => {
+                eprint!("{}", renderer.render(errors, path, &source));
+                process::exit(65);
+            }
+            None => return Err(error),
+        },
+    };
+    if let Some(style) = dump_ast {
+        print!("{}", ast_printer::print(&program, style));
+    }
+    let warnings = lint::lint(&program, &source);
+    if !warnings.is_empty() {
+        eprint!("{}", renderer.render(&warnings, path, &source));
+    }
+    if use_vm {
+        return vm::Vm::new().interpret(&program);
+    }
+
+    let mut interpreter = interpreter::Interpreter::new();
+    if trace {
+        interpreter.set_hooks(hooks::Tracer);
+    }
+    let profiler = profile::Profiler::default();
+    if profile {
+        interpreter.set_hooks(profiler.clone());
+    }
+    interpreter.set_buffered(!unbuffered)?;
+    interpreter.set_output_limit(interpreter::OutputLimit {
+        total_bytes: max_output,
+        line_bytes: None,
+        on_exceed: interpreter::OnExceed::Error,
+    });
+    let result = interpreter.interpret(&program);
+    if profile {
+        eprint!("{}", profile::to_table(&profiler.summary()));
+    }
+    // Errors that know which part of a line they are about are shown like
+    // syntax errors, so that a rich or GitHub report can point at it.
+    if let Err(error) = &result
+        && let Some(error) = error.downcast_ref::<RuntimeError>()
+        && let Some(span) = error.span
+    {
+        let errors = [Diagnostic::error(error.to_string(), span)];
+        eprint!("{}", renderer.render(&errors, path, &source));
+        process::exit(70);
+    }
+    result
+}
+
+fn explain_run(path: &str, format: &str) -> Result<()> {
+    let source = read_source(path)?;
+    let program = parser::parse(&scanner::scan(&source)?)?;
+    let trace = explain::explain(&program);
+    match format {
+        "json" => print!("{}", trace.to_json()),
+        "html" => print!("{}", trace.to_html()),
+        _ => bail!("unknown format '{}'\n{}", format, USAGE),
+    }
+    Ok(())
+}
 
-    let tokens = scanner::scan(&input)?;
-    for t in tokens {
-        println!("{:?}", t);
+fn disassemble(path: &str) -> Result<()> {
+    let source = read_source(path)?;
+    let program = parser::parse(&scanner::scan(&source)?)?;
+    print!("{}", disassembler::disassemble_program(&program)?);
+    Ok(())
+}
+
+fn print_minified(path: &str) -> Result<()> {
+    let source = read_source(path)?;
+    let program = parser::parse(&scanner::scan(&source)?)?;
+    println!("{}", minify::minify(&program));
+    Ok(())
+}
+
+fn print_audit(path: &str) -> Result<()> {
+    let source = read_source(path)?;
+    let uses = audit::audit(&parser::parse(&scanner::scan(&source)?)?);
+    if uses.is_empty() {
+        println!("no capabilities needed");
+    }
+    for capability in audit::capabilities(&uses) {
+        println!("{}", capability);
+        for u in uses.iter().filter(|u| u.capability == capability) {
+            println!("    {} {}", u.native.name, u.native.location());
+        }
+    }
+    Ok(())
+}
+
+fn print_grammar(format: &str) -> Result<()> {
+    match format {
+        "ebnf" => print!("{}", grammar::to_ebnf(parser::GRAMMAR)),
+        "railroad-html" => print!("{}", grammar::to_railroad_html(parser::GRAMMAR)),
+        _ => bail!("unknown format '{}'\n{}", format, USAGE),
+    }
+    Ok(())
+}
+
+fn run_bench(dir: &str, flags: &[&str]) -> Result<()> {
+    let mut options = bench::Options::default();
+    let mut json = false;
+    for flag in flags {
+        let count = |value: &str| {
+            value
+                .parse()
+                .with_context(|| format!("'{}' is not a count\n{}", flag, USAGE))
+        };
+        match flag.split_once('=') {
+            Some(("--runs", value)) => options.runs = count(value)?,
+            Some(("--warmup", value)) => options.warmup = count(value)?,
+            None if *flag == "--json" => json = true,
+            _ => bail!("unknown flag '{}'\n{}", flag, USAGE),
+        }
+    }
+
+    let measurements = bench::run_dir(dir.as_ref(), options)?;
+    if json {
+        print!("{}", bench::to_json(&measurements));
+    } else {
+        print!("{}", bench::to_table(&measurements));
+    }
+    Ok(())
+}
+
+fn print_tokens(path: &str) -> Result<()> {
+    let source = read_source(path)?;
+    for token in scanner::scan(&source)? {
+        println!(
+            "{}:{} {:?} {:?}",
+            token.line, token.column, token.kind, token.lexeme
+        );
+    }
+    Ok(())
+}
+
+fn print_ast(path: &str, style: Style) -> Result<()> {
+    let source = read_source(path)?;
+    let program = parser::parse(&scanner::scan(&source)?)?;
+    print!("{}", ast_printer::print(&program, style));
+    Ok(())
+}
+
+// Reads one line at a time, keeping globals between lines. A line that is a
+// lone expression statement has its value printed. Output is flushed after
+// every line.
+fn repl() -> Result<()> {
+    let mut interpreter = interpreter::Interpreter::new();
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let Some(line) = lines.next() else {
+            println!();
+            return Ok(());
+        };
+        if let Err(error) = eval_line(&mut interpreter, &line?) {
+            eprintln!("{}", error);
+        }
+    }
+}
+
+fn eval_line(interpreter: &mut interpreter::Interpreter, line: &str) -> Result<()> {
+    let program = parser::parse(&scanner::scan(line)?)?;
+    if let [Stmt::Expression(expr)] = program.as_slice() {
+        let value = interpreter.evaluate(expr);
+        interpreter.flush()?;
+        println!("{}", value?);
+        return Ok(());
+    }
+    interpreter.interpret(&program)
+}
+
+fn diff_tokens(old_path: &str, new_path: &str) -> Result<()> {
+    let old_source = read_source(old_path)?;
+    let new_source = read_source(new_path)?;
+    let old = scanner::scan(&old_source)?;
+    let new = scanner::scan(&new_source)?;
+
+    let changes: Vec<_> = token_diff::diff(&old, &new)
+        .into_iter()
+        .filter(|c| c.is_lexical())
+        .collect();
+    if changes.is_empty() {
+        println!("no lexical changes");
+    }
+    for change in changes {
+        println!("{}: {}", change, change.token().lexeme);
     }
 
     Ok(())
@@ -0,0 +1,749 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::rc::Rc;
+
+use crate::ast::{
+    BinaryOperator, ClassDecl, Expr, ExprVisitor, Fixity, FunctionDecl, Identifier, Literal,
+    Location, LogicalOperator, Program, Segment, Stmt, StmtVisitor, UnaryOperator, UpdateOperator,
+};
+use crate::interpreter::Interpreter;
+use crate::scanner::KEYWORDS;
+use crate::token::Span;
+
+/// Prints `program` back as Lox source with no comments and as little
+/// whitespace as possible, and with local variables and parameters given
+/// short names.
+///
+/// Variables are looked up by name when the program runs, so renaming is
+/// done per name rather than per declaration: a name is replaced
+/// everywhere or nowhere. Names that are observable from outside are
+/// kept: globals, built-ins, properties, and the names of functions and
+/// classes, which show when they are printed. Only error messages that
+/// mention a renamed variable differ from the original's.
+pub fn minify(program: &Program) -> String {
+    let mut names = Names::default();
+    for stmt in program {
+        names.top_level(stmt);
+    }
+    let mut minifier = Minifier {
+        renames: names.renames(),
+        out: String::new(),
+    };
+    for stmt in program {
+        stmt.accept(&mut minifier);
+    }
+    minifier.out
+}
+
+// Sorts every name in the program into those that may be renamed and those
+// that must be kept.
+#[derive(Default)]
+struct Names {
+    used: HashSet<String>,
+    locals: Vec<String>,
+    kept: HashSet<String>,
+}
+
+impl Names {
+    fn top_level(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Var(name, _) => self.keep(name),
+            Stmt::Function(declaration) => self.keep(&declaration.name),
+            Stmt::Class(declaration) => self.keep(&declaration.name),
+            _ => {}
+        }
+        self.stmt(stmt);
+    }
+
+    fn keep(&mut self, name: &Identifier) {
+        self.used.insert(name.name.clone());
+        self.kept.insert(name.name.clone());
+    }
+
+    fn local(&mut self, name: &Identifier) {
+        self.used.insert(name.name.clone());
+        if !self.locals.contains(&name.name) {
+            self.locals.push(name.name.clone());
+        }
+    }
+
+    fn function(&mut self, declaration: &FunctionDecl) {
+        for param in &declaration.params {
+            self.local(param);
+        }
+        for stmt in &declaration.body {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(expr) | Stmt::Print(expr) => self.expr(expr),
+            Stmt::Var(name, initializer) => {
+                self.local(name);
+                if let Some(initializer) = initializer {
+                    self.expr(initializer);
+                }
+            }
+            Stmt::Block(statements) => statements.iter().for_each(|s| self.stmt(s)),
+            Stmt::If(condition, then_branch, else_branch) => {
+                self.expr(condition);
+                self.stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::While(condition, body, increment) => {
+                self.expr(condition);
+                self.stmt(body);
+                if let Some(increment) = increment {
+                    self.expr(increment);
+                }
+            }
+            Stmt::Break | Stmt::Continue => {}
+            Stmt::Function(declaration) => {
+                self.keep(&declaration.name);
+                self.function(declaration);
+            }
+            Stmt::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            Stmt::Class(declaration) => {
+                self.keep(&declaration.name);
+                if let Some(superclass) = &declaration.superclass {
+                    self.used.insert(superclass.name.clone());
+                }
+                for field in &declaration.fields {
+                    self.expr(&field.initializer);
+                }
+                for method in &declaration.methods {
+                    self.function(method);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary(left, _, right, ..)
+            | Expr::Logical(left, _, right)
+            | Expr::Comma(left, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Conditional(condition, then_branch, else_branch) => {
+                self.expr(condition);
+                self.expr(then_branch);
+                self.expr(else_branch);
+            }
+            Expr::Unary(_, expr, ..) | Expr::Grouping(expr) | Expr::Update(expr, ..) => {
+                self.expr(expr)
+            }
+            Expr::Literal(_) | Expr::This(_) | Expr::Super(..) => {}
+            Expr::Interpolation(segments) => {
+                for segment in segments {
+                    if let Segment::Value(expr) = segment {
+                        self.expr(expr);
+                    }
+                }
+            }
+            Expr::Variable(name) => {
+                self.used.insert(name.name.clone());
+            }
+            Expr::Assign(name, value) => {
+                self.used.insert(name.name.clone());
+                self.expr(value);
+            }
+            Expr::CompoundAssign(target, _, value, ..) => {
+                self.expr(target);
+                self.expr(value);
+            }
+            Expr::Call(callee, arguments, ..) => {
+                self.expr(callee);
+                arguments.iter().for_each(|a| self.expr(a));
+            }
+            Expr::Get(object, ..) => self.expr(object),
+            Expr::Set(object, _, value, _) => {
+                self.expr(object);
+                self.expr(value);
+            }
+            Expr::List(elements, _) => elements.iter().for_each(|e| self.expr(e)),
+            Expr::Map(entries, _) => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            Expr::Index(object, index, ..) => {
+                self.expr(object);
+                self.expr(index);
+            }
+            Expr::Slice(object, start, end, ..) => {
+                self.expr(object);
+                start.iter().chain(end).for_each(|bound| self.expr(bound));
+            }
+            Expr::SetIndex(object, index, value, ..) => {
+                self.expr(object);
+                self.expr(index);
+                self.expr(value);
+            }
+        }
+    }
+
+    // Short replacements for every renamable local, in the order they were
+    // first declared, avoiding every name that is kept or already in use.
+    fn renames(mut self) -> HashMap<String, String> {
+        // A local named like a built-in shares its name with the calls to
+        // the built-in, so renaming it would rename those too.
+        let builtins = Interpreter::with_output(io::sink()).globals();
+        self.kept
+            .extend(builtins.into_iter().map(|global| global.name));
+
+        let mut reserved: HashSet<String> = self.used;
+        reserved.extend(KEYWORDS.iter().map(|(keyword, _)| keyword.to_string()));
+        reserved.extend(["this", "super", "init"].map(String::from));
+        reserved.extend(self.kept.iter().cloned());
+
+        let mut fresh = (0..).map(short_name).filter(|n| !reserved.contains(n));
+        self.locals
+            .into_iter()
+            .filter(|name| !self.kept.contains(name))
+            .filter_map(|name| {
+                let short = fresh.next()?;
+                (short.len() < name.len()).then_some((name, short))
+            })
+            .collect()
+    }
+}
+
+// a, b, ..., z, aa, ab, ...
+fn short_name(mut n: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'a' + (n % 26) as u8);
+        if n < 26 {
+            break;
+        }
+        n = n / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap()
+}
+
+// `s` as it is written inside quotes. The only escape is `\${`, for a `${`
+// that does not start an interpolation.
+fn escape(s: &str) -> String {
+    s.replace("${", "\\${")
+}
+
+struct Minifier {
+    renames: HashMap<String, String>,
+    out: String,
+}
+
+impl Minifier {
+    // Appends `text`, with a space first only if it would otherwise run
+    // into the previous token.
+    fn token(&mut self, text: &str) {
+        let word = |c: char| c.is_alphanumeric() || c == '_';
+        let operator = |c: char| "!=<>-+*/".contains(c);
+        if let (Some(last), Some(first)) = (self.out.chars().last(), text.chars().next())
+            && ((word(last) && word(first)) || (operator(last) && operator(first)))
+        {
+            self.out.push(' ');
+        }
+        self.out.push_str(text);
+    }
+
+    fn name(&mut self, name: &Identifier) {
+        let renamed = self.renames.get(&name.name).unwrap_or(&name.name).clone();
+        self.token(&renamed);
+    }
+
+    fn comma_separated<T>(&mut self, items: &[T], mut each: impl FnMut(&mut Self, &T)) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.token(",");
+            }
+            each(self, item);
+        }
+    }
+
+    fn function(&mut self, declaration: &FunctionDecl) {
+        self.token(&declaration.name.name);
+        self.token("(");
+        self.comma_separated(&declaration.params, |m, param| m.name(param));
+        self.token(")");
+        self.visit_block(&declaration.body);
+    }
+}
+
+impl StmtVisitor<()> for Minifier {
+    fn visit_expression(&mut self, expr: &Expr) {
+        expr.accept(self);
+        self.token(";");
+    }
+
+    fn visit_print(&mut self, expr: &Expr) {
+        self.token("print");
+        expr.accept(self);
+        self.token(";");
+    }
+
+    fn visit_var(&mut self, name: &Identifier, initializer: Option<&Expr>) {
+        self.token("var");
+        self.name(name);
+        if let Some(initializer) = initializer {
+            self.token("=");
+            initializer.accept(self);
+        }
+        self.token(";");
+    }
+
+    fn visit_block(&mut self, statements: &[Stmt]) {
+        self.token("{");
+        for stmt in statements {
+            stmt.accept(self);
+        }
+        self.token("}");
+    }
+
+    fn visit_if(&mut self, condition: &Expr, then_branch: &Stmt, else_branch: Option<&Stmt>) {
+        self.token("if");
+        self.token("(");
+        condition.accept(self);
+        self.token(")");
+        then_branch.accept(self);
+        if let Some(else_branch) = else_branch {
+            self.token("else");
+            else_branch.accept(self);
+        }
+    }
+
+    // A loop with an increment is written back as a `for`, the only syntax
+    // that keeps the increment running after a `continue`.
+    fn visit_while(&mut self, condition: &Expr, body: &Stmt, increment: Option<&Expr>) {
+        match increment {
+            Some(increment) => {
+                self.token("for");
+                self.token("(");
+                self.token(";");
+                condition.accept(self);
+                self.token(";");
+                increment.accept(self);
+            }
+            None => {
+                self.token("while");
+                self.token("(");
+                condition.accept(self);
+            }
+        }
+        self.token(")");
+        body.accept(self);
+    }
+
+    fn visit_break(&mut self) {
+        self.token("break");
+        self.token(";");
+    }
+
+    fn visit_continue(&mut self) {
+        self.token("continue");
+        self.token(";");
+    }
+
+    fn visit_function(&mut self, declaration: &Rc<FunctionDecl>) {
+        self.token("fun");
+        self.function(declaration);
+    }
+
+    fn visit_return(&mut self, value: Option<&Expr>) {
+        self.token("return");
+        if let Some(value) = value {
+            value.accept(self);
+        }
+        self.token(";");
+    }
+
+    fn visit_class(&mut self, declaration: &Rc<ClassDecl>) {
+        self.token("class");
+        self.token(&declaration.name.name);
+        if let Some(superclass) = &declaration.superclass {
+            self.token("<");
+            self.token(&superclass.name);
+        }
+        self.token("{");
+        for field in &declaration.fields {
+            self.token(&field.name.name);
+            self.token("=");
+            field.initializer.accept(self);
+            self.token(";");
+        }
+        for method in &declaration.methods {
+            self.function(method);
+        }
+        self.token("}");
+    }
+}
+
+impl ExprVisitor<()> for Minifier {
+    fn visit_binary(
+        &mut self,
+        left: &Expr,
+        op: BinaryOperator,
+        right: &Expr,
+        _location: Location,
+        _span: Span,
+    ) {
+        left.accept(self);
+        self.token(&op.to_string());
+        right.accept(self);
+    }
+
+    fn visit_logical(&mut self, left: &Expr, op: LogicalOperator, right: &Expr) {
+        left.accept(self);
+        self.token(&op.to_string());
+        right.accept(self);
+    }
+
+    fn visit_comma(&mut self, left: &Expr, right: &Expr) {
+        left.accept(self);
+        self.token(",");
+        right.accept(self);
+    }
+
+    fn visit_conditional(&mut self, condition: &Expr, then_branch: &Expr, else_branch: &Expr) {
+        condition.accept(self);
+        self.token("?");
+        then_branch.accept(self);
+        self.token(":");
+        else_branch.accept(self);
+    }
+
+    fn visit_unary(&mut self, op: UnaryOperator, right: &Expr, _location: Location, _span: Span) {
+        self.token(&op.to_string());
+        right.accept(self);
+    }
+
+    fn visit_grouping(&mut self, expr: &Expr) {
+        self.token("(");
+        expr.accept(self);
+        self.token(")");
+    }
+
+    fn visit_literal(&mut self, literal: &Literal) {
+        match literal {
+            Literal::String(s) => self.token(&format!("\"{}\"", escape(s))),
+            literal => self.token(&literal.to_string()),
+        }
+    }
+
+    fn visit_interpolation(&mut self, segments: &[Segment]) {
+        let mut text = "\"".to_string();
+        for segment in segments {
+            match segment {
+                Segment::Text(s) => text += &escape(s),
+                Segment::Value(expr) => {
+                    self.token(&text);
+                    self.token("${");
+                    expr.accept(self);
+                    text = "}".to_string();
+                }
+            }
+        }
+        self.token(&(text + "\""));
+    }
+
+    fn visit_variable(&mut self, name: &Identifier) {
+        self.name(name);
+    }
+
+    fn visit_assign(&mut self, name: &Identifier, value: &Expr) {
+        self.name(name);
+        self.token("=");
+        value.accept(self);
+    }
+
+    fn visit_compound_assign(
+        &mut self,
+        target: &Expr,
+        op: BinaryOperator,
+        value: &Expr,
+        _location: Location,
+        _span: Span,
+    ) {
+        target.accept(self);
+        self.token(&format!("{}=", op));
+        value.accept(self);
+    }
+
+    fn visit_update(
+        &mut self,
+        target: &Expr,
+        op: UpdateOperator,
+        fixity: Fixity,
+        _location: Location,
+        _span: Span,
+    ) {
+        match fixity {
+            Fixity::Prefix => {
+                self.token(&op.to_string());
+                target.accept(self);
+            }
+            Fixity::Postfix => {
+                target.accept(self);
+                self.token(&op.to_string());
+            }
+        }
+    }
+
+    fn visit_call(&mut self, callee: &Expr, arguments: &[Expr], _location: Location, _span: Span) {
+        callee.accept(self);
+        self.token("(");
+        self.comma_separated(arguments, |m, argument| argument.accept(m));
+        self.token(")");
+    }
+
+    fn visit_get(&mut self, object: &Expr, name: &Identifier, _span: Span) {
+        object.accept(self);
+        self.token(".");
+        self.token(&name.name);
+    }
+
+    fn visit_set(&mut self, object: &Expr, name: &Identifier, value: &Expr, _span: Span) {
+        object.accept(self);
+        self.token(".");
+        self.token(&name.name);
+        self.token("=");
+        value.accept(self);
+    }
+
+    fn visit_list(&mut self, elements: &[Expr], _location: Location) {
+        self.token("[");
+        self.comma_separated(elements, |m, element| element.accept(m));
+        self.token("]");
+    }
+
+    fn visit_map(&mut self, entries: &[(Expr, Expr)], _location: Location) {
+        self.token("{");
+        self.comma_separated(entries, |m, (key, value)| {
+            key.accept(m);
+            m.token(":");
+            value.accept(m);
+        });
+        self.token("}");
+    }
+
+    fn visit_index(&mut self, object: &Expr, index: &Expr, _location: Location, _span: Span) {
+        object.accept(self);
+        self.token("[");
+        index.accept(self);
+        self.token("]");
+    }
+
+    fn visit_slice(
+        &mut self,
+        object: &Expr,
+        start: Option<&Expr>,
+        end: Option<&Expr>,
+        _location: Location,
+        _span: Span,
+    ) {
+        object.accept(self);
+        self.token("[");
+        if let Some(start) = start {
+            start.accept(self);
+        }
+        self.token(":");
+        if let Some(end) = end {
+            end.accept(self);
+        }
+        self.token("]");
+    }
+
+    fn visit_set_index(
+        &mut self,
+        object: &Expr,
+        index: &Expr,
+        value: &Expr,
+        _location: Location,
+        _span: Span,
+    ) {
+        object.accept(self);
+        self.token("[");
+        index.accept(self);
+        self.token("]");
+        self.token("=");
+        value.accept(self);
+    }
+
+    fn visit_this(&mut self, _location: Location) {
+        self.token("this");
+    }
+
+    fn visit_super(&mut self, _location: Location, method: &Identifier) {
+        self.token("super");
+        self.token(".");
+        self.token(&method.name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::Write;
+
+    use anyhow::Result;
+
+    use super::*;
+    use crate::parser::parse;
+    use crate::scanner::scan;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run(source: &str) -> Result<String> {
+        let buffer = SharedBuffer::default();
+        let program = parse(&scan(source)?)?;
+        Interpreter::with_output(buffer.clone()).interpret(&program)?;
+        let output = buffer.0.borrow().clone();
+        Ok(String::from_utf8(output)?)
+    }
+
+    fn minify_source(source: &str) -> String {
+        minify(&parse(&scan(source).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn strips_whitespace_and_renames_locals() {
+        let source = "// add two numbers\nfun add(first, second) {\n  var total = first + second;\n  return total;\n}\nprint add(1, -2) - -3;";
+        assert_eq!(
+            minify_source(source),
+            "fun add(a,b){var c=a+b;return c;}print add(1,-2)- -3;"
+        );
+    }
+
+    #[test]
+    fn keeps_names_that_show_from_outside() {
+        let source = "var count = 1;\nclass Point { init(xs) { this.xs = xs; } }\n{ fun helper(count) { return count; } print helper(Point(2).xs); }";
+        assert_eq!(
+            minify_source(source),
+            "var count=1;class Point{init(a){this.xs=a;}}{fun helper(count){return count;}print helper(Point(2).xs);}"
+        );
+    }
+
+    // The minified program must run exactly like the original.
+    #[test]
+    fn behaves_like_the_original() {
+        let cases = [
+            (
+                "loops and closures",
+                "fun counter() { var count = 0; fun next() { count = count + 1; return count; } return next; }\nvar c = counter(); c(); print c();\nfor (var index = 0; index < 3; index = index + 1) print index;",
+            ),
+            (
+                "lists",
+                "{ var items = [1, [2, 3]]; items[1][0] = items[0] + 10; push(items, -1); print items; print len(items); }",
+            ),
+            (
+                "class fields",
+                "class Point { x = 0; y = this.x + 1; init(scale) { this.x = this.y * scale; } }\nvar point = Point(3); print point.x; print point.y;",
+            ),
+            (
+                "strings and slices",
+                "{ var word = \"hello\"; var letters = [word[0], word[1:3], word[:1], word[4:]]; print letters[1:]; }",
+            ),
+            (
+                "maps",
+                "{ var counts = {\"a\": 1, 2: [3]}; counts[\"a\"] = counts[\"a\"] + 1; print counts; print keys(counts); }",
+            ),
+            (
+                "break and continue",
+                "for (var index = 0; index < 6; index = index + 1) { if (index == 1) continue; if (index == 4) break; print index; }",
+            ),
+            (
+                "shadowing",
+                "var value = \"global\"; { var value = \"outer\"; { var value = \"inner\"; print value; } print value; } print value;",
+            ),
+            (
+                "redeclaring in the same scope",
+                "{ var later = 1; fun show() { print later; } var later = 2; show(); }",
+            ),
+            (
+                "a closure that sees a variable declared after it",
+                "{ fun show() { print after; } var after = \"late\"; show(); }",
+            ),
+            (
+                "classes",
+                "class A { init(name) { this.name = name; } greet(greeting) { return greeting + \" \" + this.name; } }\nclass B < A { greet(greeting) { return super.greet(greeting) + \"!\"; } }\nprint B(\"lox\").greet(\"hi\");",
+            ),
+            (
+                "operators that would run together",
+                "var a = 1; print - -a; print !!true; print 1 - -1; print (1 + 2) * 3; print 7 % -3 * -2 ** 2;",
+            ),
+            (
+                "increment and decrement",
+                "{ var count = 1; print count++ - -count; print - --count; print count++ ** 2; print count; }",
+            ),
+            (
+                "bitwise operators",
+                "var flags = 1 << 2 | 1; print flags & ~1; print flags ^ - -1; print -8 >> 1 < 0;",
+            ),
+            (
+                "string interpolation",
+                "{ var total = 3; print \"total: ${ total * 2 } of ${ \"{}\" }\"; }",
+            ),
+            (
+                "escaped interpolation",
+                "{ var total = 3; print \"\\${total} is ${ total }\" + \" \\${}\"; }",
+            ),
+            (
+                "compound assignment",
+                "var a = [1]; a[0] += 2; { var b = 3; b *= b -= 1; print b; } print a;",
+            ),
+            (
+                "conditionals",
+                "{ var sign = -1; print sign < 0 ? \"neg\" : sign > 0 ? \"pos\" : \"zero\"; }",
+            ),
+            (
+                "a local named like a built-in",
+                "fun f() { var len = 3; return len; } print f(); print len(\"ab\");",
+            ),
+            (
+                "strings with comment markers",
+                "print \"// not a comment\"; print \"a  b\";",
+            ),
+        ];
+        for (name, source) in cases {
+            let minified = minify_source(source);
+            assert!(minified.len() < source.len(), "case: {}", name);
+            assert_eq!(
+                run(&minified).unwrap(),
+                run(source).unwrap(),
+                "case: {}\nminified: {}",
+                name,
+                minified
+            );
+        }
+    }
+
+    #[test]
+    fn short_names_count_like_spreadsheet_columns() {
+        let names: Vec<String> = [0, 1, 25, 26, 27, 51, 52, 701, 702]
+            .into_iter()
+            .map(short_name)
+            .collect();
+        assert_eq!(names, ["a", "b", "z", "aa", "ab", "az", "ba", "zz", "aaa"]);
+    }
+}
@@ -0,0 +1,2774 @@
+use anyhow::{Result, bail};
+
+use std::mem;
+use std::rc::Rc;
+
+use crate::ast::{
+    BinaryOperator, ClassDecl, Expr, FieldDecl, Fixity, FunctionDecl, Identifier, Literal,
+    Location, LogicalOperator, Program, Segment, Stmt, UnaryOperator, UpdateOperator,
+};
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::grammar::{self, Expansion::*};
+use crate::token::{Span, Token, TokenKind};
+
+pub fn parse(tokens: &[Token]) -> Result<Program> {
+    Parser::new(tokens).parse()
+}
+
+/// Parses `tokens` as a single expression with nothing after it.
+pub fn parse_expression(tokens: &[Token]) -> Result<Expr> {
+    let mut parser = Parser::new(tokens);
+    let expr = parser.expression()?;
+    parser.expect(&TokenKind::EndOfFile, "end of expression")?;
+    Ok(expr)
+}
+
+const MAX_ARGUMENTS: usize = 255;
+
+/// The grammar this parser accepts, for documentation and tooling. It is
+/// not used to parse, so a change to the syntax must update it too.
+pub const GRAMMAR: &[grammar::Rule] = &[
+    grammar::Rule {
+        name: "program",
+        expansion: Sequence(&[Repeat(&Rule("declaration")), Token("EOF")]),
+    },
+    grammar::Rule {
+        name: "declaration",
+        expansion: Choice(&[
+            Rule("classDecl"),
+            Rule("funDecl"),
+            Rule("varDecl"),
+            Rule("statement"),
+        ]),
+    },
+    grammar::Rule {
+        name: "classDecl",
+        expansion: Sequence(&[
+            Literal("class"),
+            Token("IDENTIFIER"),
+            Optional(&Sequence(&[Literal("<"), Token("IDENTIFIER")])),
+            Literal("{"),
+            Repeat(&Choice(&[Rule("field"), Rule("function")])),
+            Literal("}"),
+        ]),
+    },
+    grammar::Rule {
+        name: "field",
+        expansion: Sequence(&[
+            Token("IDENTIFIER"),
+            Literal("="),
+            Rule("expression"),
+            Literal(";"),
+        ]),
+    },
+    grammar::Rule {
+        name: "funDecl",
+        expansion: Sequence(&[Literal("fun"), Rule("function")]),
+    },
+    grammar::Rule {
+        name: "function",
+        expansion: Sequence(&[
+            Token("IDENTIFIER"),
+            Literal("("),
+            Optional(&Rule("parameters")),
+            Literal(")"),
+            Rule("block"),
+        ]),
+    },
+    grammar::Rule {
+        name: "parameters",
+        expansion: Sequence(&[
+            Token("IDENTIFIER"),
+            Repeat(&Sequence(&[Literal(","), Token("IDENTIFIER")])),
+        ]),
+    },
+    grammar::Rule {
+        name: "varDecl",
+        expansion: Sequence(&[
+            Literal("var"),
+            Token("IDENTIFIER"),
+            Optional(&Sequence(&[Literal("="), Rule("expression")])),
+            Literal(";"),
+        ]),
+    },
+    grammar::Rule {
+        name: "statement",
+        expansion: Choice(&[
+            Rule("exprStmt"),
+            Rule("forStmt"),
+            Rule("ifStmt"),
+            Rule("printStmt"),
+            Rule("returnStmt"),
+            Rule("whileStmt"),
+            Rule("breakStmt"),
+            Rule("continueStmt"),
+            Rule("block"),
+        ]),
+    },
+    grammar::Rule {
+        name: "exprStmt",
+        expansion: Sequence(&[Rule("expression"), Literal(";")]),
+    },
+    grammar::Rule {
+        name: "forStmt",
+        expansion: Sequence(&[
+            Literal("for"),
+            Literal("("),
+            Choice(&[Rule("varDecl"), Rule("exprStmt"), Literal(";")]),
+            Optional(&Rule("expression")),
+            Literal(";"),
+            Optional(&Rule("expression")),
+            Literal(")"),
+            Rule("statement"),
+        ]),
+    },
+    grammar::Rule {
+        name: "ifStmt",
+        expansion: Sequence(&[
+            Literal("if"),
+            Literal("("),
+            Rule("expression"),
+            Literal(")"),
+            Rule("statement"),
+            Optional(&Sequence(&[Literal("else"), Rule("statement")])),
+        ]),
+    },
+    grammar::Rule {
+        name: "printStmt",
+        expansion: Sequence(&[Literal("print"), Rule("expression"), Literal(";")]),
+    },
+    grammar::Rule {
+        name: "returnStmt",
+        expansion: Sequence(&[
+            Literal("return"),
+            Optional(&Rule("expression")),
+            Literal(";"),
+        ]),
+    },
+    grammar::Rule {
+        name: "whileStmt",
+        expansion: Sequence(&[
+            Literal("while"),
+            Literal("("),
+            Rule("expression"),
+            Literal(")"),
+            Rule("statement"),
+        ]),
+    },
+    grammar::Rule {
+        name: "breakStmt",
+        expansion: Sequence(&[Literal("break"), Literal(";")]),
+    },
+    grammar::Rule {
+        name: "continueStmt",
+        expansion: Sequence(&[Literal("continue"), Literal(";")]),
+    },
+    grammar::Rule {
+        name: "block",
+        expansion: Sequence(&[Literal("{"), Repeat(&Rule("declaration")), Literal("}")]),
+    },
+    grammar::Rule {
+        name: "expression",
+        expansion: Sequence(&[
+            Rule("assignment"),
+            Repeat(&Sequence(&[Literal(","), Rule("assignment")])),
+        ]),
+    },
+    grammar::Rule {
+        name: "assignment",
+        expansion: Choice(&[
+            Sequence(&[
+                Optional(&Sequence(&[Rule("call"), Literal(".")])),
+                Token("IDENTIFIER"),
+                Rule("assignOp"),
+                Rule("assignment"),
+            ]),
+            Sequence(&[
+                Rule("call"),
+                Literal("["),
+                Rule("expression"),
+                Literal("]"),
+                Rule("assignOp"),
+                Rule("assignment"),
+            ]),
+            Rule("conditional"),
+        ]),
+    },
+    grammar::Rule {
+        name: "assignOp",
+        expansion: Choice(&[
+            Literal("="),
+            Literal("+="),
+            Literal("-="),
+            Literal("*="),
+            Literal("/="),
+        ]),
+    },
+    grammar::Rule {
+        name: "conditional",
+        expansion: Sequence(&[
+            Rule("logic_or"),
+            Optional(&Sequence(&[
+                Literal("?"),
+                Rule("expression"),
+                Literal(":"),
+                Rule("conditional"),
+            ])),
+        ]),
+    },
+    grammar::Rule {
+        name: "logic_or",
+        expansion: Sequence(&[
+            Rule("logic_and"),
+            Repeat(&Sequence(&[Literal("or"), Rule("logic_and")])),
+        ]),
+    },
+    grammar::Rule {
+        name: "logic_and",
+        expansion: Sequence(&[
+            Rule("equality"),
+            Repeat(&Sequence(&[Literal("and"), Rule("equality")])),
+        ]),
+    },
+    grammar::Rule {
+        name: "equality",
+        expansion: Sequence(&[
+            Rule("comparison"),
+            Repeat(&Sequence(&[
+                Choice(&[Literal("!="), Literal("==")]),
+                Rule("comparison"),
+            ])),
+        ]),
+    },
+    grammar::Rule {
+        name: "comparison",
+        expansion: Sequence(&[
+            Rule("bit_or"),
+            Repeat(&Sequence(&[
+                Choice(&[Literal(">"), Literal(">="), Literal("<"), Literal("<=")]),
+                Rule("bit_or"),
+            ])),
+        ]),
+    },
+    grammar::Rule {
+        name: "bit_or",
+        expansion: Sequence(&[
+            Rule("bit_xor"),
+            Repeat(&Sequence(&[Literal("|"), Rule("bit_xor")])),
+        ]),
+    },
+    grammar::Rule {
+        name: "bit_xor",
+        expansion: Sequence(&[
+            Rule("bit_and"),
+            Repeat(&Sequence(&[Literal("^"), Rule("bit_and")])),
+        ]),
+    },
+    grammar::Rule {
+        name: "bit_and",
+        expansion: Sequence(&[
+            Rule("shift"),
+            Repeat(&Sequence(&[Literal("&"), Rule("shift")])),
+        ]),
+    },
+    grammar::Rule {
+        name: "shift",
+        expansion: Sequence(&[
+            Rule("term"),
+            Repeat(&Sequence(&[
+                Choice(&[Literal("<<"), Literal(">>")]),
+                Rule("term"),
+            ])),
+        ]),
+    },
+    grammar::Rule {
+        name: "term",
+        expansion: Sequence(&[
+            Rule("factor"),
+            Repeat(&Sequence(&[
+                Choice(&[Literal("-"), Literal("+")]),
+                Rule("factor"),
+            ])),
+        ]),
+    },
+    grammar::Rule {
+        name: "factor",
+        expansion: Sequence(&[
+            Rule("unary"),
+            Repeat(&Sequence(&[
+                Choice(&[Literal("/"), Literal("*"), Literal("%")]),
+                Rule("unary"),
+            ])),
+        ]),
+    },
+    grammar::Rule {
+        name: "unary",
+        expansion: Choice(&[
+            Sequence(&[
+                Choice(&[Literal("!"), Literal("-"), Literal("~")]),
+                Rule("unary"),
+            ]),
+            Rule("exponent"),
+        ]),
+    },
+    grammar::Rule {
+        name: "exponent",
+        expansion: Sequence(&[
+            Rule("update"),
+            Optional(&Sequence(&[Literal("**"), Rule("unary")])),
+        ]),
+    },
+    grammar::Rule {
+        name: "update",
+        expansion: Choice(&[
+            Sequence(&[Choice(&[Literal("++"), Literal("--")]), Rule("call")]),
+            Sequence(&[
+                Rule("call"),
+                Optional(&Choice(&[Literal("++"), Literal("--")])),
+            ]),
+        ]),
+    },
+    grammar::Rule {
+        name: "call",
+        expansion: Sequence(&[
+            Rule("primary"),
+            Repeat(&Choice(&[
+                Sequence(&[Literal("("), Optional(&Rule("arguments")), Literal(")")]),
+                Sequence(&[Literal("."), Token("IDENTIFIER")]),
+                Sequence(&[
+                    Literal("["),
+                    Choice(&[
+                        Sequence(&[
+                            Optional(&Rule("expression")),
+                            Literal(":"),
+                            Optional(&Rule("expression")),
+                        ]),
+                        Rule("expression"),
+                    ]),
+                    Literal("]"),
+                ]),
+            ])),
+        ]),
+    },
+    grammar::Rule {
+        name: "arguments",
+        expansion: Sequence(&[
+            Rule("assignment"),
+            Repeat(&Sequence(&[Literal(","), Rule("assignment")])),
+        ]),
+    },
+    grammar::Rule {
+        name: "entries",
+        expansion: Sequence(&[
+            Rule("entry"),
+            Repeat(&Sequence(&[Literal(","), Rule("entry")])),
+        ]),
+    },
+    grammar::Rule {
+        name: "entry",
+        expansion: Sequence(&[Rule("assignment"), Literal(":"), Rule("assignment")]),
+    },
+    grammar::Rule {
+        name: "primary",
+        expansion: Choice(&[
+            Literal("true"),
+            Literal("false"),
+            Literal("nil"),
+            Literal("this"),
+            Token("NUMBER"),
+            Token("STRING"),
+            Rule("interpolation"),
+            Token("IDENTIFIER"),
+            Sequence(&[Literal("("), Rule("expression"), Literal(")")]),
+            Sequence(&[Literal("["), Optional(&Rule("arguments")), Literal("]")]),
+            Sequence(&[Literal("{"), Optional(&Rule("entries")), Literal("}")]),
+            Sequence(&[Literal("super"), Literal("."), Token("IDENTIFIER")]),
+        ]),
+    },
+    grammar::Rule {
+        name: "interpolation",
+        expansion: Sequence(&[
+            Token("INTERPOLATION"),
+            Rule("expression"),
+            Repeat(&Sequence(&[Token("INTERPOLATION"), Rule("expression")])),
+            Token("STRING"),
+        ]),
+    },
+];
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    current: usize,
+    // The innermost function body being parsed, which decides whether
+    // `return` is allowed and whether it may carry a value.
+    function: FunctionKind,
+    // The innermost class body being parsed, which decides whether `this`
+    // and `super` are allowed.
+    class: ClassKind,
+    // How many loops around the statement being parsed are inside the
+    // innermost function body, which decides whether `break` and
+    // `continue` are allowed.
+    loop_depth: usize,
+    diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassKind {
+    None,
+    Class,
+    Subclass,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+impl FunctionKind {
+    fn description(self) -> &'static str {
+        match self {
+            FunctionKind::None | FunctionKind::Function => "function",
+            FunctionKind::Method | FunctionKind::Initializer => "method",
+        }
+    }
+}
+
+fn equality_operator(kind: &TokenKind) -> Option<BinaryOperator> {
+    match kind {
+        TokenKind::EqualEqual => Some(BinaryOperator::Equal),
+        TokenKind::BangEqual => Some(BinaryOperator::NotEqual),
+        _ => None,
+    }
+}
+
+fn comparison_operator(kind: &TokenKind) -> Option<BinaryOperator> {
+    match kind {
+        TokenKind::Less => Some(BinaryOperator::Less),
+        TokenKind::LessEqual => Some(BinaryOperator::LessEqual),
+        TokenKind::Greater => Some(BinaryOperator::Greater),
+        TokenKind::GreaterEqual => Some(BinaryOperator::GreaterEqual),
+        _ => None,
+    }
+}
+
+fn bit_or_operator(kind: &TokenKind) -> Option<BinaryOperator> {
+    match kind {
+        TokenKind::Pipe => Some(BinaryOperator::BitOr),
+        _ => None,
+    }
+}
+
+fn bit_xor_operator(kind: &TokenKind) -> Option<BinaryOperator> {
+    match kind {
+        TokenKind::Caret => Some(BinaryOperator::BitXor),
+        _ => None,
+    }
+}
+
+fn bit_and_operator(kind: &TokenKind) -> Option<BinaryOperator> {
+    match kind {
+        TokenKind::Ampersand => Some(BinaryOperator::BitAnd),
+        _ => None,
+    }
+}
+
+fn shift_operator(kind: &TokenKind) -> Option<BinaryOperator> {
+    match kind {
+        TokenKind::LessLess => Some(BinaryOperator::ShiftLeft),
+        TokenKind::GreaterGreater => Some(BinaryOperator::ShiftRight),
+        _ => None,
+    }
+}
+
+fn term_operator(kind: &TokenKind) -> Option<BinaryOperator> {
+    match kind {
+        TokenKind::Plus => Some(BinaryOperator::Add),
+        TokenKind::Minus => Some(BinaryOperator::Subtract),
+        _ => None,
+    }
+}
+
+fn factor_operator(kind: &TokenKind) -> Option<BinaryOperator> {
+    match kind {
+        TokenKind::Star => Some(BinaryOperator::Multiply),
+        TokenKind::Slash => Some(BinaryOperator::Divide),
+        TokenKind::Percent => Some(BinaryOperator::Modulo),
+        _ => None,
+    }
+}
+
+// The assignment of `value` to `target`, or `None` if `target` is not
+// something that can be assigned to.
+fn assign_to(target: Expr, value: Expr) -> Option<Expr> {
+    match target {
+        Expr::Variable(name) => Some(Expr::assign(name, value)),
+        Expr::Get(object, name, span) => Some(Expr::set(*object, name, value, span)),
+        Expr::Index(object, index, location, span) => {
+            Some(Expr::set_index(*object, *index, value, location, span))
+        }
+        _ => None,
+    }
+}
+
+// Whether `expr` is something that can be both read and assigned to.
+fn is_place(expr: &Expr) -> bool {
+    matches!(expr, Expr::Variable(_) | Expr::Get(..) | Expr::Index(..))
+}
+
+fn update_operator(kind: &TokenKind) -> Option<UpdateOperator> {
+    match kind {
+        TokenKind::PlusPlus => Some(UpdateOperator::Increment),
+        TokenKind::MinusMinus => Some(UpdateOperator::Decrement),
+        _ => None,
+    }
+}
+
+// The operator a compound assignment such as `+=` applies.
+fn compound_operator(kind: &TokenKind) -> Option<BinaryOperator> {
+    match kind {
+        TokenKind::PlusEqual => Some(BinaryOperator::Add),
+        TokenKind::MinusEqual => Some(BinaryOperator::Subtract),
+        TokenKind::StarEqual => Some(BinaryOperator::Multiply),
+        TokenKind::SlashEqual => Some(BinaryOperator::Divide),
+        _ => None,
+    }
+}
+
+fn unary_operator(kind: &TokenKind) -> Option<UnaryOperator> {
+    match kind {
+        TokenKind::Minus => Some(UnaryOperator::Negate),
+        TokenKind::Bang => Some(UnaryOperator::Not),
+        TokenKind::Tilde => Some(UnaryOperator::BitNot),
+        _ => None,
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn new(tokens: &'a [Token]) -> Self {
+        Self {
+            tokens,
+            current: 0,
+            function: FunctionKind::None,
+            class: ClassKind::None,
+            loop_depth: 0,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    // Keeps parsing past errors so that every syntax error in the file is
+    // reported at once.
+    fn parse(&mut self) -> Result<Program> {
+        let mut program = Vec::new();
+        while !self.is_at_end() {
+            program.extend(self.recovering_declaration());
+        }
+        if !self.diagnostics.is_empty() {
+            return Err(Diagnostics(mem::take(&mut self.diagnostics)).into());
+        }
+        Ok(program)
+    }
+
+    // Parses a declaration, or records why it couldn't and skips ahead to
+    // where the next one probably starts.
+    fn recovering_declaration(&mut self) -> Option<Stmt> {
+        match self.declaration() {
+            Ok(stmt) => Some(stmt),
+            Err(error) => {
+                let diagnostic = match error.downcast::<Diagnostic>() {
+                    Ok(diagnostic) => diagnostic,
+                    Err(error) => Diagnostic::error(error.to_string(), self.span()),
+                };
+                self.diagnostics.push(diagnostic);
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    // Discards tokens up to the end of the current statement: just past a
+    // semicolon, or before a keyword that begins a statement.
+    fn synchronize(&mut self) {
+        self.advance();
+        while !self.is_at_end() {
+            if self.current > 0 && self.tokens[self.current - 1].kind == TokenKind::Semicolon {
+                return;
+            }
+            if matches!(
+                self.peek(),
+                TokenKind::Class
+                    | TokenKind::Fun
+                    | TokenKind::Var
+                    | TokenKind::For
+                    | TokenKind::If
+                    | TokenKind::While
+                    | TokenKind::Print
+                    | TokenKind::Return
+                    | TokenKind::Break
+                    | TokenKind::Continue
+            ) {
+                return;
+            }
+            self.advance();
+        }
+    }
+
+    fn declaration(&mut self) -> Result<Stmt> {
+        if self.try_eat(&TokenKind::Var) {
+            return self.var_declaration();
+        }
+        if self.try_eat(&TokenKind::Fun) {
+            return Ok(Stmt::Function(Rc::new(
+                self.function(FunctionKind::Function)?,
+            )));
+        }
+        if self.try_eat(&TokenKind::Class) {
+            return self.class_declaration();
+        }
+        self.statement()
+    }
+
+    fn class_declaration(&mut self) -> Result<Stmt> {
+        let name = self.expect_identifier("class name")?;
+        let superclass = if self.try_eat(&TokenKind::Less) {
+            if matches!(self.peek(), TokenKind::Identifier(superclass) if *superclass == name.name)
+            {
+                bail!(
+                    "parser: a class can't inherit from itself {}",
+                    self.location()
+                );
+            }
+            Some(self.expect_identifier("superclass name")?)
+        } else {
+            None
+        };
+        self.expect(&TokenKind::LeftBrace, "'{' before class body")?;
+
+        let kind = match superclass {
+            Some(_) => ClassKind::Subclass,
+            None => ClassKind::Class,
+        };
+        let enclosing = std::mem::replace(&mut self.class, kind);
+        let body = self.class_body();
+        self.class = enclosing;
+
+        let (fields, methods) = body?;
+        Ok(Stmt::Class(Rc::new(ClassDecl {
+            name,
+            superclass,
+            fields,
+            methods,
+        })))
+    }
+
+    // Parses field and method declarations up to and including the class's
+    // closing brace.
+    fn class_body(&mut self) -> Result<(Vec<FieldDecl>, Vec<Rc<FunctionDecl>>)> {
+        let opener = self.current - 1;
+        let mut fields = Vec::new();
+        let mut methods = Vec::new();
+        while !matches!(self.peek(), TokenKind::RightBrace | TokenKind::EndOfFile) {
+            if matches!(self.peek(), TokenKind::Identifier(_))
+                && self.tokens.get(self.current + 1).map(|t| &t.kind) == Some(&TokenKind::Equal)
+            {
+                fields.push(self.field()?);
+                continue;
+            }
+            let kind = match self.peek() {
+                TokenKind::Identifier(name) if name == "init" => FunctionKind::Initializer,
+                _ => FunctionKind::Method,
+            };
+            methods.push(Rc::new(self.function(kind)?));
+        }
+        self.expect_closing(
+            opener,
+            "class body",
+            &TokenKind::RightBrace,
+            "'}' after class body",
+        )?;
+        Ok((fields, methods))
+    }
+
+    fn field(&mut self) -> Result<FieldDecl> {
+        let name = self.expect_identifier("field name")?;
+        self.expect(&TokenKind::Equal, "'=' after field name")?;
+        // An initializer runs for each new instance, which it may refer to
+        // as `this`, but it is not a function body that could `return`.
+        let initializer = self.expression()?;
+        self.expect(&TokenKind::Semicolon, "';' after field initializer")?;
+        Ok(FieldDecl { name, initializer })
+    }
+
+    fn function(&mut self, kind: FunctionKind) -> Result<FunctionDecl> {
+        let kind_name = kind.description();
+        let name = self.expect_identifier(&format!("{} name", kind_name))?;
+
+        self.expect(
+            &TokenKind::LeftParen,
+            &format!("'(' after {} name", kind_name),
+        )?;
+        let mut params = Vec::new();
+        if !matches!(self.peek(), TokenKind::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    bail!(
+                        "parser: can't have more than {} parameters {}",
+                        MAX_ARGUMENTS,
+                        self.location()
+                    );
+                }
+                params.push(self.expect_identifier("parameter name")?);
+                if !self.try_eat(&TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+        self.expect(&TokenKind::RightParen, "')' after parameters")?;
+
+        self.expect(
+            &TokenKind::LeftBrace,
+            &format!("'{{' before {} body", kind_name),
+        )?;
+        let enclosing = std::mem::replace(&mut self.function, kind);
+        let enclosing_loops = std::mem::replace(&mut self.loop_depth, 0);
+        let body = self.block();
+        self.function = enclosing;
+        self.loop_depth = enclosing_loops;
+
+        Ok(FunctionDecl {
+            name,
+            params,
+            body: body?,
+        })
+    }
+
+    fn var_declaration(&mut self) -> Result<Stmt> {
+        let name = self.expect_identifier("variable name")?;
+        let initializer = if self.try_eat(&TokenKind::Equal) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.expect(&TokenKind::Semicolon, "';' after variable declaration")?;
+        Ok(Stmt::Var(name, initializer))
+    }
+
+    fn statement(&mut self) -> Result<Stmt> {
+        if self.try_eat(&TokenKind::LeftBrace) {
+            return Ok(Stmt::Block(self.block()?));
+        }
+        if self.try_eat(&TokenKind::If) {
+            return self.if_statement();
+        }
+        if self.try_eat(&TokenKind::While) {
+            return self.while_statement();
+        }
+        if self.try_eat(&TokenKind::For) {
+            return self.for_statement();
+        }
+        if matches!(self.peek(), TokenKind::Return) {
+            return self.return_statement();
+        }
+        if matches!(self.peek(), TokenKind::Break | TokenKind::Continue) {
+            return self.loop_jump_statement();
+        }
+        if self.try_eat(&TokenKind::Print) {
+            let expr = self.expression()?;
+            self.expect(&TokenKind::Semicolon, "';' after value")?;
+            return Ok(Stmt::Print(expr));
+        }
+
+        let expr = self.expression()?;
+        self.expect(&TokenKind::Semicolon, "';' after expression")?;
+        Ok(Stmt::Expression(expr))
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt> {
+        self.expect(&TokenKind::LeftParen, "'(' after 'if'")?;
+        let condition = self.expression()?;
+        self.expect(&TokenKind::RightParen, "')' after if condition")?;
+
+        // An `else` binds to the nearest preceding `if`.
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.try_eat(&TokenKind::Else) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+
+        Ok(Stmt::If(condition, then_branch, else_branch))
+    }
+
+    fn return_statement(&mut self) -> Result<Stmt> {
+        if self.function == FunctionKind::None {
+            bail!(
+                "parser: can't return from top-level code {}",
+                self.location()
+            );
+        }
+        self.advance();
+
+        let value = if matches!(self.peek(), TokenKind::Semicolon) {
+            None
+        } else {
+            if self.function == FunctionKind::Initializer {
+                bail!(
+                    "parser: can't return a value from an initializer {}",
+                    self.location()
+                );
+            }
+            Some(self.expression()?)
+        };
+        self.expect(&TokenKind::Semicolon, "';' after return value")?;
+        Ok(Stmt::Return(value))
+    }
+
+    fn loop_jump_statement(&mut self) -> Result<Stmt> {
+        let (keyword, stmt) = match self.peek() {
+            TokenKind::Break => ("break", Stmt::Break),
+            _ => ("continue", Stmt::Continue),
+        };
+        if self.loop_depth == 0 {
+            bail!(
+                "parser: can't use '{}' outside of a loop {}",
+                keyword,
+                self.location()
+            );
+        }
+        self.advance();
+        self.expect(&TokenKind::Semicolon, &format!("';' after '{}'", keyword))?;
+        Ok(stmt)
+    }
+
+    fn while_statement(&mut self) -> Result<Stmt> {
+        self.expect(&TokenKind::LeftParen, "'(' after 'while'")?;
+        let condition = self.expression()?;
+        self.expect(&TokenKind::RightParen, "')' after while condition")?;
+        let body = self.loop_body()?;
+        Ok(Stmt::While(condition, Box::new(body), None))
+    }
+
+    fn loop_body(&mut self) -> Result<Stmt> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+        body
+    }
+
+    // There is no dedicated `for` node: the loop is desugared into
+    //
+    //     { initializer; while (condition) body }
+    //
+    // with a missing condition treated as `true`. The increment stays on
+    // the while node rather than joining the body, so that `continue`
+    // doesn't skip it.
+    fn for_statement(&mut self) -> Result<Stmt> {
+        self.expect(&TokenKind::LeftParen, "'(' after 'for'")?;
+
+        let initializer = if self.try_eat(&TokenKind::Semicolon) {
+            None
+        } else if self.try_eat(&TokenKind::Var) {
+            Some(self.var_declaration()?)
+        } else {
+            let expr = self.expression()?;
+            self.expect(&TokenKind::Semicolon, "';' after loop initializer")?;
+            Some(Stmt::Expression(expr))
+        };
+
+        let condition = if matches!(self.peek(), TokenKind::Semicolon) {
+            Expr::literal(Literal::Bool(true))
+        } else {
+            self.expression()?
+        };
+        self.expect(&TokenKind::Semicolon, "';' after loop condition")?;
+
+        let increment = if matches!(self.peek(), TokenKind::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.expect(&TokenKind::RightParen, "')' after for clauses")?;
+
+        let body = self.loop_body()?;
+        let mut desugared = Stmt::While(condition, Box::new(body), increment);
+        if let Some(initializer) = initializer {
+            desugared = Stmt::Block(vec![initializer, desugared]);
+        }
+
+        Ok(desugared)
+    }
+
+    // Parses the statements of a block whose opening brace was already consumed.
+    fn block(&mut self) -> Result<Vec<Stmt>> {
+        let opener = self.current - 1;
+        let mut statements = Vec::new();
+        while !matches!(self.peek(), TokenKind::RightBrace | TokenKind::EndOfFile) {
+            statements.extend(self.recovering_declaration());
+        }
+        self.expect_closing(opener, "block", &TokenKind::RightBrace, "'}' after block")?;
+        Ok(statements)
+    }
+
+    // The comma operator binds loosest of all. Call arguments are also
+    // separated by commas, so each of them is parsed as an assignment
+    // instead; a comma expression as an argument needs parentheses.
+    fn expression(&mut self) -> Result<Expr> {
+        let mut expr = self.assignment()?;
+        while self.try_eat(&TokenKind::Comma) {
+            let right = self.assignment()?;
+            expr = Expr::comma(expr, right);
+        }
+        Ok(expr)
+    }
+
+    fn assignment(&mut self) -> Result<Expr> {
+        let location = self.location();
+        let expr = self.conditional()?;
+
+        let compound = compound_operator(self.peek());
+        if compound.is_some() || self.peek() == &TokenKind::Equal {
+            let operator = self.position();
+            let operator_span = self.span();
+            self.advance();
+            // Assignment is right-associative, so the value may itself be an
+            // assignment.
+            let value = self.assignment()?;
+            if let Some(op) = compound {
+                if !is_place(&expr) {
+                    bail!("parser: invalid assignment target {}", location);
+                }
+                return Ok(Expr::compound_assign(
+                    expr,
+                    op,
+                    value,
+                    operator,
+                    operator_span,
+                ));
+            }
+            return match assign_to(expr, value) {
+                Some(assignment) => Ok(assignment),
+                None => bail!("parser: invalid assignment target {}", location),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    // The else branch may itself be a conditional, which makes `?:` right
+    // associative. The then branch can be any expression, since `:` ends it.
+    fn conditional(&mut self) -> Result<Expr> {
+        let condition = self.or()?;
+        if !self.try_eat(&TokenKind::Question) {
+            return Ok(condition);
+        }
+        let then_branch = self.expression()?;
+        self.expect(&TokenKind::Colon, "':' after the first branch of '?'")?;
+        let else_branch = self.conditional()?;
+        Ok(Expr::conditional(condition, then_branch, else_branch))
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.try_eat(&TokenKind::Or) {
+            let right = self.and()?;
+            expr = Expr::logical(expr, LogicalOperator::Or, right);
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.equality()?;
+        while self.try_eat(&TokenKind::And) {
+            let right = self.equality()?;
+            expr = Expr::logical(expr, LogicalOperator::And, right);
+        }
+        Ok(expr)
+    }
+
+    fn equality(&mut self) -> Result<Expr> {
+        self.binary(equality_operator, Self::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        self.binary(comparison_operator, Self::bit_or)
+    }
+
+    // The bitwise operators bind tighter than comparisons, unlike in C, so
+    // `a & mask == 0` compares the masked bits.
+    fn bit_or(&mut self) -> Result<Expr> {
+        self.binary(bit_or_operator, Self::bit_xor)
+    }
+
+    fn bit_xor(&mut self) -> Result<Expr> {
+        self.binary(bit_xor_operator, Self::bit_and)
+    }
+
+    fn bit_and(&mut self) -> Result<Expr> {
+        self.binary(bit_and_operator, Self::shift)
+    }
+
+    fn shift(&mut self) -> Result<Expr> {
+        self.binary(shift_operator, Self::term)
+    }
+
+    fn term(&mut self) -> Result<Expr> {
+        self.binary(term_operator, Self::factor)
+    }
+
+    fn factor(&mut self) -> Result<Expr> {
+        self.binary(factor_operator, Self::unary)
+    }
+
+    // Parses a left-associative chain of `operand (operator operand)*`.
+    fn binary(
+        &mut self,
+        operator: impl Fn(&TokenKind) -> Option<BinaryOperator>,
+        operand: impl Fn(&mut Self) -> Result<Expr>,
+    ) -> Result<Expr> {
+        let mut expr = operand(self)?;
+        while let Some(op) = operator(self.peek()) {
+            let location = self.position();
+            let span = self.span();
+            self.advance();
+            let right = operand(self)?;
+            expr = Expr::binary(expr, op, right, location, span);
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if let Some(op) = unary_operator(self.peek()) {
+            let location = self.position();
+            let span = self.span();
+            self.advance();
+            let right = self.unary()?;
+            return Ok(Expr::unary(op, right, location, span));
+        }
+        self.exponent()
+    }
+
+    // `**` binds tighter than a unary operator before it, so `-2 ** 2` is
+    // `-(2 ** 2)`, and groups to the right, so `2 ** 3 ** 2` is `2 ** 9`.
+    fn exponent(&mut self) -> Result<Expr> {
+        let base = self.update()?;
+        let location = self.position();
+        let span = self.span();
+        if self.try_eat(&TokenKind::StarStar) {
+            let exponent = self.unary()?;
+            return Ok(Expr::binary(
+                base,
+                BinaryOperator::Power,
+                exponent,
+                location,
+                span,
+            ));
+        }
+        Ok(base)
+    }
+
+    fn update(&mut self) -> Result<Expr> {
+        let location = self.position();
+        let span = self.span();
+        if let Some(op) = update_operator(self.peek()) {
+            self.advance();
+            let target = self.call()?;
+            return Self::updated(target, op, Fixity::Prefix, location, span);
+        }
+
+        let target = self.call()?;
+        let location = self.position();
+        let span = self.span();
+        let Some(op) = update_operator(self.peek()) else {
+            return Ok(target);
+        };
+        self.advance();
+        Self::updated(target, op, Fixity::Postfix, location, span)
+    }
+
+    fn updated(
+        target: Expr,
+        op: UpdateOperator,
+        fixity: Fixity,
+        location: Location,
+        span: Span,
+    ) -> Result<Expr> {
+        if !is_place(&target) {
+            bail!(
+                "parser: '{}' needs a variable, property or element to change {}",
+                op,
+                location
+            );
+        }
+        Ok(Expr::update(target, op, fixity, location, span))
+    }
+
+    fn call(&mut self) -> Result<Expr> {
+        let mut expr = self.primary()?;
+        loop {
+            let location = self.position();
+            let start = self.span().start;
+            if self.try_eat(&TokenKind::LeftParen) {
+                let arguments = self.arguments()?;
+                expr = Expr::call(expr, arguments, location, self.span_from(start));
+            } else if self.try_eat(&TokenKind::Dot) {
+                let name = self.expect_identifier("property name after '.'")?;
+                expr = Expr::get(expr, name, self.span_from(start));
+            } else if self.try_eat(&TokenKind::LeftBracket) {
+                let opener = self.current - 1;
+                let index = match self.peek() {
+                    TokenKind::Colon => None,
+                    _ => Some(self.expression()?),
+                };
+                expr = match (index, self.try_eat(&TokenKind::Colon)) {
+                    (index, true) => {
+                        let end = match self.peek() {
+                            TokenKind::RightBracket => None,
+                            _ => Some(self.expression()?),
+                        };
+                        self.expect_closing(
+                            opener,
+                            "slice",
+                            &TokenKind::RightBracket,
+                            "']' after slice",
+                        )?;
+                        Expr::slice(expr, index, end, location, self.span_from(start))
+                    }
+                    (Some(index), false) => {
+                        self.expect_closing(
+                            opener,
+                            "index",
+                            &TokenKind::RightBracket,
+                            "']' after index",
+                        )?;
+                        Expr::index(expr, index, location, self.span_from(start))
+                    }
+                    // Only a ':' stops the index from being parsed.
+                    (None, false) => unreachable!(),
+                };
+            } else {
+                break;
+            }
+        }
+        Ok(expr)
+    }
+
+    // Parses a call's argument list after its opening parenthesis.
+    fn arguments(&mut self) -> Result<Vec<Expr>> {
+        let opener = self.current - 1;
+        let arguments =
+            self.comma_separated(&TokenKind::RightParen, "arguments", Self::assignment)?;
+        self.expect_closing(
+            opener,
+            "argument list",
+            &TokenKind::RightParen,
+            "')' after arguments",
+        )?;
+        Ok(arguments)
+    }
+
+    // Parses assignments separated by commas, up to but not including
+    // `end`. Like call arguments, the elements of a list literal are limited
+    // in number.
+    fn comma_separated<T>(
+        &mut self,
+        end: &TokenKind,
+        what: &str,
+        mut item: impl FnMut(&mut Self) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        if self.peek() != end {
+            loop {
+                if items.len() >= MAX_ARGUMENTS {
+                    bail!(
+                        "parser: can't have more than {} {} {}",
+                        MAX_ARGUMENTS,
+                        what,
+                        self.location()
+                    );
+                }
+                items.push(item(self)?);
+                if !self.try_eat(&TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+        Ok(items)
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let literal = match self.peek() {
+            TokenKind::Number(n) => Literal::Number(*n),
+            TokenKind::String(s) => Literal::String(s.clone()),
+            TokenKind::True => Literal::Bool(true),
+            TokenKind::False => Literal::Bool(false),
+            TokenKind::Nil => Literal::Nil,
+            TokenKind::This => {
+                if self.class == ClassKind::None {
+                    bail!(
+                        "parser: can't use 'this' outside of a class {}",
+                        self.location()
+                    );
+                }
+                let location = self.position();
+                self.advance();
+                return Ok(Expr::This(location));
+            }
+            TokenKind::Super => {
+                match self.class {
+                    ClassKind::None => bail!(
+                        "parser: can't use 'super' outside of a class {}",
+                        self.location()
+                    ),
+                    ClassKind::Class => bail!(
+                        "parser: can't use 'super' in a class with no superclass {}",
+                        self.location()
+                    ),
+                    ClassKind::Subclass => {}
+                }
+                let location = self.position();
+                self.advance();
+                self.expect(&TokenKind::Dot, "'.' after 'super'")?;
+                let method = self.expect_identifier("superclass method name")?;
+                return Ok(Expr::Super(location, method));
+            }
+            TokenKind::Identifier(_) => {
+                let name = self.expect_identifier("expression")?;
+                return Ok(Expr::Variable(name));
+            }
+            TokenKind::Interpolation(text) => {
+                let text = text.clone();
+                self.advance();
+                return self.interpolation(text);
+            }
+            TokenKind::LeftParen => {
+                let opener = self.current;
+                self.advance();
+                let expr = self.expression()?;
+                self.expect_closing(
+                    opener,
+                    "parenthesis",
+                    &TokenKind::RightParen,
+                    "')' after expression",
+                )?;
+                return Ok(Expr::grouping(expr));
+            }
+            TokenKind::LeftBracket => {
+                let location = self.position();
+                let opener = self.current;
+                self.advance();
+                let elements = self.comma_separated(
+                    &TokenKind::RightBracket,
+                    "list elements",
+                    Self::assignment,
+                )?;
+                self.expect_closing(
+                    opener,
+                    "list",
+                    &TokenKind::RightBracket,
+                    "']' after list elements",
+                )?;
+                return Ok(Expr::List(elements, location));
+            }
+            TokenKind::LeftBrace => {
+                let location = self.position();
+                let opener = self.current;
+                self.advance();
+                let entries = self.comma_separated(&TokenKind::RightBrace, "map entries", |p| {
+                    let key = p.assignment()?;
+                    p.expect(&TokenKind::Colon, "':' after map key")?;
+                    Ok((key, p.assignment()?))
+                })?;
+                self.expect_closing(
+                    opener,
+                    "map",
+                    &TokenKind::RightBrace,
+                    "'}' after map entries",
+                )?;
+                return Ok(Expr::Map(entries, location));
+            }
+            _ => bail!(
+                "parser: expected expression, found {} {}",
+                self.found(),
+                self.location()
+            ),
+        };
+        self.advance();
+        Ok(Expr::literal(literal))
+    }
+
+    // `first` is the text before the first `${`. Empty text is left out.
+    fn interpolation(&mut self, first: String) -> Result<Expr> {
+        let mut segments = Vec::new();
+        let mut text = first;
+        loop {
+            if !text.is_empty() {
+                segments.push(Segment::Text(text));
+            }
+            if self.at_interpolation_end() {
+                bail!(
+                    "parser: expected expression inside '${{}}', found {} {}",
+                    self.found(),
+                    self.location()
+                );
+            }
+            segments.push(Segment::Value(self.expression()?));
+
+            let end = self.at_interpolation_end();
+            match self.peek().clone() {
+                TokenKind::Interpolation(rest) if end => {
+                    self.advance();
+                    text = rest;
+                }
+                TokenKind::String(rest) if end => {
+                    self.advance();
+                    if !rest.is_empty() {
+                        segments.push(Segment::Text(rest));
+                    }
+                    break;
+                }
+                _ => bail!(
+                    "parser: expected '}}' after interpolated expression, found {} {}",
+                    self.found(),
+                    self.location()
+                ),
+            }
+        }
+        Ok(Expr::Interpolation(segments))
+    }
+
+    // Whether the next token is the text after the `}` that closes an
+    // interpolation.
+    fn at_interpolation_end(&self) -> bool {
+        self.tokens.get(self.current).is_some_and(|token| {
+            matches!(
+                token.kind,
+                TokenKind::Interpolation(_) | TokenKind::String(_)
+            ) && token.lexeme.starts_with('}')
+        })
+    }
+
+    fn expect(&mut self, kind: &TokenKind, description: &str) -> Result<()> {
+        if self.try_eat(kind) {
+            return Ok(());
+        }
+        bail!(
+            "parser: expected {}, found {} {}",
+            description,
+            self.found(),
+            self.location()
+        )
+    }
+
+    // Like `expect`, for the token that closes the `what` the token at index
+    // `opener` began. Running out of input first is reported at the end of
+    // the file, with a label on the opener, which is usually far back.
+    fn expect_closing(
+        &mut self,
+        opener: usize,
+        what: &str,
+        kind: &TokenKind,
+        description: &str,
+    ) -> Result<()> {
+        if !self.is_at_end() {
+            return self.expect(kind, description);
+        }
+        let opener = &self.tokens[opener];
+        let closer = match kind {
+            TokenKind::RightBrace => "}",
+            TokenKind::RightParen => ")",
+            TokenKind::RightBracket => "]",
+            kind => unreachable!("{:?} doesn't close anything", kind),
+        };
+        let message = format!(
+            "parser: unexpected end of file, expected '{}' to close the {} opened at line {}",
+            closer, what, opener.line
+        );
+        bail!(
+            Diagnostic::error(message, self.span())
+                .with_label(opener.span, format!("the {} opened here", what))
+        )
+    }
+
+    fn expect_identifier(&mut self, description: &str) -> Result<Identifier> {
+        if let Some(Token {
+            kind: TokenKind::Identifier(name),
+            line,
+            column,
+            ..
+        }) = self.tokens.get(self.current)
+        {
+            let identifier = Identifier {
+                name: name.clone(),
+                line: *line,
+                column: *column,
+            };
+            self.advance();
+            return Ok(identifier);
+        }
+        bail!(
+            "parser: expected {}, found {} {}",
+            description,
+            self.found(),
+            self.location()
+        )
+    }
+
+    // The current token as error messages refer to it.
+    fn found(&self) -> String {
+        match self.tokens.get(self.current) {
+            Some(token) => token.describe(),
+            None => "end of file".to_string(),
+        }
+    }
+
+    fn location(&self) -> String {
+        match self.tokens.get(self.current) {
+            Some(token) => token.location(),
+            None => "at end of input".to_string(),
+        }
+    }
+
+    fn position(&self) -> Location {
+        match self.tokens.get(self.current).or(self.tokens.last()) {
+            Some(token) => Location {
+                line: token.line,
+                column: token.column,
+            },
+            None => Location { line: 1, column: 1 },
+        }
+    }
+
+    fn span(&self) -> Span {
+        self.tokens
+            .get(self.current)
+            .or(self.tokens.last())
+            .map_or(Span::default(), |token| token.span)
+    }
+
+    // The span from `start` to the end of the last token consumed.
+    fn span_from(&self, start: usize) -> Span {
+        let end = match self.current {
+            0 => start,
+            current => self.tokens[current - 1].span.end,
+        };
+        Span { start, end }
+    }
+
+    fn try_eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek() == kind {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    fn peek(&self) -> &TokenKind {
+        static END_OF_FILE: TokenKind = TokenKind::EndOfFile;
+        self.tokens
+            .get(self.current)
+            .map_or(&END_OF_FILE, |t| &t.kind)
+    }
+
+    fn advance(&mut self) {
+        if !self.is_at_end() {
+            self.current += 1
+        }
+    }
+
+    fn is_at_end(&self) -> bool {
+        matches!(self.peek(), TokenKind::EndOfFile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::scan;
+    use BinaryOperator::*;
+
+    struct TestCase {
+        name: &'static str,
+        input: &'static str,
+        assertion: Box<dyn Matcher>,
+    }
+
+    trait Matcher {
+        fn check(&self, result: &Result<Program>) -> Result<()>;
+    }
+
+    struct ProgramMatcher {
+        expected: Program,
+    }
+
+    impl Matcher for ProgramMatcher {
+        fn check(&self, result: &Result<Program>) -> Result<()> {
+            match result {
+                Ok(program) if *program == self.expected => Ok(()),
+                Ok(program) => bail!(
+                    "Programs did not match.\nExpected: {:?}\n  Actual: {:?}",
+                    self.expected,
+                    program
+                ),
+                Err(e) => bail!("Expected success, but the parse failed with: {}", e),
+            }
+        }
+    }
+
+    struct ErrorMsgMatcher {
+        expected: String,
+    }
+
+    impl Matcher for ErrorMsgMatcher {
+        fn check(&self, result: &Result<Program>) -> Result<()> {
+            match result {
+                Ok(_) => bail!("Expected a parse error, but the operation succeeded."),
+                Err(e) => {
+                    let actual_msg = e.to_string();
+                    if actual_msg.contains(&self.expected) {
+                        Ok(())
+                    } else {
+                        bail!(
+                            "Error message did not match.\nExpected to contain: \"{}\"\n           Actual: \"{}\"",
+                            self.expected,
+                            actual_msg
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    macro_rules! program_eq {
+        ($($stmt:expr),*) => {
+            Box::new(ProgramMatcher { expected: vec![$($stmt),*] })
+        };
+    }
+
+    macro_rules! error_msg_eq {
+        ($msg:expr) => {
+            Box::new(ErrorMsgMatcher {
+                expected: $msg.to_string(),
+            })
+        };
+    }
+
+    fn span(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    // An operator at `column` of a one-line source.
+    fn binary(left: Expr, op: BinaryOperator, right: Expr, column: usize) -> Expr {
+        let end = column - 1 + op.to_string().len();
+        Expr::binary(
+            left,
+            op,
+            right,
+            Location { line: 1, column },
+            span(column - 1, end),
+        )
+    }
+
+    fn unary(op: UnaryOperator, right: Expr, column: usize) -> Expr {
+        let end = column - 1 + op.to_string().len();
+        Expr::unary(
+            op,
+            right,
+            Location { line: 1, column },
+            span(column - 1, end),
+        )
+    }
+
+    fn num(n: i32) -> Expr {
+        Expr::literal(Literal::Number(n))
+    }
+
+    fn ident(name: &str, line: usize, column: usize) -> Identifier {
+        Identifier {
+            name: name.to_string(),
+            line,
+            column,
+        }
+    }
+
+    fn run_test_internal(test_cases: &[TestCase]) {
+        for tc in test_cases {
+            let parse_result = scan(tc.input).and_then(|tokens| parse(&tokens));
+
+            let check_result = tc.assertion.check(&parse_result);
+
+            if let Err(error_message) = check_result {
+                panic!(
+                    "\n\n- Test Case Failed: '{}'\n- Input: '{}'\n- Reason: {}\n\n",
+                    tc.name, tc.input, error_message
+                );
+            }
+        }
+    }
+
+    macro_rules! run_tests {
+        ($($test_case:expr),* $(,)?) => {
+            run_test_internal(&[$($test_case),*])
+        };
+    }
+
+    #[test]
+    fn literals() {
+        run_tests!(
+            TestCase {
+                name: "success - number",
+                input: "4;",
+                assertion: program_eq!(Stmt::Expression(num(4))),
+            },
+            TestCase {
+                name: "success - string",
+                input: r#""a";"#,
+                assertion: program_eq!(Stmt::Expression(Expr::Literal(Literal::String(
+                    "a".to_string()
+                )))),
+            },
+            TestCase {
+                name: "success - keywords",
+                input: "true; false; nil;",
+                assertion: program_eq!(
+                    Stmt::Expression(Expr::Literal(Literal::Bool(true))),
+                    Stmt::Expression(Expr::Literal(Literal::Bool(false))),
+                    Stmt::Expression(Expr::Literal(Literal::Nil))
+                ),
+            },
+            TestCase {
+                name: "success - empty program",
+                input: "",
+                assertion: program_eq!(),
+            },
+        )
+    }
+
+    #[test]
+    fn precedence() {
+        run_tests!(
+            TestCase {
+                name: "success - factor binds tighter than term",
+                input: "1 + 2 * 3;",
+                assertion: program_eq!(Stmt::Expression(binary(
+                    num(1),
+                    Add,
+                    binary(num(2), Multiply, num(3), 7),
+                    3
+                ))),
+            },
+            TestCase {
+                name: "success - left associative",
+                input: "1 - 2 - 3;",
+                assertion: program_eq!(Stmt::Expression(binary(
+                    binary(num(1), Subtract, num(2), 3),
+                    Subtract,
+                    num(3),
+                    7
+                ))),
+            },
+            TestCase {
+                name: "success - modulo is a factor",
+                input: "1 + 2 % 3 * 4;",
+                assertion: program_eq!(Stmt::Expression(binary(
+                    num(1),
+                    Add,
+                    binary(binary(num(2), Modulo, num(3), 7), Multiply, num(4), 11),
+                    3
+                ))),
+            },
+            TestCase {
+                name: "success - exponent binds tighter than unary and groups to the right",
+                input: "-2 ** 3 ** -1 * 2;",
+                assertion: program_eq!(Stmt::Expression(binary(
+                    unary(
+                        UnaryOperator::Negate,
+                        binary(
+                            num(2),
+                            Power,
+                            binary(num(3), Power, unary(UnaryOperator::Negate, num(1), 12), 9),
+                            4
+                        ),
+                        1
+                    ),
+                    Multiply,
+                    num(2),
+                    15
+                ))),
+            },
+            TestCase {
+                name: "success - bitwise precedence from shift to or",
+                input: "a | b ^ c & d << 1 + 2 < e;",
+                assertion: program_eq!(Stmt::Expression(binary(
+                    binary(
+                        Expr::Variable(ident("a", 1, 1)),
+                        BitOr,
+                        binary(
+                            Expr::Variable(ident("b", 1, 5)),
+                            BitXor,
+                            binary(
+                                Expr::Variable(ident("c", 1, 9)),
+                                BitAnd,
+                                binary(
+                                    Expr::Variable(ident("d", 1, 13)),
+                                    ShiftLeft,
+                                    binary(num(1), Add, num(2), 20),
+                                    15
+                                ),
+                                11
+                            ),
+                            7
+                        ),
+                        3
+                    ),
+                    Less,
+                    Expr::Variable(ident("e", 1, 26)),
+                    24
+                ))),
+            },
+            TestCase {
+                name: "success - bitwise not is a unary operator",
+                input: "~a >> ~-1;",
+                assertion: program_eq!(Stmt::Expression(binary(
+                    unary(UnaryOperator::BitNot, Expr::Variable(ident("a", 1, 2)), 1),
+                    ShiftRight,
+                    unary(
+                        UnaryOperator::BitNot,
+                        unary(UnaryOperator::Negate, num(1), 8),
+                        7
+                    ),
+                    4
+                ))),
+            },
+            TestCase {
+                name: "success - comparison binds tighter than equality",
+                input: "1 < 2 == 3 >= 4;",
+                assertion: program_eq!(Stmt::Expression(binary(
+                    binary(num(1), Less, num(2), 3),
+                    Equal,
+                    binary(num(3), GreaterEqual, num(4), 12),
+                    7
+                ))),
+            },
+            TestCase {
+                name: "success - grouping overrides precedence",
+                input: "(1 + 2) / 3;",
+                assertion: program_eq!(Stmt::Expression(binary(
+                    Expr::grouping(binary(num(1), Add, num(2), 4)),
+                    Divide,
+                    num(3),
+                    9
+                ))),
+            },
+            TestCase {
+                name: "success - nested unary",
+                input: "!-1 != 2;",
+                assertion: program_eq!(Stmt::Expression(binary(
+                    unary(
+                        UnaryOperator::Not,
+                        unary(UnaryOperator::Negate, num(1), 2),
+                        1
+                    ),
+                    NotEqual,
+                    num(2),
+                    5
+                ))),
+            },
+            TestCase {
+                name: "success - conditional is right associative",
+                input: "1 ? 2 : 3 ? 4 : 5;",
+                assertion: program_eq!(Stmt::Expression(Expr::conditional(
+                    num(1),
+                    num(2),
+                    Expr::conditional(num(3), num(4), num(5))
+                ))),
+            },
+            TestCase {
+                name: "success - conditional binds looser than or",
+                input: "1 or 2 ? 3 + 4 : 5;",
+                assertion: program_eq!(Stmt::Expression(Expr::conditional(
+                    Expr::logical(num(1), LogicalOperator::Or, num(2)),
+                    binary(num(3), Add, num(4), 12),
+                    num(5)
+                ))),
+            },
+            TestCase {
+                name: "success - comma is left associative and binds loosest",
+                input: "1, 2 ? 3 : 4, 5;",
+                assertion: program_eq!(Stmt::Expression(Expr::comma(
+                    Expr::comma(num(1), Expr::conditional(num(2), num(3), num(4))),
+                    num(5)
+                ))),
+            },
+            TestCase {
+                name: "success - commas in a call separate arguments",
+                input: "f(1, (2, 3));",
+                assertion: program_eq!(Stmt::Expression(Expr::call(
+                    Expr::Variable(ident("f", 1, 1)),
+                    vec![num(1), Expr::grouping(Expr::comma(num(2), num(3)))],
+                    Location { line: 1, column: 2 },
+                    span(1, 12)
+                ))),
+            },
+            TestCase {
+                name: "failure - conditional without an else branch",
+                input: "1 ? 2;",
+                assertion: error_msg_eq!(
+                    "parser: expected ':' after the first branch of '?', found ';' at line 1, column 6"
+                ),
+            },
+        )
+    }
+
+    #[test]
+    fn statements() {
+        run_tests!(
+            TestCase {
+                name: "success - print statement",
+                input: "print 1 + 2;",
+                assertion: program_eq!(Stmt::Print(binary(num(1), Add, num(2), 9))),
+            },
+            TestCase {
+                name: "failure - missing semicolon",
+                input: "print 1",
+                assertion: error_msg_eq!("expected ';' after value"),
+            },
+            TestCase {
+                name: "failure - unclosed grouping",
+                input: "(1 + 2;",
+                assertion: error_msg_eq!("expected ')' after expression"),
+            },
+            TestCase {
+                name: "failure - missing operand",
+                input: "1 +;",
+                assertion: error_msg_eq!("expected expression, found ';'"),
+            },
+            TestCase {
+                name: "failure - error location",
+                input: "print 1;\nprint (2;",
+                assertion: error_msg_eq!(
+                    "expected ')' after expression, found ';' at line 2, column 9"
+                ),
+            },
+            TestCase {
+                name: "failure - unexpected end of file",
+                input: "1 *",
+                assertion: error_msg_eq!("expected expression, found end of file"),
+            },
+        )
+    }
+
+    #[test]
+    fn variables() {
+        run_tests!(
+            TestCase {
+                name: "success - declaration with initializer",
+                input: "var x = 1;",
+                assertion: program_eq!(Stmt::Var(ident("x", 1, 5), Some(num(1)))),
+            },
+            TestCase {
+                name: "success - declaration without initializer",
+                input: "var x;",
+                assertion: program_eq!(Stmt::Var(ident("x", 1, 5), None)),
+            },
+            TestCase {
+                name: "success - variable in expression",
+                input: "print x + 1;",
+                assertion: program_eq!(Stmt::Print(binary(
+                    Expr::Variable(ident("x", 1, 7)),
+                    Add,
+                    num(1),
+                    9
+                ))),
+            },
+            TestCase {
+                name: "success - assignment is right associative",
+                input: "a = b = 2;",
+                assertion: program_eq!(Stmt::Expression(Expr::assign(
+                    ident("a", 1, 1),
+                    Expr::assign(ident("b", 1, 5), num(2))
+                ))),
+            },
+            TestCase {
+                name: "failure - missing variable name",
+                input: "var 1 = 2;",
+                assertion: error_msg_eq!("expected variable name, found '1' at line 1, column 5"),
+            },
+            TestCase {
+                name: "failure - invalid assignment target",
+                input: "1 + a = 2;",
+                assertion: error_msg_eq!("invalid assignment target at line 1, column 1"),
+            },
+        )
+    }
+
+    #[test]
+    fn compound_assignment() {
+        let var = |name, column| Expr::Variable(ident(name, 1, column));
+        let at = |column| Location { line: 1, column };
+        run_tests!(
+            TestCase {
+                name: "success - reads and writes the target",
+                input: "a += 1;",
+                assertion: program_eq!(Stmt::Expression(Expr::compound_assign(
+                    var("a", 1),
+                    Add,
+                    num(1),
+                    at(3),
+                    span(2, 4)
+                ))),
+            },
+            TestCase {
+                name: "success - the whole right side is the operand",
+                input: "a *= 2 + 3;",
+                assertion: program_eq!(Stmt::Expression(Expr::compound_assign(
+                    var("a", 1),
+                    Multiply,
+                    binary(num(2), Add, num(3), 8),
+                    at(3),
+                    span(2, 4)
+                ))),
+            },
+            TestCase {
+                name: "success - right associative with plain assignment",
+                input: "a -= b /= c = 2;",
+                assertion: program_eq!(Stmt::Expression(Expr::compound_assign(
+                    var("a", 1),
+                    Subtract,
+                    Expr::compound_assign(
+                        var("b", 6),
+                        Divide,
+                        Expr::assign(ident("c", 1, 11), num(2)),
+                        at(8),
+                        span(7, 9)
+                    ),
+                    at(3),
+                    span(2, 4)
+                ))),
+            },
+            TestCase {
+                name: "success - property target",
+                input: "a.b += 1;",
+                assertion: program_eq!(Stmt::Expression(Expr::compound_assign(
+                    Expr::get(var("a", 1), ident("b", 1, 3), span(1, 3)),
+                    Add,
+                    num(1),
+                    at(5),
+                    span(4, 6)
+                ))),
+            },
+            TestCase {
+                name: "success - index target",
+                input: "a[i] -= 1;",
+                assertion: program_eq!(Stmt::Expression(Expr::compound_assign(
+                    Expr::index(var("a", 1), var("i", 3), at(2), span(1, 4)),
+                    Subtract,
+                    num(1),
+                    at(6),
+                    span(5, 7)
+                ))),
+            },
+            TestCase {
+                name: "failure - invalid target",
+                input: "a + 1 += 2;",
+                assertion: error_msg_eq!("invalid assignment target at line 1, column 1"),
+            },
+        )
+    }
+
+    #[test]
+    fn increment_and_decrement() {
+        use UpdateOperator::*;
+        let var = |name, column| Expr::Variable(ident(name, 1, column));
+        let at = |column| Location { line: 1, column };
+        run_tests!(
+            TestCase {
+                name: "success - prefix",
+                input: "++a;",
+                assertion: program_eq!(Stmt::Expression(Expr::update(
+                    var("a", 3),
+                    Increment,
+                    Fixity::Prefix,
+                    at(1),
+                    span(0, 2)
+                ))),
+            },
+            TestCase {
+                name: "success - postfix on a property",
+                input: "a.b--;",
+                assertion: program_eq!(Stmt::Expression(Expr::update(
+                    Expr::get(var("a", 1), ident("b", 1, 3), span(1, 3)),
+                    Decrement,
+                    Fixity::Postfix,
+                    at(4),
+                    span(3, 5)
+                ))),
+            },
+            TestCase {
+                name: "success - binds tighter than unary and exponent",
+                input: "-a++ ** 2;",
+                assertion: program_eq!(Stmt::Expression(unary(
+                    UnaryOperator::Negate,
+                    binary(
+                        Expr::update(var("a", 2), Increment, Fixity::Postfix, at(3), span(2, 4)),
+                        Power,
+                        num(2),
+                        6
+                    ),
+                    1
+                ))),
+            },
+            TestCase {
+                name: "failure - prefix on a literal",
+                input: "++1;",
+                assertion: error_msg_eq!(
+                    "'++' needs a variable, property or element to change at line 1, column 1"
+                ),
+            },
+            TestCase {
+                name: "failure - postfix on a grouping",
+                input: "print (a)--;",
+                assertion: error_msg_eq!(
+                    "'--' needs a variable, property or element to change at line 1, column 10"
+                ),
+            },
+            TestCase {
+                name: "failure - postfix on a call",
+                input: "f()++;",
+                assertion: error_msg_eq!(
+                    "'++' needs a variable, property or element to change at line 1, column 4"
+                ),
+            },
+            TestCase {
+                name: "failure - applied twice",
+                input: "++a++;",
+                assertion: error_msg_eq!("expected ';' after expression, found '++'"),
+            },
+        )
+    }
+
+    #[test]
+    fn blocks() {
+        run_tests!(
+            TestCase {
+                name: "success - empty block",
+                input: "{}",
+                assertion: program_eq!(Stmt::Block(vec![])),
+            },
+            TestCase {
+                name: "success - nested blocks",
+                input: "{ var a = 1; { print a; } }",
+                assertion: program_eq!(Stmt::Block(vec![
+                    Stmt::Var(ident("a", 1, 7), Some(num(1))),
+                    Stmt::Block(vec![Stmt::Print(Expr::Variable(ident("a", 1, 22)))]),
+                ])),
+            },
+            TestCase {
+                name: "failure - unclosed block",
+                input: "{ print 1;",
+                assertion: error_msg_eq!(
+                    "unexpected end of file, expected '}' to close the block opened at line 1"
+                ),
+            },
+        )
+    }
+
+    #[test]
+    fn conditionals() {
+        let t = || Expr::literal(Literal::Bool(true));
+        let print = |n| Stmt::Print(num(n));
+        run_tests!(
+            TestCase {
+                name: "success - if without else",
+                input: "if (true) print 1;",
+                assertion: program_eq!(Stmt::If(t(), Box::new(print(1)), None)),
+            },
+            TestCase {
+                name: "success - if with else",
+                input: "if (true) print 1; else print 2;",
+                assertion: program_eq!(Stmt::If(t(), Box::new(print(1)), Some(Box::new(print(2))))),
+            },
+            TestCase {
+                name: "success - dangling else binds to nearest if",
+                input: "if (true) if (true) print 1; else print 2;",
+                assertion: program_eq!(Stmt::If(
+                    t(),
+                    Box::new(Stmt::If(t(), Box::new(print(1)), Some(Box::new(print(2))))),
+                    None
+                )),
+            },
+            TestCase {
+                name: "failure - missing parenthesis",
+                input: "if true print 1;",
+                assertion: error_msg_eq!("expected '(' after 'if'"),
+            },
+        )
+    }
+
+    #[test]
+    fn logical_operators() {
+        run_tests!(
+            TestCase {
+                name: "success - and binds tighter than or",
+                input: "1 or 2 and 3;",
+                assertion: program_eq!(Stmt::Expression(Expr::logical(
+                    num(1),
+                    LogicalOperator::Or,
+                    Expr::logical(num(2), LogicalOperator::And, num(3))
+                ))),
+            },
+            TestCase {
+                name: "success - equality binds tighter than and",
+                input: "1 == 2 and 3;",
+                assertion: program_eq!(Stmt::Expression(Expr::logical(
+                    binary(num(1), Equal, num(2), 3),
+                    LogicalOperator::And,
+                    num(3)
+                ))),
+            },
+            TestCase {
+                name: "success - or is left associative",
+                input: "1 or 2 or 3;",
+                assertion: program_eq!(Stmt::Expression(Expr::logical(
+                    Expr::logical(num(1), LogicalOperator::Or, num(2)),
+                    LogicalOperator::Or,
+                    num(3)
+                ))),
+            },
+        )
+    }
+
+    #[test]
+    fn loops() {
+        let t = || Expr::literal(Literal::Bool(true));
+        let i = |column| Expr::Variable(ident("i", 1, column));
+        run_tests!(
+            TestCase {
+                name: "success - while loop",
+                input: "while (true) print 1;",
+                assertion: program_eq!(Stmt::While(t(), Box::new(Stmt::Print(num(1))), None)),
+            },
+            TestCase {
+                name: "success - for loop desugars to while",
+                input: "for (var i = 0; i < 3; i = i + 1) print i;",
+                assertion: program_eq!(Stmt::Block(vec![
+                    Stmt::Var(ident("i", 1, 10), Some(num(0))),
+                    Stmt::While(
+                        binary(i(17), Less, num(3), 19),
+                        Box::new(Stmt::Print(i(41))),
+                        Some(Expr::assign(
+                            ident("i", 1, 24),
+                            binary(i(28), Add, num(1), 30)
+                        ))
+                    ),
+                ])),
+            },
+            TestCase {
+                name: "success - for loop with empty clauses",
+                input: "for (;;) print 1;",
+                assertion: program_eq!(Stmt::While(t(), Box::new(Stmt::Print(num(1))), None)),
+            },
+            TestCase {
+                name: "success - for loop with expression initializer",
+                input: "for (i = 0; false;) {}",
+                assertion: program_eq!(Stmt::Block(vec![
+                    Stmt::Expression(Expr::assign(ident("i", 1, 6), num(0))),
+                    Stmt::While(
+                        Expr::literal(Literal::Bool(false)),
+                        Box::new(Stmt::Block(vec![])),
+                        None
+                    ),
+                ])),
+            },
+            TestCase {
+                name: "failure - missing clause separator",
+                input: "for (var i = 0; i < 3) print i;",
+                assertion: error_msg_eq!("expected ';' after loop condition"),
+            },
+            TestCase {
+                name: "success - break and continue in a loop body",
+                input: "while (true) { if (false) continue; break; }",
+                assertion: program_eq!(Stmt::While(
+                    t(),
+                    Box::new(Stmt::Block(vec![
+                        Stmt::If(
+                            Expr::literal(Literal::Bool(false)),
+                            Box::new(Stmt::Continue),
+                            None
+                        ),
+                        Stmt::Break,
+                    ])),
+                    None
+                )),
+            },
+            TestCase {
+                name: "failure - break outside a loop",
+                input: "if (true) break;",
+                assertion: error_msg_eq!(
+                    "can't use 'break' outside of a loop at line 1, column 11"
+                ),
+            },
+            TestCase {
+                name: "failure - continue in a function inside a loop",
+                input: "while (true) { fun f() { continue; } }",
+                assertion: error_msg_eq!(
+                    "can't use 'continue' outside of a loop at line 1, column 26"
+                ),
+            },
+            TestCase {
+                name: "failure - break after a loop",
+                input: "for (;;) {} break;",
+                assertion: error_msg_eq!(
+                    "can't use 'break' outside of a loop at line 1, column 13"
+                ),
+            },
+            TestCase {
+                name: "failure - missing semicolon after break",
+                input: "while (true) break",
+                assertion: error_msg_eq!("expected ';' after 'break'"),
+            },
+        )
+    }
+
+    #[test]
+    fn interpolation() {
+        let var = |name, column| Expr::Variable(ident(name, 1, column));
+        let text = |s: &str| Segment::Text(s.to_string());
+        run_tests!(
+            TestCase {
+                name: "success - text and values",
+                input: r#""a${x + 1}b${y}c";"#,
+                assertion: program_eq!(Stmt::Expression(Expr::Interpolation(vec![
+                    text("a"),
+                    Segment::Value(binary(var("x", 5), Add, num(1), 7)),
+                    text("b"),
+                    Segment::Value(var("y", 14)),
+                    text("c"),
+                ]))),
+            },
+            TestCase {
+                name: "success - empty text is left out",
+                input: r#""${x}";"#,
+                assertion: program_eq!(Stmt::Expression(Expr::Interpolation(vec![
+                    Segment::Value(var("x", 4))
+                ]))),
+            },
+            TestCase {
+                name: "success - an escaped '${' is text",
+                input: r#""a\${x}${y}";"#,
+                assertion: program_eq!(Stmt::Expression(Expr::Interpolation(vec![
+                    text("a${x}"),
+                    Segment::Value(var("y", 10)),
+                ]))),
+            },
+            TestCase {
+                name: "failure - empty interpolation",
+                input: r#"print "a${}";"#,
+                assertion: error_msg_eq!(
+                    "expected expression inside '${}', found '}\"' at line 1, column 11"
+                ),
+            },
+            TestCase {
+                name: "failure - more than one expression",
+                input: r#"print "${a b}";"#,
+                assertion: error_msg_eq!(
+                    "expected '}' after interpolated expression, found 'b' at line 1, column 12"
+                ),
+            },
+        )
+    }
+
+    #[test]
+    fn lists() {
+        let var = |name, column| Expr::Variable(ident(name, 1, column));
+        let at = |column| Location { line: 1, column };
+        run_tests!(
+            TestCase {
+                name: "success - list literals",
+                input: "[]; [1, (2, 3)];",
+                assertion: program_eq!(
+                    Stmt::Expression(Expr::List(vec![], at(1))),
+                    Stmt::Expression(Expr::List(
+                        vec![num(1), Expr::grouping(Expr::comma(num(2), num(3)))],
+                        at(5)
+                    ))
+                ),
+            },
+            TestCase {
+                name: "success - indexing chains with calls",
+                input: "a[0](b)[1 + 2];",
+                assertion: program_eq!(Stmt::Expression(Expr::index(
+                    Expr::call(
+                        Expr::index(var("a", 1), num(0), at(2), span(1, 4)),
+                        vec![var("b", 6)],
+                        at(5),
+                        span(4, 7)
+                    ),
+                    binary(num(1), Add, num(2), 11),
+                    at(8),
+                    span(7, 14)
+                ))),
+            },
+            TestCase {
+                name: "success - index assignment",
+                input: "a[i] = b[j] = 1;",
+                assertion: program_eq!(Stmt::Expression(Expr::set_index(
+                    var("a", 1),
+                    var("i", 3),
+                    Expr::set_index(var("b", 8), var("j", 10), num(1), at(9), span(8, 11)),
+                    at(2),
+                    span(1, 4)
+                ))),
+            },
+            TestCase {
+                name: "success - slices with and without bounds",
+                input: "a[1:b]; a[:2]; a[1:]; a[:];",
+                assertion: program_eq!(
+                    Stmt::Expression(Expr::slice(
+                        var("a", 1),
+                        Some(num(1)),
+                        Some(var("b", 5)),
+                        at(2),
+                        span(1, 6)
+                    )),
+                    Stmt::Expression(Expr::slice(
+                        var("a", 9),
+                        None,
+                        Some(num(2)),
+                        at(10),
+                        span(9, 13)
+                    )),
+                    Stmt::Expression(Expr::slice(
+                        var("a", 16),
+                        Some(num(1)),
+                        None,
+                        at(17),
+                        span(16, 20)
+                    )),
+                    Stmt::Expression(Expr::slice(var("a", 23), None, None, at(24), span(23, 26)))
+                ),
+            },
+            TestCase {
+                name: "failure - assigning to a slice",
+                input: "a[0:1] = b;",
+                assertion: error_msg_eq!("invalid assignment target"),
+            },
+            TestCase {
+                name: "failure - unclosed index",
+                input: "a[0;",
+                assertion: error_msg_eq!("expected ']' after index, found ';'"),
+            },
+            TestCase {
+                name: "failure - unclosed list",
+                input: "var a = [1, 2;",
+                assertion: error_msg_eq!("expected ']' after list elements, found ';'"),
+            },
+        )
+    }
+
+    #[test]
+    fn maps() {
+        let var = |name, column| Expr::Variable(ident(name, 1, column));
+        let at = |column| Location { line: 1, column };
+        let key = |s: &str| Expr::literal(Literal::String(s.to_string()));
+        run_tests!(
+            TestCase {
+                name: "success - map literals",
+                input: "var a = {}; var b = {\"x\": 1, 2: a ? 3 : 4};",
+                assertion: program_eq!(
+                    Stmt::Var(ident("a", 1, 5), Some(Expr::Map(vec![], at(9)))),
+                    Stmt::Var(
+                        ident("b", 1, 17),
+                        Some(Expr::Map(
+                            vec![
+                                (key("x"), num(1)),
+                                (num(2), Expr::conditional(var("a", 33), num(3), num(4))),
+                            ],
+                            at(21)
+                        ))
+                    )
+                ),
+            },
+            TestCase {
+                name: "success - a brace that starts a statement is a block",
+                input: "{ 1; }",
+                assertion: program_eq!(Stmt::Block(vec![Stmt::Expression(num(1))])),
+            },
+            TestCase {
+                name: "failure - missing colon",
+                input: "var a = {\"x\" 1};",
+                assertion: error_msg_eq!("expected ':' after map key, found '1'"),
+            },
+            TestCase {
+                name: "failure - unclosed map",
+                input: "var a = {1: 2;",
+                assertion: error_msg_eq!("expected '}' after map entries, found ';'"),
+            },
+        )
+    }
+
+    #[test]
+    fn functions() {
+        let var = |name, column| Expr::Variable(ident(name, 1, column));
+        run_tests!(
+            TestCase {
+                name: "success - declaration",
+                input: "fun add(a, b) { return a + b; }",
+                assertion: program_eq!(Stmt::Function(Rc::new(FunctionDecl {
+                    name: ident("add", 1, 5),
+                    params: vec![ident("a", 1, 9), ident("b", 1, 12)],
+                    body: vec![Stmt::Return(Some(binary(
+                        var("a", 24),
+                        Add,
+                        var("b", 28),
+                        26
+                    )))],
+                }))),
+            },
+            TestCase {
+                name: "success - bare return",
+                input: "fun f() { return; }",
+                assertion: program_eq!(Stmt::Function(Rc::new(FunctionDecl {
+                    name: ident("f", 1, 5),
+                    params: vec![],
+                    body: vec![Stmt::Return(None)],
+                }))),
+            },
+            TestCase {
+                name: "success - chained calls",
+                input: "f(1)(2, 3)();",
+                assertion: program_eq!(Stmt::Expression(Expr::call(
+                    Expr::call(
+                        Expr::call(
+                            var("f", 1),
+                            vec![num(1)],
+                            Location { line: 1, column: 2 },
+                            span(1, 4)
+                        ),
+                        vec![num(2), num(3)],
+                        Location { line: 1, column: 5 },
+                        span(4, 10)
+                    ),
+                    vec![],
+                    Location {
+                        line: 1,
+                        column: 11
+                    },
+                    span(10, 12)
+                ))),
+            },
+            TestCase {
+                name: "failure - return at top level",
+                input: "return 1;",
+                assertion: error_msg_eq!("can't return from top-level code at line 1, column 1"),
+            },
+            TestCase {
+                name: "failure - unclosed argument list",
+                input: "f(1, 2;",
+                assertion: error_msg_eq!("expected ')' after arguments"),
+            },
+            TestCase {
+                name: "failure - missing function body",
+                input: "fun f();",
+                assertion: error_msg_eq!("expected '{' before function body"),
+            },
+        )
+    }
+
+    #[test]
+    fn classes() {
+        let method = |name, column, body| {
+            Rc::new(FunctionDecl {
+                name: ident(name, 1, column),
+                params: vec![],
+                body,
+            })
+        };
+        run_tests!(
+            TestCase {
+                name: "success - class with methods",
+                input: "class A { init() { this.x = 1; } get() { return this.x; } }",
+                assertion: program_eq!(Stmt::Class(Rc::new(ClassDecl {
+                    name: ident("A", 1, 7),
+                    superclass: None,
+                    fields: vec![],
+                    methods: vec![
+                        method(
+                            "init",
+                            11,
+                            vec![Stmt::Expression(Expr::set(
+                                Expr::This(Location {
+                                    line: 1,
+                                    column: 20
+                                }),
+                                ident("x", 1, 25),
+                                num(1),
+                                span(23, 25)
+                            ))]
+                        ),
+                        method(
+                            "get",
+                            34,
+                            vec![Stmt::Return(Some(Expr::get(
+                                Expr::This(Location {
+                                    line: 1,
+                                    column: 49
+                                }),
+                                ident("x", 1, 54),
+                                span(52, 54)
+                            )))]
+                        ),
+                    ],
+                }))),
+            },
+            TestCase {
+                name: "success - property chains",
+                input: "a.b.c = d.e();",
+                assertion: program_eq!(Stmt::Expression(Expr::set(
+                    Expr::get(
+                        Expr::Variable(ident("a", 1, 1)),
+                        ident("b", 1, 3),
+                        span(1, 3)
+                    ),
+                    ident("c", 1, 5),
+                    Expr::call(
+                        Expr::get(
+                            Expr::Variable(ident("d", 1, 9)),
+                            ident("e", 1, 11),
+                            span(9, 11)
+                        ),
+                        vec![],
+                        Location {
+                            line: 1,
+                            column: 12
+                        },
+                        span(11, 13)
+                    ),
+                    span(3, 5)
+                ))),
+            },
+            TestCase {
+                name: "success - bare return in initializer",
+                input: "class A { init() { return; } }",
+                assertion: program_eq!(Stmt::Class(Rc::new(ClassDecl {
+                    name: ident("A", 1, 7),
+                    superclass: None,
+                    fields: vec![],
+                    methods: vec![method("init", 11, vec![Stmt::Return(None)])],
+                }))),
+            },
+            TestCase {
+                name: "success - fields with initializers",
+                input: "class P { x = 0; y = this.x; init() {} }",
+                assertion: program_eq!(Stmt::Class(Rc::new(ClassDecl {
+                    name: ident("P", 1, 7),
+                    superclass: None,
+                    fields: vec![
+                        FieldDecl {
+                            name: ident("x", 1, 11),
+                            initializer: num(0),
+                        },
+                        FieldDecl {
+                            name: ident("y", 1, 18),
+                            initializer: Expr::get(
+                                Expr::This(Location {
+                                    line: 1,
+                                    column: 22
+                                }),
+                                ident("x", 1, 27),
+                                span(25, 27)
+                            ),
+                        },
+                    ],
+                    methods: vec![method("init", 30, vec![])],
+                }))),
+            },
+            TestCase {
+                name: "failure - field without a semicolon",
+                input: "class P { x = 0 y = 1; }",
+                assertion: error_msg_eq!("expected ';' after field initializer, found 'y'"),
+            },
+            TestCase {
+                name: "failure - this outside of a class",
+                input: "print this;",
+                assertion: error_msg_eq!("can't use 'this' outside of a class at line 1, column 7"),
+            },
+            TestCase {
+                name: "failure - this in a function outside of a class",
+                input: "fun f() { return this; }",
+                assertion: error_msg_eq!("can't use 'this' outside of a class"),
+            },
+            TestCase {
+                name: "failure - return a value from an initializer",
+                input: "class A { init() { return 1; } }",
+                assertion: error_msg_eq!(
+                    "can't return a value from an initializer at line 1, column 27"
+                ),
+            },
+            TestCase {
+                name: "failure - missing property name",
+                input: "a.1;",
+                assertion: error_msg_eq!("expected property name after '.'"),
+            },
+            TestCase {
+                name: "failure - unclosed class body",
+                input: "class A { f() {}",
+                assertion: error_msg_eq!("expected '}' to close the class body opened at line 1"),
+            },
+        )
+    }
+
+    #[test]
+    fn inheritance() {
+        run_tests!(
+            TestCase {
+                name: "success - subclass calling super",
+                input: "class B < A { f() { return super.f; } }",
+                assertion: program_eq!(Stmt::Class(Rc::new(ClassDecl {
+                    name: ident("B", 1, 7),
+                    superclass: Some(ident("A", 1, 11)),
+                    fields: vec![],
+                    methods: vec![Rc::new(FunctionDecl {
+                        name: ident("f", 1, 15),
+                        params: vec![],
+                        body: vec![Stmt::Return(Some(Expr::Super(
+                            Location {
+                                line: 1,
+                                column: 28
+                            },
+                            ident("f", 1, 34)
+                        )))],
+                    })],
+                }))),
+            },
+            TestCase {
+                name: "failure - inherit from itself",
+                input: "class A < A {}",
+                assertion: error_msg_eq!("a class can't inherit from itself at line 1, column 11"),
+            },
+            TestCase {
+                name: "failure - missing superclass name",
+                input: "class A < {}",
+                assertion: error_msg_eq!("expected superclass name"),
+            },
+            TestCase {
+                name: "failure - super outside of a class",
+                input: "super.f();",
+                assertion: error_msg_eq!(
+                    "can't use 'super' outside of a class at line 1, column 1"
+                ),
+            },
+            TestCase {
+                name: "failure - super without a superclass",
+                input: "class A { f() { super.f(); } }",
+                assertion: error_msg_eq!("can't use 'super' in a class with no superclass"),
+            },
+            TestCase {
+                name: "failure - super without a method",
+                input: "class B < A { f() { super; } }",
+                assertion: error_msg_eq!("expected '.' after 'super'"),
+            },
+        )
+    }
+
+    #[test]
+    fn reports_every_syntax_error() {
+        type Reported<'a> = &'a [(&'a str, Span)];
+        let cases: &[(&str, &str, Reported)] = &[
+            (
+                "top-level statements",
+                "var = 1;\nprint 2;\nprint (3;\nprint 4 5;\nclass A < A {}",
+                &[
+                    (
+                        "parser: expected variable name, found '=' at line 1, column 5",
+                        Span { start: 4, end: 5 },
+                    ),
+                    (
+                        "parser: expected ')' after expression, found ';' at line 3, column 9",
+                        Span { start: 26, end: 27 },
+                    ),
+                    (
+                        "parser: expected ';' after value, found '5' at line 4, column 9",
+                        Span { start: 36, end: 37 },
+                    ),
+                    (
+                        "parser: a class can't inherit from itself at line 5, column 11",
+                        Span { start: 49, end: 50 },
+                    ),
+                ],
+            ),
+            (
+                "recovers inside a function body",
+                "fun f() { print ; return 1; }\nprint 1 2;",
+                &[
+                    (
+                        "parser: expected expression, found ';' at line 1, column 17",
+                        Span { start: 16, end: 17 },
+                    ),
+                    (
+                        "parser: expected ';' after value, found '2' at line 2, column 9",
+                        Span { start: 38, end: 39 },
+                    ),
+                ],
+            ),
+        ];
+        for (name, source, expected) in cases {
+            let error = parse(&scan(source).unwrap()).unwrap_err();
+            let Some(Diagnostics(diagnostics)) = error.downcast_ref::<Diagnostics>() else {
+                panic!("expected diagnostics, got: {}", error);
+            };
+            let reported: Vec<(&str, Span)> = diagnostics
+                .iter()
+                .map(|d| (d.message.as_str(), d.span))
+                .collect();
+            assert_eq!(reported, *expected, "case: {}", name);
+        }
+    }
+
+    #[test]
+    fn unclosed_delimiters_point_back_at_the_opener() {
+        let source = "fun f() {\n  print (1 +\n";
+        let error = parse(&scan(source).unwrap()).unwrap_err();
+        let Some(Diagnostics(diagnostics)) = error.downcast_ref::<Diagnostics>() else {
+            panic!("expected diagnostics, got: {}", error);
+        };
+        type Reported<'a> = (&'a str, Span, Vec<(&'a str, Span)>);
+        let reported: Vec<Reported> = diagnostics
+            .iter()
+            .map(|d| {
+                let labels = d
+                    .labels
+                    .iter()
+                    .map(|l| (l.message.as_str(), l.span))
+                    .collect();
+                (d.message.as_str(), d.span, labels)
+            })
+            .collect();
+        assert_eq!(
+            reported,
+            vec![
+                (
+                    "parser: expected expression, found end of file at line 3, column 1",
+                    span(23, 23),
+                    vec![],
+                ),
+                (
+                    "parser: unexpected end of file, expected '}' to close the block opened at line 1",
+                    span(23, 23),
+                    vec![("the block opened here", span(8, 9))],
+                ),
+            ]
+        );
+
+        let error = parse(&scan("print [1, (2").unwrap()).unwrap_err();
+        let Some(Diagnostics(diagnostics)) = error.downcast_ref::<Diagnostics>() else {
+            panic!("expected diagnostics, got: {}", error);
+        };
+        assert_eq!(
+            diagnostics[0].message,
+            "parser: unexpected end of file, expected ')' to close the parenthesis opened at line 1"
+        );
+        assert_eq!(diagnostics[0].labels[0].span, span(10, 11));
+    }
+}
@@ -0,0 +1,186 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::hooks::Hooks;
+use crate::value::Value;
+
+/// Hooks that count calls and time spent per function. Clones share their
+/// counts, so keep one to read the summary from after giving another to
+/// `Interpreter::set_hooks`.
+#[derive(Clone, Default)]
+pub struct Profiler {
+    state: Rc<RefCell<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    functions: HashMap<String, FunctionProfile>,
+    // The calls in progress, outermost first.
+    stack: Vec<Call>,
+}
+
+struct Call {
+    function: String,
+    start: Instant,
+    // Time spent in calls this one made, which is not its exclusive time.
+    in_callees: Duration,
+}
+
+/// The totals for one function, or class called as a constructor.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FunctionProfile {
+    pub function: String,
+    pub calls: usize,
+    /// Time from call to return, counted once for recursive calls.
+    pub inclusive: Duration,
+    /// Inclusive time minus the time spent in the calls it made.
+    pub exclusive: Duration,
+}
+
+impl Profiler {
+    /// Every function that returned at least once, by most exclusive time
+    /// first. Calls still in progress, such as those a runtime error
+    /// interrupted, are left out.
+    pub fn summary(&self) -> Vec<FunctionProfile> {
+        let mut functions: Vec<FunctionProfile> =
+            self.state.borrow().functions.values().cloned().collect();
+        functions.sort_by(|a, b| {
+            b.exclusive
+                .cmp(&a.exclusive)
+                .then_with(|| a.function.cmp(&b.function))
+        });
+        functions
+    }
+}
+
+impl Hooks for Profiler {
+    fn on_call(&mut self, function: &Value, _arguments: &[Value]) {
+        self.state.borrow_mut().stack.push(Call {
+            function: function.to_string(),
+            start: Instant::now(),
+            in_callees: Duration::ZERO,
+        });
+    }
+
+    fn on_return(&mut self, _function: &Value, _value: &Value) {
+        let mut state = self.state.borrow_mut();
+        let Some(call) = state.stack.pop() else {
+            return;
+        };
+        let elapsed = call.start.elapsed();
+        let recursive = state.stack.iter().any(|c| c.function == call.function);
+        if let Some(caller) = state.stack.last_mut() {
+            caller.in_callees += elapsed;
+        }
+
+        let profile = state
+            .functions
+            .entry(call.function.clone())
+            .or_insert_with(|| FunctionProfile {
+                function: call.function,
+                calls: 0,
+                inclusive: Duration::ZERO,
+                exclusive: Duration::ZERO,
+            });
+        profile.calls += 1;
+        if !recursive {
+            profile.inclusive += elapsed;
+        }
+        profile.exclusive += elapsed.saturating_sub(call.in_callees);
+    }
+}
+
+/// One line per function, times in milliseconds.
+pub fn to_table(functions: &[FunctionProfile]) -> String {
+    let width = functions
+        .iter()
+        .map(|f| f.function.len())
+        .chain(["function".len()])
+        .max()
+        .unwrap_or(0);
+    let mut table = format!(
+        "{:width$}  {:>8}  {:>10}  {:>10}\n",
+        "function", "calls", "incl ms", "excl ms"
+    );
+    for f in functions {
+        table += &format!(
+            "{:width$}  {:8}  {:10.3}  {:10.3}\n",
+            f.function,
+            f.calls,
+            f.inclusive.as_secs_f64() * 1000.0,
+            f.exclusive.as_secs_f64() * 1000.0
+        );
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::parser::parse;
+    use crate::scanner::scan;
+
+    fn profile(source: &str) -> Vec<FunctionProfile> {
+        let profiler = Profiler::default();
+        let mut interpreter = Interpreter::with_output(io::sink());
+        interpreter.set_hooks(profiler.clone());
+        let _ = interpreter.interpret(&parse(&scan(source).unwrap()).unwrap());
+        profiler.summary()
+    }
+
+    #[test]
+    fn counts_calls_per_function() {
+        let mut functions = profile(
+            "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }\n\
+             fun run() { return fib(10) + len(\"ab\"); }\n\
+             run(); run();",
+        );
+        functions.sort_by(|a, b| a.function.cmp(&b.function));
+        let calls: Vec<(&str, usize)> = functions
+            .iter()
+            .map(|f| (f.function.as_str(), f.calls))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                ("<fn fib>", 2 * 177),
+                ("<fn run>", 2),
+                ("<native fn len>", 2)
+            ]
+        );
+
+        let [fib, run, _] = functions.as_slice() else {
+            unreachable!();
+        };
+        // Recursion is not counted twice, and run's time includes fib's.
+        assert!(fib.inclusive <= run.inclusive);
+        assert!(fib.exclusive <= fib.inclusive);
+        assert!(run.exclusive <= run.inclusive - fib.inclusive);
+    }
+
+    #[test]
+    fn leaves_out_calls_an_error_interrupted() {
+        let functions = profile("fun ok() {} fun bad() { ok(); return nil + 1; } bad();");
+        let names: Vec<&str> = functions.iter().map(|f| f.function.as_str()).collect();
+        assert_eq!(names, ["<fn ok>"]);
+    }
+
+    #[test]
+    fn writes_a_table() {
+        let functions = [FunctionProfile {
+            function: "<fn fib>".to_string(),
+            calls: 177,
+            inclusive: Duration::from_micros(2500),
+            exclusive: Duration::from_micros(1250),
+        }];
+        assert_eq!(
+            to_table(&functions),
+            "function     calls     incl ms     excl ms\n<fn fib>       177       2.500       1.250\n"
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::ast::Location;
+use crate::token::Span;
+
+/// An error raised while running a program.
+#[derive(Debug, PartialEq)]
+pub struct RuntimeError {
+    /// What went wrong, without the "interpreter: " prefix or location.
+    pub message: String,
+    /// Where it went wrong, when the interpreter knows.
+    pub location: Option<Location>,
+    /// The source the error is about, for errors raised by one link of a
+    /// call or property chain.
+    pub span: Option<Span>,
+    /// The calls the error unwound through, innermost first.
+    pub trace: Vec<Frame>,
+}
+
+/// A call that was in progress when a runtime error was raised.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Frame {
+    /// The callee as it prints, e.g. `<fn f>`.
+    pub function: String,
+    /// Where the call was made.
+    pub location: Location,
+}
+
+impl RuntimeError {
+    pub fn new(message: String, location: Option<Location>) -> Self {
+        Self {
+            message,
+            location,
+            span: None,
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn with_span(self, span: Span) -> Self {
+        Self {
+            span: Some(span),
+            ..self
+        }
+    }
+
+    /// Recovers the `RuntimeError` inside `error`, or wraps an error raised
+    /// elsewhere (an I/O error, or one from a native function) in a new one.
+    pub fn from_error(error: anyhow::Error) -> Self {
+        match error.downcast::<RuntimeError>() {
+            Ok(error) => error,
+            Err(error) => {
+                let message = format!("{:#}", error);
+                let message = message.strip_prefix("interpreter: ").unwrap_or(&message);
+                Self::new(message.to_string(), None)
+            }
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "interpreter: {}", self.message)?;
+        if let Some(location) = self.location {
+            write!(f, " {}", location)?;
+        }
+        for frame in &self.trace {
+            write!(f, "\n  in {} called {}", frame.function, frame.location)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for RuntimeError {}
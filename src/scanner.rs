@@ -1,88 +1,214 @@
-use std::mem;
+use std::iter;
 
-use anyhow::{Result, bail};
+use anyhow::Result;
 
-use crate::token::{Token, TokenKind};
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::token::{Span, Token, TokenKind};
 
 pub fn scan(input: &str) -> Result<Vec<Token>> {
     Scanner::new(input).scan()
 }
 
-struct Scanner {
-    input: Vec<char>,
-    current: usize,
-    tokens: Vec<Token>,
+/// Scans `input` lazily, one token each time the iterator is advanced. The
+/// iterator borrows `input` rather than copying it. An error does not end
+/// the scan: the token after it comes next. The last token is always
+/// `EndOfFile`.
+pub fn tokens(input: &str) -> impl Iterator<Item = Result<Token>> {
+    let mut scanner = Scanner::new(input);
+    iter::from_fn(move || {
+        let next = scanner.next_token()?;
+        Some(next.map_err(|diagnostic| Diagnostics(vec![diagnostic]).into()))
+    })
+}
+
+struct Scanner<'a> {
+    input: &'a str,
+    position: Position,
+    token_start: Position,
+    // The token or error the last call to `scan_next_token` produced.
+    pending: Option<Result<Token, Diagnostic>>,
+    finished: bool,
+    // The `${` whose expression is being scanned, if any.
+    interpolation: Option<Interpolation>,
+}
+
+struct Interpolation {
+    start: Position,
+    // The opening quote of the string it is in.
+    quote: Position,
+    // How many `{` inside the expression are still open; the `}` that
+    // closes the interpolation comes when there are none.
+    braces: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Position {
+    byte: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    fn start() -> Self {
+        Self {
+            byte: 0,
+            line: 1,
+            column: 1,
+        }
+    }
 }
 
 fn equals(ch: char) -> impl Fn(char) -> bool {
-    let predicate = move |c| c == ch;
-    predicate
+    move |c| c == ch
 }
 
 fn is_numeric() -> impl Fn(char) -> bool {
-    let predicate = move |c: char| c.is_numeric();
-    predicate
+    move |c: char| c.is_numeric()
 }
 
-fn is_alphabetic() -> impl Fn(char) -> bool {
-    let predicate = move |c: char| c.is_alphabetic();
-    predicate
+// The ASCII characters `char::is_whitespace` accepts.
+fn is_ascii_whitespace(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b'\r' | b'\x0b' | b'\x0c')
 }
 
-fn is_whitespace() -> impl Fn(char) -> bool {
-    let predicate = move |c: char| c.is_whitespace();
-    predicate
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
 }
 
-fn is_not_newline() -> impl Fn(char) -> bool {
-    let predicate = move |c: char| c != '\n';
-    predicate
+fn is_identifier() -> impl Fn(char) -> bool {
+    move |c: char| c.is_alphanumeric() || c == '_'
 }
 
-fn is_not_double_quote() -> impl Fn(char) -> bool {
-    let predicate = move |c: char| c != '"';
-    predicate
-}
+// Reserved words; any other word scans as an identifier.
+pub(crate) const KEYWORDS: &[(&str, TokenKind)] = &[
+    ("and", TokenKind::And),
+    ("break", TokenKind::Break),
+    ("class", TokenKind::Class),
+    ("continue", TokenKind::Continue),
+    ("else", TokenKind::Else),
+    ("false", TokenKind::False),
+    ("for", TokenKind::For),
+    ("fun", TokenKind::Fun),
+    ("if", TokenKind::If),
+    ("nil", TokenKind::Nil),
+    ("or", TokenKind::Or),
+    ("print", TokenKind::Print),
+    ("return", TokenKind::Return),
+    ("super", TokenKind::Super),
+    ("this", TokenKind::This),
+    ("true", TokenKind::True),
+    ("var", TokenKind::Var),
+    ("while", TokenKind::While),
+];
 
-impl Scanner {
-    fn new(input: &str) -> Self {
+impl<'a> Scanner<'a> {
+    fn new(input: &'a str) -> Self {
         Self {
-            input: input.to_string().chars().collect(),
-            current: 0,
-            tokens: Vec::new(),
+            input,
+            position: Position::start(),
+            token_start: Position::start(),
+            pending: None,
+            finished: false,
+            interpolation: None,
         }
     }
 
+    // Keeps scanning past errors so that every problem in the file is
+    // reported at once.
     fn scan(&mut self) -> Result<Vec<Token>> {
-        loop {
-            self.scan_next_token()?;
-            if self.is_at_end() {
+        let mut tokens = Vec::new();
+        let mut diagnostics = Vec::new();
+        while let Some(next) = self.next_token() {
+            match next {
+                Ok(token) => tokens.push(token),
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
+        }
+        if !diagnostics.is_empty() {
+            return Err(Diagnostics(diagnostics).into());
+        }
+        Ok(tokens)
+    }
+
+    // Scans just far enough to produce the next token or error, or `None`
+    // once the input is exhausted.
+    fn next_token(&mut self) -> Option<Result<Token, Diagnostic>> {
+        while self.pending.is_none() && !self.finished {
+            self.scan_next_token();
+            if self.pending.is_none() && self.is_at_end() {
+                self.finished = true;
+                self.token_start = self.position;
                 self.add_token(TokenKind::EndOfFile);
-                return Ok(mem::take(&mut self.tokens));
             }
         }
+        self.pending.take()
     }
 
-    fn scan_next_token(&mut self) -> Result<()> {
+    fn scan_next_token(&mut self) {
         self.consume_whitespace();
         if self.is_at_end() {
-            return Ok(());
+            if let Some(interpolation) = self.interpolation.take() {
+                self.report_unterminated(interpolation);
+            }
+            return;
         }
 
+        self.token_start = self.position;
         let ch = self.eat_next();
         match ch {
             '(' => self.add_token(TokenKind::LeftParen),
             ')' => self.add_token(TokenKind::RightParen),
-            '{' => self.add_token(TokenKind::LeftBrace),
-            '}' => self.add_token(TokenKind::RightBrace),
+            '{' => {
+                if let Some(interpolation) = &mut self.interpolation {
+                    interpolation.braces += 1;
+                }
+                self.add_token(TokenKind::LeftBrace)
+            }
+            '[' => self.add_token(TokenKind::LeftBracket),
+            ']' => self.add_token(TokenKind::RightBracket),
+            '}' => match &mut self.interpolation {
+                Some(Interpolation {
+                    braces: 0, quote, ..
+                }) => {
+                    let quote = *quote;
+                    self.interpolation = None;
+                    self.string(quote);
+                }
+                Some(interpolation) => {
+                    interpolation.braces -= 1;
+                    self.add_token(TokenKind::RightBrace)
+                }
+                None => self.add_token(TokenKind::RightBrace),
+            },
             ',' => self.add_token(TokenKind::Comma),
+            '?' => self.add_token(TokenKind::Question),
+            ':' => self.add_token(TokenKind::Colon),
             '.' => self.add_token(TokenKind::Dot),
             ';' => self.add_token(TokenKind::Semicolon),
-            '-' => self.add_token(TokenKind::Minus),
-            '+' => self.add_token(TokenKind::Plus),
-            '*' => self.add_token(TokenKind::Star),
-            '/' => match self.try_eat_next(equals('/')) {
-                Some(_) => self.consume_single_line_comment(),
+            '-' => match self.try_eat_next(|c| c == '-' || c == '=') {
+                Some('-') => self.add_token(TokenKind::MinusMinus),
+                Some(_) => self.add_token(TokenKind::MinusEqual),
+                None => self.add_token(TokenKind::Minus),
+            },
+            '+' => match self.try_eat_next(|c| c == '+' || c == '=') {
+                Some('+') => self.add_token(TokenKind::PlusPlus),
+                Some(_) => self.add_token(TokenKind::PlusEqual),
+                None => self.add_token(TokenKind::Plus),
+            },
+            '*' => match self.try_eat_next(|c| c == '*' || c == '=') {
+                Some('*') => self.add_token(TokenKind::StarStar),
+                Some(_) => self.add_token(TokenKind::StarEqual),
+                None => self.add_token(TokenKind::Star),
+            },
+            '%' => self.add_token(TokenKind::Percent),
+            '&' => self.add_token(TokenKind::Ampersand),
+            '|' => self.add_token(TokenKind::Pipe),
+            '^' => self.add_token(TokenKind::Caret),
+            '~' => self.add_token(TokenKind::Tilde),
+            '/' => match self.try_eat_next(|c| c == '/' || c == '*' || c == '=') {
+                Some('/') => self.consume_single_line_comment(),
+                Some('*') => self.consume_block_comment(),
+                Some(_) => self.add_token(TokenKind::SlashEqual),
                 None => self.add_token(TokenKind::Slash),
             },
             '!' => match self.try_eat_next(equals('=')) {
@@ -93,84 +219,211 @@ impl Scanner {
                 Some(_) => self.add_token(TokenKind::EqualEqual),
                 None => self.add_token(TokenKind::Equal),
             },
-            '<' => match self.try_eat_next(equals('=')) {
+            '<' => match self.try_eat_next(|c| c == '<' || c == '=') {
+                Some('<') => self.add_token(TokenKind::LessLess),
                 Some(_) => self.add_token(TokenKind::LessEqual),
                 None => self.add_token(TokenKind::Less),
             },
-            '>' => match self.try_eat_next(equals('=')) {
+            '>' => match self.try_eat_next(|c| c == '>' || c == '=') {
+                Some('>') => self.add_token(TokenKind::GreaterGreater),
                 Some(_) => self.add_token(TokenKind::GreaterEqual),
                 None => self.add_token(TokenKind::Greater),
             },
-            '"' => {
-                let start = self.current;
-
-                self.consume_while(is_not_double_quote());
-                if self.is_at_end() {
-                    // FIXME: better error message for unterminated strings
-                    bail!("unterminated string")
-                }
-                self.advance();
-
-                let end = self.current - 1;
-
-                let string = self.input[start..end].iter().collect();
-                self.add_token(TokenKind::String(string));
-            }
+            '"' => self.string(self.token_start),
             ch if ch.is_numeric() => {
                 let mut literal = String::from(ch);
                 while let Some(ch) = self.try_eat_next(is_numeric()) {
                     literal.push(ch);
                 }
-                let number = literal.parse::<i32>()?;
-                self.add_token(TokenKind::Number(number))
+                match literal.parse::<i32>() {
+                    Ok(number) => self.add_token(TokenKind::Number(number)),
+                    Err(_) => {
+                        let message = format!(
+                            "scanner: number {} is out of range {}",
+                            literal,
+                            self.token_location()
+                        );
+                        self.report(message)
+                    }
+                }
             }
-            ch if ch.is_alphabetic() => {
+            ch if is_identifier_start(ch) => {
                 let mut word = String::from(ch);
-                while let Some(ch) = self.try_eat_next(is_alphabetic()) {
+                while let Some(ch) = self.try_eat_next(is_identifier()) {
                     word.push(ch);
                 }
 
-                match word.as_str() {
-                    "and" => self.add_token(TokenKind::And),
-                    "class" => self.add_token(TokenKind::Class),
-                    "else" => self.add_token(TokenKind::Else),
-                    "false" => self.add_token(TokenKind::False),
-                    "for" => self.add_token(TokenKind::For),
-                    "fun" => self.add_token(TokenKind::Fun),
-                    "if" => self.add_token(TokenKind::If),
-                    "nil" => self.add_token(TokenKind::Nil),
-                    "or" => self.add_token(TokenKind::Or),
-                    "print" => self.add_token(TokenKind::Print),
-                    "return" => self.add_token(TokenKind::Return),
-                    "super" => self.add_token(TokenKind::Super),
-                    "this" => self.add_token(TokenKind::This),
-                    "true" => self.add_token(TokenKind::True),
-                    "var" => self.add_token(TokenKind::Var),
-                    "while" => self.add_token(TokenKind::While),
-                    _ => bail!("unrecognized keyword: {}", word),
+                let kind = match KEYWORDS.iter().find(|(keyword, _)| *keyword == word) {
+                    Some((_, kind)) => kind.clone(),
+                    None => TokenKind::Identifier(word),
+                };
+                self.add_token(kind)
+            }
+            _ => {
+                let message = format!(
+                    "scanner: unrecognized token: '{}' {}",
+                    ch,
+                    self.token_location()
+                );
+                self.report(message)
+            }
+        }
+    }
+
+    // Scans the text of a string from just after its opening `"`, or after
+    // the `}` of an interpolation in it, up to the closing `"` or the next
+    // `${`. `quote` is where the string opened.
+    fn string(&mut self, quote: Position) {
+        let mut text = String::new();
+        // Where a `${` appeared in a string inside an interpolation.
+        let mut nested = None;
+        loop {
+            if self.is_at_end() {
+                // A string that is open inside an interpolation most likely
+                // means the `}` was left out, not the closing quote.
+                return match self.interpolation.take() {
+                    Some(interpolation) => self.report_unterminated(interpolation),
+                    None => {
+                        self.token_start = quote;
+                        let message = format!(
+                            "scanner: unterminated string starting {}",
+                            self.token_location()
+                        );
+                        self.report(message)
+                    }
+                };
+            }
+            let position = self.position;
+            match self.eat_next() {
+                '"' => break,
+                // A backslash makes the `${` after it part of the text.
+                '\\' if self.rest().starts_with("${") => {
+                    self.advance();
+                    self.advance();
+                    text.push_str("${");
                 }
+                '$' if self.try_eat_next(equals('{')).is_some() => {
+                    if self.interpolation.is_none() {
+                        self.interpolation = Some(Interpolation {
+                            start: position,
+                            quote,
+                            braces: 0,
+                        });
+                        return self.add_token(TokenKind::Interpolation(text));
+                    }
+                    nested.get_or_insert(position);
+                    text.push_str("${");
+                }
+                ch => text.push(ch),
             }
-            _ => bail!("scanner: unrecognized token: '{}'", ch),
         }
+        match nested {
+            Some(position) => {
+                let message = format!(
+                    "scanner: interpolations can't be nested, found '${{' at line {}, column {} in a string inside one",
+                    position.line, position.column
+                );
+                self.report(message)
+            }
+            None => self.add_token(TokenKind::String(text)),
+        }
+    }
 
-        Ok(())
+    fn report_unterminated(&mut self, interpolation: Interpolation) {
+        self.token_start = interpolation.start;
+        let message = format!(
+            "scanner: unterminated interpolation, '${{' {} has no closing '}}'",
+            self.token_location()
+        );
+        self.report(message)
     }
 
+    // Whitespace and line comments make up most of a typical source file,
+    // so both are skipped by searching the bytes of the input instead of
+    // going through `try_eat_next` one character at a time.
     fn consume_whitespace(&mut self) {
-        self.consume_while(is_whitespace())
+        loop {
+            let bytes = self.rest().as_bytes();
+            let ascii = bytes
+                .iter()
+                .position(|&b| !is_ascii_whitespace(b))
+                .unwrap_or(bytes.len());
+            self.skip(ascii);
+            // Whitespace outside ASCII is rare, so it is taken one at a time.
+            match self.rest().chars().next() {
+                Some(c) if !c.is_ascii() && c.is_whitespace() => self.advance(),
+                _ => return,
+            }
+        }
     }
 
     fn consume_single_line_comment(&mut self) {
-        self.consume_while(is_not_newline())
+        // `str::find` with a `char` is a memchr over the bytes, reading a
+        // word at a time.
+        let rest = self.rest();
+        self.skip(rest.find('\n').unwrap_or(rest.len()))
     }
 
-    fn consume_while(&mut self, predicate: impl Fn(char) -> bool) {
-        while self.try_eat_next(&predicate).is_some() {}
+    // Block comments nest, so `/* a /* b */ c */` is a single comment.
+    fn consume_block_comment(&mut self) {
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end() {
+                let message = format!(
+                    "scanner: unterminated block comment starting {}",
+                    self.token_location()
+                );
+                return self.report(message);
+            }
+            match self.eat_next() {
+                '/' if self.try_eat_next(equals('*')).is_some() => depth += 1,
+                '*' if self.try_eat_next(equals('/')).is_some() => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    // Moves past the next `len` bytes, which must end where a character does.
+    fn skip(&mut self, len: usize) {
+        let skipped = &self.rest()[..len];
+
+        self.position.byte += len;
+        match skipped.rfind('\n') {
+            Some(last_newline) => {
+                self.position.line += skipped.bytes().filter(|&b| b == b'\n').count();
+                self.position.column = skipped[last_newline + 1..].chars().count() + 1;
+            }
+            None => self.position.column += skipped.chars().count(),
+        }
     }
 
     fn add_token(&mut self, kind: TokenKind) {
-        let new_token = Token { kind };
-        self.tokens.push(new_token)
+        let new_token = Token {
+            kind,
+            line: self.token_start.line,
+            column: self.token_start.column,
+            span: Span {
+                start: self.token_start.byte,
+                end: self.position.byte,
+            },
+            lexeme: self.input[self.token_start.byte..self.position.byte].to_string(),
+        };
+        self.pending = Some(Ok(new_token));
+    }
+
+    fn report(&mut self, message: String) {
+        let span = Span {
+            start: self.token_start.byte,
+            end: self.position.byte,
+        };
+        self.pending = Some(Err(Diagnostic::error(message, span)));
+    }
+
+    fn token_location(&self) -> String {
+        format!(
+            "at line {}, column {}",
+            self.token_start.line, self.token_start.column
+        )
     }
 
     fn eat_next(&mut self) -> char {
@@ -193,23 +446,37 @@ impl Scanner {
         None
     }
 
+    // What is left of `input` to scan.
+    fn rest(&self) -> &'a str {
+        &self.input[self.position.byte..]
+    }
+
     fn next(&self) -> char {
-        self.input[self.current]
+        self.rest().chars().next().unwrap()
     }
 
     fn advance(&mut self) {
-        self.current += 1
+        let ch = self.next();
+        self.position.byte += ch.len_utf8();
+        if ch == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
     }
 
     fn is_at_end(&self) -> bool {
-        self.current >= self.input.len()
+        self.position.byte >= self.input.len()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::diagnostic::Severity;
     use TokenKind::*;
+    use anyhow::bail;
 
     struct TestCase {
         name: &'static str,
@@ -229,8 +496,7 @@ mod tests {
         fn check(&self, result: &Result<Vec<Token>>) -> Result<()> {
             match result {
                 Ok(tokens) => {
-                    let actual: Vec<TokenKind> =
-                        tokens.into_iter().map(|t| t.kind.clone()).collect();
+                    let actual: Vec<TokenKind> = tokens.iter().map(|t| t.kind.clone()).collect();
 
                     if actual == self.expected {
                         Ok(())
@@ -410,9 +676,9 @@ mod tests {
                 assertion: token_kinds_eq!(Number(12), Plus, Number(345), EndOfFile),
             },
             TestCase {
-                name: "failure - invalid character after a number",
+                name: "success - identifier after a number",
                 input: "0d",
-                assertion: error_msg_eq!("unrecognized keyword: d"),
+                assertion: token_kinds_eq!(Number(0), Identifier("d".to_string()), EndOfFile),
             },
         )
     }
@@ -453,6 +719,45 @@ mod tests {
                     EndOfFile
                 ),
             },
+            TestCase {
+                name: "success - interpolation",
+                input: r#""a${x + 1}b${ {} }""#,
+                assertion: token_kinds_eq!(
+                    Interpolation("a".to_string()),
+                    Identifier("x".to_string()),
+                    Plus,
+                    Number(1),
+                    Interpolation("b".to_string()),
+                    LeftBrace,
+                    RightBrace,
+                    String("".to_string()),
+                    EndOfFile
+                ),
+            },
+            TestCase {
+                name: "success - strings inside an interpolation",
+                input: r#""${f("}")}" "$ {}""#,
+                assertion: token_kinds_eq!(
+                    Interpolation("".to_string()),
+                    Identifier("f".to_string()),
+                    LeftParen,
+                    String("}".to_string()),
+                    RightParen,
+                    String("".to_string()),
+                    String("$ {}".to_string()),
+                    EndOfFile
+                ),
+            },
+            TestCase {
+                name: "success - an escaped interpolation is text",
+                input: r#""\${x}" "\$x" "\""#,
+                assertion: token_kinds_eq!(
+                    String("${x}".to_string()),
+                    String("\\$x".to_string()),
+                    String("\\".to_string()),
+                    EndOfFile
+                ),
+            },
             TestCase {
                 name: "failure - unterminated string literal",
                 input: r#"
@@ -497,15 +802,34 @@ mod tests {
                 input: "{}",
                 assertion: token_kinds_eq!(LeftBrace, RightBrace, EndOfFile),
             },
+            TestCase {
+                name: "success - brackets",
+                input: "[]",
+                assertion: token_kinds_eq!(LeftBracket, RightBracket, EndOfFile),
+            },
             TestCase {
                 name: "success - punctuation",
-                input: ",.;",
-                assertion: token_kinds_eq!(Comma, Dot, Semicolon, EndOfFile),
+                input: ",.;?:",
+                assertion: token_kinds_eq!(Comma, Dot, Semicolon, Question, Colon, EndOfFile),
             },
             TestCase {
                 name: "success - operators",
-                input: "-+/*",
-                assertion: token_kinds_eq!(Minus, Plus, Slash, Star, EndOfFile),
+                input: "-+*/",
+                assertion: token_kinds_eq!(Minus, Plus, Star, Slash, EndOfFile),
+            },
+            TestCase {
+                name: "success - bitwise",
+                input: "&|^~&&",
+                assertion: token_kinds_eq!(
+                    Ampersand, Pipe, Caret, Tilde, Ampersand, Ampersand, EndOfFile
+                ),
+            },
+            TestCase {
+                name: "success - modulo and exponent",
+                input: "% ** *** * *",
+                assertion: token_kinds_eq!(
+                    Percent, StarStar, StarStar, Star, Star, Star, EndOfFile
+                ),
             },
         )
     }
@@ -525,14 +849,43 @@ mod tests {
             },
             TestCase {
                 name: "success - less equal",
-                input: "<<==",
+                input: "< <==",
                 assertion: token_kinds_eq!(Less, LessEqual, Equal, EndOfFile),
             },
             TestCase {
                 name: "success - greater equal",
-                input: ">>==",
+                input: "> >==",
                 assertion: token_kinds_eq!(Greater, GreaterEqual, Equal, EndOfFile),
             },
+            TestCase {
+                name: "success - shifts",
+                input: "<<< >>>= <<=",
+                assertion: token_kinds_eq!(
+                    LessLess,
+                    Less,
+                    GreaterGreater,
+                    GreaterEqual,
+                    LessLess,
+                    Equal,
+                    EndOfFile
+                ),
+            },
+            TestCase {
+                name: "success - compound assignment",
+                input: "+=-=*=/=**= + =",
+                assertion: token_kinds_eq!(
+                    PlusEqual, MinusEqual, StarEqual, SlashEqual, StarStar, Equal, Plus, Equal,
+                    EndOfFile
+                ),
+            },
+            TestCase {
+                name: "success - increment and decrement",
+                input: "++ -- +++ --- - -",
+                assertion: token_kinds_eq!(
+                    PlusPlus, MinusMinus, PlusPlus, Plus, MinusMinus, Minus, Minus, Minus,
+                    EndOfFile
+                ),
+            },
         )
     }
 
@@ -558,6 +911,26 @@ mod tests {
                 "#,
                 assertion: token_kinds_eq!(Slash, Slash, EndOfFile)
             },
+            TestCase {
+                name: "success - block comment",
+                input: "1 /* a\n * b */ + 2",
+                assertion: token_kinds_eq!(Number(1), Plus, Number(2), EndOfFile)
+            },
+            TestCase {
+                name: "success - nested block comments",
+                input: "/* a /* b */ still a comment */ 1",
+                assertion: token_kinds_eq!(Number(1), EndOfFile)
+            },
+            TestCase {
+                name: "success - comment markers inside a block comment",
+                input: "/* // ** / */ 1",
+                assertion: token_kinds_eq!(Number(1), EndOfFile)
+            },
+            TestCase {
+                name: "failure - unterminated block comment",
+                input: "1\n  /* a /* b */",
+                assertion: error_msg_eq!("unterminated block comment starting at line 2, column 3")
+            },
         )
     }
 
@@ -595,19 +968,238 @@ mod tests {
                 assertion: token_kinds_eq!(Number(3), And, True, EndOfFile)
             },
             TestCase {
-                name: "failure - invalid keyword",
-                input: "d",
-                assertion: error_msg_eq!("unrecognized keyword: d"),
+                name: "success - identifier",
+                input: "var d",
+                assertion: token_kinds_eq!(Var, Identifier("d".to_string()), EndOfFile),
+            },
+            TestCase {
+                name: "success - identifier with keyword prefix",
+                input: "classy orchid",
+                assertion: token_kinds_eq!(
+                    Identifier("classy".to_string()),
+                    Identifier("orchid".to_string()),
+                    EndOfFile
+                ),
+            },
+            TestCase {
+                name: "success - identifiers with digits and underscores",
+                input: "_private x2 snake_case __",
+                assertion: token_kinds_eq!(
+                    Identifier("_private".to_string()),
+                    Identifier("x2".to_string()),
+                    Identifier("snake_case".to_string()),
+                    Identifier("__".to_string()),
+                    EndOfFile
+                ),
+            },
+            TestCase {
+                name: "success - keyword with a suffix",
+                input: "var_ if2",
+                assertion: token_kinds_eq!(
+                    Identifier("var_".to_string()),
+                    Identifier("if2".to_string()),
+                    EndOfFile
+                ),
             },
         )
     }
 
+    #[test]
+    fn token_positions() {
+        let tokens = scan("1 +\n  \"会意\" // comment\n\t!=").unwrap();
+        let positions: Vec<(usize, usize, Span)> =
+            tokens.iter().map(|t| (t.line, t.column, t.span)).collect();
+        assert_eq!(
+            positions,
+            vec![
+                (1, 1, Span { start: 0, end: 1 }),
+                (1, 3, Span { start: 2, end: 3 }),
+                (2, 3, Span { start: 6, end: 14 }),
+                (3, 2, Span { start: 27, end: 29 }),
+                (3, 4, Span { start: 29, end: 29 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn end_of_file_is_after_trailing_whitespace_and_comments() {
+        for (input, line, column, end) in [
+            ("", 1, 1, 0),
+            ("a // c\n", 2, 1, 7),
+            ("a\n\t/* 会 */", 2, 9, 12),
+        ] {
+            let tokens = scan(input).unwrap();
+            let eof = tokens.last().unwrap();
+            assert_eq!(eof.kind, TokenKind::EndOfFile);
+            assert_eq!(
+                (eof.line, eof.column, eof.span),
+                (line, column, Span { start: end, end }),
+                "input: {:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn lexemes() {
+        let tokens = scan("foo >= \"会意\" /* skipped */ 0042;").unwrap();
+        let lexemes: Vec<&str> = tokens.iter().map(|t| t.lexeme.as_str()).collect();
+        assert_eq!(lexemes, vec!["foo", ">=", "\"会意\"", "0042", ";", ""]);
+    }
+
+    #[test]
+    fn error_positions() {
+        run_tests!(
+            TestCase {
+                name: "failure - unterminated string reports its start",
+                input: "1 +\n  \"abc\n\n",
+                assertion: error_msg_eq!("unterminated string starting at line 2, column 3"),
+            },
+            TestCase {
+                name: "failure - unterminated string after an interpolation reports its start",
+                input: "print\n \"a${b} c;",
+                assertion: error_msg_eq!("unterminated string starting at line 2, column 2"),
+            },
+            TestCase {
+                name: "failure - unterminated interpolation",
+                input: "print \"a ${b + 1;\nprint c;",
+                assertion: error_msg_eq!(
+                    "unterminated interpolation, '${' at line 1, column 10 has no closing '}'"
+                ),
+            },
+            TestCase {
+                name: "failure - unterminated interpolation with a quote after it",
+                input: "print \"a ${b\";",
+                assertion: error_msg_eq!(
+                    "unterminated interpolation, '${' at line 1, column 10 has no closing '}'"
+                ),
+            },
+            TestCase {
+                name: "failure - nested interpolation",
+                input: "print \"${ \"in ${b}\" }\";",
+                assertion: error_msg_eq!(
+                    "interpolations can't be nested, found '${' at line 1, column 15 in a string inside one"
+                ),
+            },
+            TestCase {
+                name: "failure - unrecognized token",
+                input: "(\n #",
+                assertion: error_msg_eq!("unrecognized token: '#' at line 2, column 2"),
+            },
+            TestCase {
+                name: "failure - number out of range",
+                input: "1 + 99999999999",
+                assertion: error_msg_eq!("number 99999999999 is out of range at line 1, column 5"),
+            },
+        )
+    }
+
+    #[test]
+    fn reports_every_error() {
+        let error = scan("# 1 @\n\"abc").unwrap_err();
+        let Some(Diagnostics(diagnostics)) = error.downcast_ref::<Diagnostics>() else {
+            panic!("expected diagnostics, got: {}", error);
+        };
+        let reported: Vec<(&str, Span)> = diagnostics
+            .iter()
+            .map(|d| (d.message.as_str(), d.span))
+            .collect();
+        assert_eq!(
+            reported,
+            vec![
+                (
+                    "scanner: unrecognized token: '#' at line 1, column 1",
+                    Span { start: 0, end: 1 }
+                ),
+                (
+                    "scanner: unrecognized token: '@' at line 1, column 5",
+                    Span { start: 4, end: 5 }
+                ),
+                (
+                    "scanner: unterminated string starting at line 2, column 1",
+                    Span { start: 6, end: 10 }
+                ),
+            ]
+        );
+        assert!(diagnostics.iter().all(|d| d.severity == Severity::Error));
+    }
+
+    #[test]
+    #[ignore = "benchmark; run with `cargo test --release -- --ignored --nocapture`"]
+    fn bench_large_generated_input() {
+        let line = "    print 12 + 345 * 6; // running total for the report\n\n";
+        let input = line.repeat(100_000);
+
+        let start = std::time::Instant::now();
+        let result = scan(&input);
+        let elapsed = start.elapsed();
+
+        assert!(result.is_ok());
+        let mb = input.len() as f64 / (1024.0 * 1024.0);
+        println!(
+            "scanned {:.1} MiB in {:?} ({:.1} MiB/s)",
+            mb,
+            elapsed,
+            mb / elapsed.as_secs_f64()
+        );
+    }
+
+    #[test]
+    fn skipping_keeps_positions_exact() {
+        // Runs long enough to span many words of the byte searches, with
+        // characters of more than one byte in and around them.
+        let line = format!("{}// {} 会意\n\t\r\n", " ".repeat(37), "x".repeat(61));
+        let input = format!("{}\u{3000} print // é", line.repeat(1000));
+        let tokens = scan(&input).unwrap();
+
+        let positions: Vec<(usize, usize, Span)> =
+            tokens.iter().map(|t| (t.line, t.column, t.span)).collect();
+        let print = line.len() * 1000 + '\u{3000}'.len_utf8() + 1;
+        assert_eq!(
+            positions,
+            [
+                (
+                    2001,
+                    3,
+                    Span {
+                        start: print,
+                        end: print + 5
+                    }
+                ),
+                (
+                    2001,
+                    13,
+                    Span {
+                        start: input.len(),
+                        end: input.len()
+                    }
+                ),
+            ]
+        );
+    }
+
     #[test]
     fn fails_on_unrecognized_input() {
         run_tests!(TestCase {
             name: "failures - unrecognized single character",
-            input: "?",
-            assertion: error_msg_eq!("unrecognized token: '?'"),
+            input: "#",
+            assertion: error_msg_eq!("unrecognized token: '#'"),
         });
     }
+
+    #[test]
+    fn scans_lazily_past_errors() {
+        let mut lazy = tokens("1 # +");
+        assert_eq!(lazy.next().unwrap().unwrap().kind, Number(1));
+        assert_eq!(
+            lazy.next().unwrap().unwrap_err().to_string(),
+            "scanner: unrecognized token: '#' at line 1, column 3"
+        );
+        let rest: Vec<TokenKind> = lazy.map(|t| t.unwrap().kind).collect();
+        assert_eq!(rest, vec![Plus, EndOfFile]);
+
+        let source = "var a = \"会意\"; // done\n";
+        let lazy: Vec<Token> = tokens(source).map(Result::unwrap).collect();
+        assert_eq!(lazy, scan(source).unwrap());
+    }
 }
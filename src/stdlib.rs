@@ -0,0 +1,478 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+
+use crate::interpreter::Output;
+use crate::value::{LoxMap, MapKey, Value};
+
+/// What a backend provides for the built-ins to be defined in it.
+pub(crate) trait Host {
+    fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value> + 'static,
+    );
+
+    /// Where `print` writes to, for `flush`.
+    fn output(&self) -> Output;
+}
+
+/// Defines the built-in functions every interpreter starts with.
+pub(crate) fn register(interpreter: &mut impl Host) {
+    interpreter.define_native("clock", 0, clock);
+    interpreter.define_native("len", 1, len);
+    interpreter.define_native("push", 2, push);
+    interpreter.define_native("pop", 1, pop);
+    interpreter.define_native("keys", 1, keys);
+    interpreter.define_native("values", 1, values);
+    interpreter.define_native("has", 2, has);
+    interpreter.define_native("substr", 3, substr);
+    interpreter.define_native("parseNumber", 1, parse_number);
+    interpreter.define_native("str", 1, to_string);
+    interpreter.define_native("abs", 1, abs);
+    interpreter.define_native("floor", 1, floor);
+    interpreter.define_native("sqrt", 1, sqrt);
+    let out = interpreter.output();
+    interpreter.define_native("readLine", 0, move |_| {
+        read_line(&out, &mut io::stdin().lock())
+    });
+    interpreter.define_native("readFile", 1, read_file);
+    interpreter.define_native("writeFile", 2, write_file);
+
+    let out = interpreter.output();
+    interpreter.define_native("flush", 0, move |_| {
+        out.borrow_mut()
+            .flush()
+            .context("interpreter: flush: could not write output")?;
+        Ok(Value::Nil)
+    });
+}
+
+fn string<'a>(function: &str, value: &'a Value) -> Result<&'a str> {
+    match value {
+        Value::String(s) => Ok(s),
+        value => bail!(
+            "interpreter: {} expects a string, found {}",
+            function,
+            value.type_name()
+        ),
+    }
+}
+
+fn number(function: &str, value: &Value) -> Result<i32> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        value => bail!(
+            "interpreter: {} expects a number, found {}",
+            function,
+            value.type_name()
+        ),
+    }
+}
+
+/// Whole seconds since the Unix epoch. That is too coarse to time anything
+/// shorter than a few seconds, and a number only holds it until
+/// 2038-01-19 03:14:07 UTC; `clock` fails after that.
+fn clock(_arguments: &[Value]) -> Result<Value> {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("interpreter: clock: system time is before the Unix epoch")?;
+    seconds(since_epoch)
+}
+
+fn seconds(since_epoch: Duration) -> Result<Value> {
+    let seconds =
+        i32::try_from(since_epoch.as_secs()).context("interpreter: clock: time is out of range")?;
+    Ok(Value::Number(seconds))
+}
+
+fn list<'a>(function: &str, value: &'a Value) -> Result<&'a RefCell<Vec<Value>>> {
+    match value {
+        Value::List(list) => Ok(list),
+        value => bail!(
+            "interpreter: {} expects a list, found {}",
+            function,
+            value.type_name()
+        ),
+    }
+}
+
+fn map<'a>(function: &str, value: &'a Value) -> Result<&'a RefCell<LoxMap>> {
+    match value {
+        Value::Map(map) => Ok(map),
+        value => bail!(
+            "interpreter: {} expects a map, found {}",
+            function,
+            value.type_name()
+        ),
+    }
+}
+
+/// The number of characters in a string, elements in a list, or entries
+/// in a map.
+fn len(arguments: &[Value]) -> Result<Value> {
+    let length = match &arguments[0] {
+        Value::String(s) => s.chars().count(),
+        Value::List(list) => list.borrow().len(),
+        Value::Map(map) => map.borrow().len(),
+        value => bail!(
+            "interpreter: len expects a string, list or map, found {}",
+            value.type_name()
+        ),
+    };
+    let length = i32::try_from(length).context("interpreter: len: value is too long")?;
+    Ok(Value::Number(length))
+}
+
+/// `push(list, value)`: adds `value` to the end of `list`.
+fn push(arguments: &[Value]) -> Result<Value> {
+    list("push", &arguments[0])?
+        .borrow_mut()
+        .push(arguments[1].clone());
+    Ok(Value::Nil)
+}
+
+/// Removes the last element of a list and returns it.
+fn pop(arguments: &[Value]) -> Result<Value> {
+    match list("pop", &arguments[0])?.borrow_mut().pop() {
+        Some(value) => Ok(value),
+        None => bail!("interpreter: pop from an empty list"),
+    }
+}
+
+fn new_list(elements: Vec<Value>) -> Value {
+    Value::List(Rc::new(RefCell::new(elements)))
+}
+
+/// A new list of the keys of a map, in the order they were added.
+fn keys(arguments: &[Value]) -> Result<Value> {
+    let map = map("keys", &arguments[0])?.borrow();
+    Ok(new_list(
+        map.iter().map(|(key, _)| key.to_value()).collect(),
+    ))
+}
+
+/// A new list of the values of a map, in the order of their keys.
+fn values(arguments: &[Value]) -> Result<Value> {
+    let map = map("values", &arguments[0])?.borrow();
+    Ok(new_list(
+        map.iter().map(|(_, value)| value.clone()).collect(),
+    ))
+}
+
+/// `has(map, key)`: whether `map` has an entry for `key`.
+fn has(arguments: &[Value]) -> Result<Value> {
+    let map = map("has", &arguments[0])?.borrow();
+    match MapKey::from_value(&arguments[1]) {
+        Some(key) => Ok(Value::Bool(map.contains_key(&key))),
+        None => bail!(
+            "interpreter: map key must be a number or string, found {}",
+            arguments[1].type_name()
+        ),
+    }
+}
+
+/// `substr(s, start, length)`: `length` characters of `s` from `start`.
+fn substr(arguments: &[Value]) -> Result<Value> {
+    let s = string("substr", &arguments[0])?;
+    let start = number("substr", &arguments[1])?;
+    let length = number("substr", &arguments[2])?;
+    let count = s.chars().count();
+    let range = usize::try_from(start)
+        .ok()
+        .zip(usize::try_from(length).ok())
+        .filter(|(start, length)| start + length <= count);
+    match range {
+        Some((start, length)) => Ok(Value::String(s.chars().skip(start).take(length).collect())),
+        None => bail!(
+            "interpreter: substr: {} characters from {} is out of bounds for a string of length {}",
+            length,
+            start,
+            count
+        ),
+    }
+}
+
+/// The number written in a string, or nil if it does not hold one.
+fn parse_number(arguments: &[Value]) -> Result<Value> {
+    let s = string("parseNumber", &arguments[0])?;
+    Ok(s.trim().parse().map_or(Value::Nil, Value::Number))
+}
+
+/// The value as `print` would write it, which is also how string
+/// interpolation shows it.
+fn to_string(arguments: &[Value]) -> Result<Value> {
+    Ok(Value::String(arguments[0].to_string()))
+}
+
+fn abs(arguments: &[Value]) -> Result<Value> {
+    let n = number("abs", &arguments[0])?;
+    match n.checked_abs() {
+        Some(n) => Ok(Value::Number(n)),
+        None => bail!("interpreter: integer overflow in 'abs({})'", n),
+    }
+}
+
+/// Numbers are integers, so this only checks that it was given one.
+fn floor(arguments: &[Value]) -> Result<Value> {
+    Ok(Value::Number(number("floor", &arguments[0])?))
+}
+
+/// The square root, rounded down.
+fn sqrt(arguments: &[Value]) -> Result<Value> {
+    let n = number("sqrt", &arguments[0])?;
+    if n < 0 {
+        bail!("interpreter: sqrt of a negative number: {}", n);
+    }
+    Ok(Value::Number(n.isqrt()))
+}
+
+/// The next line of `input` without its line ending, or nil at the end.
+/// What was printed to `out` is flushed first, so that a prompt shows
+/// before the wait for input.
+fn read_line(out: &Output, input: &mut impl BufRead) -> Result<Value> {
+    out.borrow_mut()
+        .flush()
+        .context("interpreter: readLine: could not write output")?;
+    let mut line = String::new();
+    if input
+        .read_line(&mut line)
+        .context("interpreter: readLine")?
+        == 0
+    {
+        return Ok(Value::Nil);
+    }
+    let trimmed = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(trimmed);
+    Ok(Value::String(line))
+}
+
+fn read_file(arguments: &[Value]) -> Result<Value> {
+    let path = string("readFile", &arguments[0])?;
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("interpreter: readFile: could not read '{}'", path))?;
+    Ok(Value::String(contents))
+}
+
+fn write_file(arguments: &[Value]) -> Result<Value> {
+    let path = string("writeFile", &arguments[0])?;
+    let contents = string("writeFile", &arguments[1])?;
+    fs::write(path, contents)
+        .with_context(|| format!("interpreter: writeFile: could not write '{}'", path))?;
+    Ok(Value::Nil)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    use super::*;
+
+    fn s(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    fn list(elements: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(elements)))
+    }
+
+    fn error(result: Result<Value>) -> String {
+        result.unwrap_err().to_string()
+    }
+
+    #[test]
+    fn clock_counts_seconds_since_the_epoch() {
+        let Value::Number(seconds) = clock(&[]).unwrap() else {
+            panic!("clock should return a number");
+        };
+        // Some time after this was written.
+        assert!(seconds > 1_700_000_000);
+    }
+
+    #[test]
+    fn clock_rounds_down_and_stops_in_2038() {
+        assert_eq!(
+            seconds(Duration::from_millis(1_999)).unwrap(),
+            Value::Number(1)
+        );
+        let last = Duration::from_secs(i32::MAX as u64);
+        assert_eq!(seconds(last).unwrap(), Value::Number(i32::MAX));
+        assert_eq!(
+            error(seconds(last + Duration::from_secs(1))),
+            "interpreter: clock: time is out of range"
+        );
+    }
+
+    #[test]
+    fn len_counts_characters() {
+        assert_eq!(len(&[s("")]).unwrap(), Value::Number(0));
+        assert_eq!(len(&[s("会意!")]).unwrap(), Value::Number(3));
+        assert_eq!(
+            len(&[list(vec![s("a"), Value::Nil])]).unwrap(),
+            Value::Number(2)
+        );
+        assert_eq!(
+            error(len(&[Value::Nil])),
+            "interpreter: len expects a string, list or map, found nil"
+        );
+    }
+
+    #[test]
+    fn push_and_pop_change_the_list_they_are_given() {
+        let items = list(vec![]);
+        push(&[items.clone(), Value::Number(1)]).unwrap();
+        push(&[items.clone(), s("two")]).unwrap();
+        assert_eq!(items.to_string(), r#"[1, "two"]"#);
+        assert_eq!(pop(std::slice::from_ref(&items)).unwrap(), s("two"));
+        assert_eq!(pop(std::slice::from_ref(&items)).unwrap(), Value::Number(1));
+        assert_eq!(error(pop(&[items])), "interpreter: pop from an empty list");
+        assert_eq!(
+            error(push(&[s("a"), Value::Nil])),
+            "interpreter: push expects a list, found string"
+        );
+    }
+
+    #[test]
+    fn keys_values_and_has_read_a_map() {
+        let mut entries = LoxMap::default();
+        entries.insert(MapKey::String("b".to_string()), Value::Number(1));
+        entries.insert(MapKey::Number(0), s("zero"));
+        let entries = Value::Map(Rc::new(RefCell::new(entries)));
+        let arguments = std::slice::from_ref(&entries);
+        assert_eq!(keys(arguments).unwrap().to_string(), r#"["b", 0]"#);
+        assert_eq!(values(arguments).unwrap().to_string(), r#"[1, "zero"]"#);
+        assert_eq!(len(arguments).unwrap(), Value::Number(2));
+        assert_eq!(
+            has(&[entries.clone(), Value::Number(0)]).unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(has(&[entries.clone(), s("0")]).unwrap(), Value::Bool(false));
+        assert_eq!(
+            error(has(&[entries, Value::Bool(true)])),
+            "interpreter: map key must be a number or string, found bool"
+        );
+        assert_eq!(
+            error(keys(&[list(vec![])])),
+            "interpreter: keys expects a map, found list"
+        );
+    }
+
+    #[test]
+    fn substr_takes_a_character_range() {
+        let text = s("héllo");
+        let call =
+            |start, length| substr(&[text.clone(), Value::Number(start), Value::Number(length)]);
+        assert_eq!(call(1, 3).unwrap(), s("éll"));
+        assert_eq!(call(5, 0).unwrap(), s(""));
+        assert_eq!(
+            error(call(3, 3)),
+            "interpreter: substr: 3 characters from 3 is out of bounds for a string of length 5"
+        );
+        assert!(error(call(-1, 1)).contains("out of bounds"));
+    }
+
+    #[test]
+    fn parse_number_reads_integers() {
+        assert_eq!(parse_number(&[s(" 42\n")]).unwrap(), Value::Number(42));
+        assert_eq!(parse_number(&[s("-7")]).unwrap(), Value::Number(-7));
+        assert_eq!(parse_number(&[s("4.2")]).unwrap(), Value::Nil);
+        assert_eq!(parse_number(&[s("")]).unwrap(), Value::Nil);
+    }
+
+    #[test]
+    fn str_writes_values_as_print_does() {
+        assert_eq!(to_string(&[Value::Number(-3)]).unwrap(), s("-3"));
+        assert_eq!(to_string(&[s("text")]).unwrap(), s("text"));
+        assert_eq!(to_string(&[Value::Nil]).unwrap(), s("nil"));
+        assert_eq!(
+            to_string(&[list(vec![Value::Bool(true), s("a")])]).unwrap(),
+            s(r#"[true, "a"]"#)
+        );
+    }
+
+    #[test]
+    fn abs_of_numbers() {
+        assert_eq!(abs(&[Value::Number(-3)]).unwrap(), Value::Number(3));
+        assert_eq!(abs(&[Value::Number(3)]).unwrap(), Value::Number(3));
+        assert!(error(abs(&[Value::Number(i32::MIN)])).contains("integer overflow"));
+    }
+
+    #[test]
+    fn floor_of_integers_is_the_integer() {
+        assert_eq!(floor(&[Value::Number(-3)]).unwrap(), Value::Number(-3));
+        assert_eq!(
+            error(floor(&[s("1")])),
+            "interpreter: floor expects a number, found string"
+        );
+    }
+
+    #[test]
+    fn sqrt_rounds_down() {
+        assert_eq!(sqrt(&[Value::Number(16)]).unwrap(), Value::Number(4));
+        assert_eq!(sqrt(&[Value::Number(15)]).unwrap(), Value::Number(3));
+        assert_eq!(
+            error(sqrt(&[Value::Number(-4)])),
+            "interpreter: sqrt of a negative number: -4"
+        );
+    }
+
+    #[derive(Clone, Default)]
+    struct Written(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Written {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn output(written: &Written) -> Output {
+        Rc::new(RefCell::new(io::BufWriter::new(Box::new(written.clone()))))
+    }
+
+    #[test]
+    fn read_line_strips_line_endings() {
+        let out = output(&Written::default());
+        let mut input = Cursor::new("first\r\nsecond\nlast");
+        assert_eq!(read_line(&out, &mut input).unwrap(), s("first"));
+        assert_eq!(read_line(&out, &mut input).unwrap(), s("second"));
+        assert_eq!(read_line(&out, &mut input).unwrap(), s("last"));
+        assert_eq!(read_line(&out, &mut input).unwrap(), Value::Nil);
+    }
+
+    #[test]
+    fn read_line_flushes_buffered_output_first() {
+        let written = Written::default();
+        let out = output(&written);
+        out.borrow_mut().write_all(b"name?\n").unwrap();
+        assert!(written.0.borrow().is_empty());
+
+        read_line(&out, &mut Cursor::new("lox\n")).unwrap();
+        assert_eq!(*written.0.borrow(), b"name?\n");
+    }
+
+    #[test]
+    fn files_round_trip() {
+        let path = env::temp_dir().join(format!("rslox-stdlib-{}.txt", std::process::id()));
+        let path = s(path.to_str().unwrap());
+
+        assert_eq!(write_file(&[path.clone(), s("saved")]).unwrap(), Value::Nil);
+        assert_eq!(read_file(std::slice::from_ref(&path)).unwrap(), s("saved"));
+
+        let Value::String(path) = path else {
+            unreachable!()
+        };
+        fs::remove_file(&path).unwrap();
+        assert!(error(read_file(&[s(&path)])).starts_with("interpreter: readFile: could not read"));
+    }
+}
@@ -0,0 +1,128 @@
+use std::io::Write;
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{Result, anyhow};
+
+use crate::interpreter::Interpreter;
+use crate::parser::parse;
+use crate::scanner::scan;
+
+enum Command {
+    Run(String, Sender<Result<()>>),
+    Global(String, Sender<Option<String>>),
+}
+
+/// A handle to an interpreter running on its own thread.
+///
+/// Values are `Rc`-based and cannot leave the thread that made them, so the
+/// handle sends it commands instead; it is `Send + Sync` and can be shared
+/// between host threads. Commands run one at a time in the order received.
+pub struct SharedInterpreter {
+    commands: Option<Sender<Command>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SharedInterpreter {
+    pub fn spawn(out: impl Write + Send + 'static) -> Self {
+        let (commands, received) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            let mut interpreter = Interpreter::with_output(out);
+            for command in received {
+                match command {
+                    Command::Run(source, reply) => {
+                        let result = scan(&source)
+                            .and_then(|tokens| parse(&tokens))
+                            .and_then(|program| interpreter.interpret(&program));
+                        let _ = reply.send(result);
+                    }
+                    Command::Global(name, reply) => {
+                        let value = interpreter.get_global(&name);
+                        let _ = reply.send(value.map(|value| value.to_string()));
+                    }
+                }
+            }
+        });
+        Self {
+            commands: Some(commands),
+            thread: Some(thread),
+        }
+    }
+
+    /// Scans, parses, and runs `source`, waiting for it to finish.
+    pub fn run(&self, source: &str) -> Result<()> {
+        let (reply, result) = mpsc::channel();
+        self.send(Command::Run(source.to_string(), reply))?;
+        result.recv()?
+    }
+
+    /// The global `name` as it would be printed, if it is defined.
+    pub fn get_global(&self, name: &str) -> Result<Option<String>> {
+        let (reply, result) = mpsc::channel();
+        self.send(Command::Global(name.to_string(), reply))?;
+        Ok(result.recv()?)
+    }
+
+    fn send(&self, command: Command) -> Result<()> {
+        self.commands
+            .as_ref()
+            .expect("the sender is only taken on drop")
+            .send(command)
+            .map_err(|_| anyhow!("interpreter: the interpreter thread has stopped"))
+    }
+}
+
+impl Drop for SharedInterpreter {
+    fn drop(&mut self) {
+        // Closing the channel ends the thread's command loop.
+        self.commands.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn runs_commands_from_many_threads() {
+        let interpreter = Arc::new(SharedInterpreter::spawn(io::sink()));
+        interpreter.run("var count = 0;").unwrap();
+
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let interpreter = Arc::clone(&interpreter);
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        interpreter.run("count = count + 1;").unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        assert_eq!(
+            interpreter.get_global("count").unwrap().as_deref(),
+            Some("40")
+        );
+        assert_eq!(interpreter.get_global("missing").unwrap(), None);
+    }
+
+    #[test]
+    fn reports_errors_to_the_caller() {
+        let interpreter = SharedInterpreter::spawn(io::sink());
+        let error = interpreter.run("print missing;").unwrap_err();
+        assert!(error.to_string().contains("undefined variable 'missing'"));
+
+        // The interpreter keeps running after a failed command.
+        interpreter.run("var a = 1;").unwrap();
+        assert_eq!(interpreter.get_global("a").unwrap().as_deref(), Some("1"));
+    }
+}
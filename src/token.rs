@@ -1,10 +1,16 @@
 #[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
 pub enum TokenKind {
+    Ampersand,
     And,
     Bang,
     BangEqual,
+    Break,
+    Caret,
     Class,
+    Colon,
     Comma,
+    Continue,
     Dot,
     Else,
     EndOfFile,
@@ -15,32 +21,82 @@ pub enum TokenKind {
     Fun,
     Greater,
     GreaterEqual,
+    GreaterGreater,
+    Identifier(String),
     If,
+    /// The text of a string up to a `${`, or from the `}` that closes one up
+    /// to the next. The tokens of the interpolated expression follow, and
+    /// the `String` with the text after the last `}` ends the string.
+    Interpolation(String),
     LeftBrace,
+    LeftBracket,
     LeftParen,
     Less,
     LessEqual,
+    LessLess,
     Minus,
+    MinusEqual,
+    MinusMinus,
     Nil,
     Number(i32),
     Or,
+    Percent,
+    Pipe,
     Plus,
+    PlusEqual,
+    PlusPlus,
     Print,
+    Question,
     Return,
     RightBrace,
+    RightBracket,
     RightParen,
     Semicolon,
     Slash,
+    SlashEqual,
     Star,
+    StarEqual,
+    StarStar,
     String(String),
     Super,
     This,
+    Tilde,
     True,
     Var,
     While,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Span {
+    /// Byte offset of the first byte of the token.
+    pub start: usize,
+    /// Byte offset one past the last byte of the token.
+    pub end: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     pub kind: TokenKind,
+    /// 1-based line the token starts on.
+    pub line: usize,
+    /// 1-based column, in characters, the token starts at.
+    pub column: usize,
+    pub span: Span,
+    /// The source text exactly as written; empty for `EndOfFile`.
+    pub lexeme: String,
+}
+
+impl Token {
+    /// How error messages refer to this token: its lexeme in quotes, or
+    /// "end of file".
+    pub fn describe(&self) -> String {
+        match self.kind {
+            TokenKind::EndOfFile => "end of file".to_string(),
+            _ => format!("'{}'", self.lexeme),
+        }
+    }
+
+    pub fn location(&self) -> String {
+        format!("at line {}, column {}", self.line, self.column)
+    }
 }
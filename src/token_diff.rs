@@ -0,0 +1,125 @@
+use std::fmt;
+
+use crate::token::Token;
+
+#[derive(Debug)]
+pub enum Change<'a> {
+    Same(&'a Token),
+    Removed(&'a Token),
+    Added(&'a Token),
+}
+
+impl<'a> Change<'a> {
+    pub fn is_lexical(&self) -> bool {
+        !matches!(self, Change::Same(_))
+    }
+
+    pub fn token(&self) -> &'a Token {
+        match self {
+            Change::Same(token) | Change::Removed(token) | Change::Added(token) => token,
+        }
+    }
+}
+
+impl fmt::Display for Change<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = match self {
+            Change::Same(_) => ' ',
+            Change::Removed(_) => '-',
+            Change::Added(_) => '+',
+        };
+        let token = self.token();
+        write!(f, "{} {:?} {}", sign, token.kind, token.location())
+    }
+}
+
+/// Aligns two token streams on their longest common subsequence of kinds.
+///
+/// Whitespace and comments never reach the token stream, so a
+/// formatting-only edit produces nothing but `Change::Same`.
+pub fn diff<'a>(old: &'a [Token], new: &'a [Token]) -> Vec<Change<'a>> {
+    // lcs[i][j] is the length of the common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i].kind == new[j].kind {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i].kind == new[j].kind {
+            changes.push(Change::Same(&old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            changes.push(Change::Removed(&old[i]));
+            i += 1;
+        } else {
+            changes.push(Change::Added(&new[j]));
+            j += 1;
+        }
+    }
+    changes.extend(old[i..].iter().map(Change::Removed));
+    changes.extend(new[j..].iter().map(Change::Added));
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::scan;
+    use crate::token::TokenKind;
+    use TokenKind::*;
+
+    fn lexical_changes(old: &str, new: &str) -> Vec<std::string::String> {
+        let old = scan(old).unwrap();
+        let new = scan(new).unwrap();
+        diff(&old, &new)
+            .iter()
+            .filter(|c| c.is_lexical())
+            .map(|c| c.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn formatting_only_edit_has_no_lexical_changes() {
+        let changes = lexical_changes("1+2;", "1 +\n    2 ; // sum");
+        assert!(changes.is_empty(), "unexpected changes: {:?}", changes);
+    }
+
+    #[test]
+    fn reports_added_and_removed_tokens() {
+        let old = scan("1 + 2").unwrap();
+        let new = scan("1 * 2").unwrap();
+        let changes: Vec<(char, TokenKind)> = diff(&old, &new)
+            .iter()
+            .map(|c| (c.to_string().remove(0), c.token().kind.clone()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (' ', Number(1)),
+                ('-', Plus),
+                ('+', Star),
+                (' ', Number(2)),
+                (' ', EndOfFile),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_trailing_insertions() {
+        let changes = lexical_changes("true", "true and false");
+        assert_eq!(
+            changes,
+            vec!["+ And at line 1, column 6", "+ False at line 1, column 10"]
+        );
+    }
+}
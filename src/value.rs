@@ -0,0 +1,478 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use anyhow::{Result, bail};
+
+use crate::ast::{FieldDecl, FunctionDecl, Identifier};
+use crate::chunk::Function;
+use crate::environment::Environment;
+use crate::interpreter::{Flow, Interpreter};
+use crate::runtime_error::RuntimeError;
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Value {
+    Number(i32),
+    String(String),
+    Bool(bool),
+    Nil,
+    Function(Rc<LoxFunction>),
+    Native(Rc<NativeFunction>),
+    /// A function compiled for the bytecode VM.
+    Compiled(Rc<Function>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    /// Shared, so that every variable holding a list sees changes made
+    /// through any of them.
+    List(Rc<RefCell<Vec<Value>>>),
+    /// Shared like a list.
+    Map(Rc<RefCell<LoxMap>>),
+}
+
+impl Value {
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Bool(_) => "bool",
+            Value::Nil => "nil",
+            Value::Function(_) | Value::Native(_) | Value::Compiled(_) => "function",
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Number(l), Value::Number(r)) => l == r,
+            (Value::String(l), Value::String(r)) => l == r,
+            (Value::Bool(l), Value::Bool(r)) => l == r,
+            (Value::Nil, Value::Nil) => true,
+            // Functions, classes, instances, lists, and maps are equal only
+            // to themselves.
+            (Value::Function(l), Value::Function(r)) => Rc::ptr_eq(l, r),
+            (Value::Native(l), Value::Native(r)) => Rc::ptr_eq(l, r),
+            (Value::Compiled(l), Value::Compiled(r)) => Rc::ptr_eq(l, r),
+            (Value::Class(l), Value::Class(r)) => Rc::ptr_eq(l, r),
+            (Value::Instance(l), Value::Instance(r)) => Rc::ptr_eq(l, r),
+            (Value::List(l), Value::List(r)) => Rc::ptr_eq(l, r),
+            (Value::Map(l), Value::Map(r)) => Rc::ptr_eq(l, r),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_value(f, self, &mut Vec::new())
+    }
+}
+
+// Writes `value`, where `open` holds the lists and maps being written
+// around it. One written again inside itself shows as `[...]` or `{...}`, so
+// one that contains itself still prints.
+fn write_value(
+    f: &mut fmt::Formatter<'_>,
+    value: &Value,
+    open: &mut Vec<*const ()>,
+) -> fmt::Result {
+    match value {
+        Value::Number(n) => write!(f, "{}", n),
+        Value::String(s) => write!(f, "{}", s),
+        Value::Bool(b) => write!(f, "{}", b),
+        Value::Nil => write!(f, "nil"),
+        Value::Function(function) => write!(f, "{:?}", function),
+        Value::Native(native) => write!(f, "{:?}", native),
+        Value::Compiled(function) => write!(f, "{:?}", function),
+        Value::Class(class) => write!(f, "{:?}", class),
+        Value::Instance(instance) => write!(f, "{:?}", instance.borrow()),
+        Value::List(elements) => {
+            let id = Rc::as_ptr(elements) as *const ();
+            if open.contains(&id) {
+                return write!(f, "[...]");
+            }
+            open.push(id);
+            write!(f, "[")?;
+            for (i, element) in elements.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_element(f, element, open)?;
+            }
+            open.pop();
+            write!(f, "]")
+        }
+        Value::Map(map) => {
+            let id = Rc::as_ptr(map) as *const ();
+            if open.contains(&id) {
+                return write!(f, "{{...}}");
+            }
+            open.push(id);
+            write!(f, "{{")?;
+            for (i, (key, value)) in map.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}: ", key)?;
+                write_element(f, value, open)?;
+            }
+            open.pop();
+            write!(f, "}}")
+        }
+    }
+}
+
+// Writes `value` as part of a list or map. Strings are quoted there, the
+// same way as map keys, so that `["1"]` and `[1]` can be told apart.
+fn write_element(
+    f: &mut fmt::Formatter<'_>,
+    value: &Value,
+    open: &mut Vec<*const ()>,
+) -> fmt::Result {
+    match value {
+        Value::String(s) => write!(f, "{:?}", s),
+        value => write_value(f, value, open),
+    }
+}
+
+pub(crate) trait Callable {
+    fn arity(&self) -> usize;
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value>;
+}
+
+/// A function declared in Lox, together with the environment it was
+/// declared in so that its body can see the variables around it.
+pub struct LoxFunction {
+    declaration: Rc<FunctionDecl>,
+    closure: Rc<RefCell<Environment>>,
+    is_initializer: bool,
+}
+
+impl LoxFunction {
+    pub(crate) fn new(
+        declaration: Rc<FunctionDecl>,
+        closure: Rc<RefCell<Environment>>,
+        is_initializer: bool,
+    ) -> Self {
+        Self {
+            declaration,
+            closure,
+            is_initializer,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.declaration.name.name
+    }
+
+    /// Returns a copy of this method whose body sees `instance` as `this`.
+    pub(crate) fn bind(&self, instance: Value) -> LoxFunction {
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        environment.define("this", instance);
+        LoxFunction::new(
+            Rc::clone(&self.declaration),
+            Rc::new(RefCell::new(environment)),
+            self.is_initializer,
+        )
+    }
+
+    fn this(&self) -> Value {
+        self.closure
+            .borrow()
+            .get(&Identifier {
+                name: "this".to_string(),
+                line: self.declaration.name.line,
+                column: self.declaration.name.column,
+            })
+            .expect("initializers are always bound to an instance")
+    }
+}
+
+impl fmt::Debug for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<fn {}>", self.name())
+    }
+}
+
+impl Callable for LoxFunction {
+    fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        for (param, argument) in self.declaration.params.iter().zip(arguments) {
+            environment.define(&param.name, argument);
+        }
+
+        let flow = interpreter.execute_block(&self.declaration.body, environment)?;
+        // An initializer always yields its instance, even on a bare `return;`.
+        if self.is_initializer {
+            return Ok(self.this());
+        }
+        match flow {
+            Flow::Return(value) => Ok(value),
+            Flow::Next => Ok(Value::Nil),
+            Flow::Break | Flow::Continue => {
+                unreachable!("the parser only allows break and continue inside loops")
+            }
+        }
+    }
+}
+
+type NativeFn = dyn Fn(&[Value]) -> Result<Value>;
+
+/// A function implemented in Rust, such as `clock`.
+pub struct NativeFunction {
+    name: String,
+    arity: usize,
+    function: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new(
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value> + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            arity,
+            function: Box::new(function),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Calls the function with arguments already checked against its arity.
+    pub fn call_native(&self, arguments: &[Value]) -> Result<Value> {
+        (self.function)(arguments)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+impl Callable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(&self, _interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+        self.call_native(&arguments)
+    }
+}
+
+pub struct LoxClass {
+    name: String,
+    superclass: Option<Rc<LoxClass>>,
+    methods: HashMap<String, Rc<LoxFunction>>,
+    fields: Vec<FieldDecl>,
+    // Where field initializers run, the same scope the methods close over.
+    closure: Rc<RefCell<Environment>>,
+}
+
+impl LoxClass {
+    pub(crate) fn new(
+        name: String,
+        superclass: Option<Rc<LoxClass>>,
+        methods: HashMap<String, Rc<LoxFunction>>,
+        fields: Vec<FieldDecl>,
+        closure: Rc<RefCell<Environment>>,
+    ) -> Self {
+        Self {
+            name,
+            superclass,
+            methods,
+            fields,
+            closure,
+        }
+    }
+
+    // Sets the fields a superclass declares, then this class's own, so a
+    // subclass's default replaces its superclass's.
+    fn initialize_fields(&self, interpreter: &mut Interpreter, instance: &Value) -> Result<()> {
+        if let Some(superclass) = &self.superclass {
+            superclass.initialize_fields(interpreter, instance)?;
+        }
+        if self.fields.is_empty() {
+            return Ok(());
+        }
+        let Value::Instance(fields) = instance else {
+            unreachable!("fields are only initialized on instances");
+        };
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        environment.define("this", instance.clone());
+        let environment = Rc::new(RefCell::new(environment));
+        for field in &self.fields {
+            let value = interpreter.evaluate_in(&field.initializer, Rc::clone(&environment))?;
+            fields.borrow_mut().set(&field.name, value);
+        }
+        Ok(())
+    }
+
+    /// Looks up `name` on this class, then on each superclass in turn.
+    pub fn find_method(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        match self.methods.get(name) {
+            Some(method) => Some(Rc::clone(method)),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
+    }
+}
+
+impl fmt::Debug for LoxClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<class {}>", self.name)
+    }
+}
+
+// Implemented on the `Rc` because each instance keeps a handle to its class.
+impl Callable for Rc<LoxClass> {
+    fn arity(&self) -> usize {
+        self.find_method("init").map_or(0, |init| init.arity())
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+        let instance = Value::Instance(Rc::new(RefCell::new(LoxInstance::new(Rc::clone(self)))));
+        self.initialize_fields(interpreter, &instance)?;
+        if let Some(init) = self.find_method("init") {
+            init.bind(instance.clone()).call(interpreter, arguments)?;
+        }
+        Ok(instance)
+    }
+}
+
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: HashMap<String, Value>,
+}
+
+impl LoxInstance {
+    pub(crate) fn new(class: Rc<LoxClass>) -> Self {
+        Self {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    /// Looks up a property on `instance`: fields shadow methods, and methods
+    /// come back bound to the instance.
+    pub(crate) fn get(instance: &Rc<RefCell<LoxInstance>>, name: &Identifier) -> Result<Value> {
+        let this = instance.borrow();
+        if let Some(value) = this.fields.get(&name.name) {
+            return Ok(value.clone());
+        }
+        if let Some(method) = this.class.find_method(&name.name) {
+            let bound = method.bind(Value::Instance(Rc::clone(instance)));
+            return Ok(Value::Function(Rc::new(bound)));
+        }
+        bail!(RuntimeError::new(
+            format!("undefined property '{}' on {:?}", name.name, this),
+            Some(name.position())
+        ))
+    }
+
+    pub(crate) fn set(&mut self, name: &Identifier, value: Value) {
+        self.fields.insert(name.name.clone(), value);
+    }
+}
+
+impl fmt::Debug for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{} instance>", self.class.name)
+    }
+}
+
+/// What a map can be keyed by. Keys are equal when their values are, so
+/// `1` and `"1"` are different keys.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[non_exhaustive]
+pub enum MapKey {
+    Number(i32),
+    String(String),
+}
+
+impl MapKey {
+    /// The key `value` stands for, or `None` if it can't be a key.
+    pub fn from_value(value: &Value) -> Option<MapKey> {
+        match value {
+            Value::Number(n) => Some(MapKey::Number(*n)),
+            Value::String(s) => Some(MapKey::String(s.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            MapKey::Number(n) => Value::Number(*n),
+            MapKey::String(s) => Value::String(s.clone()),
+        }
+    }
+}
+
+impl fmt::Display for MapKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapKey::Number(n) => write!(f, "{}", n),
+            // Quoted, so that `"1"` and `1` can be told apart.
+            MapKey::String(s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+/// The entries of a map, which keep the order their keys were first
+/// inserted in.
+#[derive(Debug, Default)]
+pub struct LoxMap {
+    entries: Vec<(MapKey, Value)>,
+    slots: HashMap<MapKey, usize>,
+}
+
+impl LoxMap {
+    pub fn get(&self, key: &MapKey) -> Option<&Value> {
+        self.slots.get(key).map(|&slot| &self.entries[slot].1)
+    }
+
+    /// Sets the value for `key`, which keeps its place if it was already
+    /// there.
+    pub fn insert(&mut self, key: MapKey, value: Value) {
+        match self.slots.get(&key) {
+            Some(&slot) => self.entries[slot].1 = value,
+            None => {
+                self.slots.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn contains_key(&self, key: &MapKey) -> bool {
+        self.slots.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&MapKey, &Value)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+}
@@ -0,0 +1,481 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, BufWriter, Write};
+use std::rc::Rc;
+
+use anyhow::{Context, Result, bail};
+
+use crate::ast::{Location, Program};
+use crate::chunk::{Function, OpCode};
+use crate::compiler;
+use crate::interner::{Interner, Symbol};
+use crate::interpreter::{self, MAX_FRAMES, Output};
+use crate::runtime_error::{Frame, RuntimeError};
+use crate::stdlib::{self, Host};
+use crate::value::{Callable, NativeFunction, Value};
+
+/// A stack machine that runs the bytecode `compiler` produces. It behaves
+/// like `Interpreter` for every program it can compile.
+pub struct Vm {
+    out: Output,
+    // Names globals, and what the running chunks were compiled with.
+    interner: Interner,
+    globals: HashMap<Symbol, Value>,
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+}
+
+struct CallFrame {
+    function: Rc<Function>,
+    ip: usize,
+    // Where the frame's slot 0, the function being called, is on the stack.
+    base: usize,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::with_output(io::stdout())
+    }
+
+    /// A VM whose `print` writes to `out`, buffered as it is by
+    /// `Interpreter::with_output`.
+    pub fn with_output(out: impl Write + 'static) -> Self {
+        let out: Box<dyn Write> = Box::new(out);
+        let mut vm = Self {
+            out: Rc::new(RefCell::new(BufWriter::new(out))),
+            interner: Interner::default(),
+            globals: HashMap::new(),
+            stack: Vec::new(),
+            frames: Vec::new(),
+        };
+        stdlib::register(&mut vm);
+        vm
+    }
+
+    /// Defines a global function `name` implemented by `function`, which is
+    /// only ever called with exactly `arity` arguments.
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value> + 'static,
+    ) {
+        let native = NativeFunction::new(name, arity, function);
+        let name = self.interner.intern(name);
+        self.globals.insert(name, Value::Native(Rc::new(native)));
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        let name = self.interner.get(name)?;
+        self.globals.get(&name).cloned()
+    }
+
+    /// Writes through any buffered `print` output.
+    pub fn flush(&mut self) -> Result<()> {
+        self.out
+            .borrow_mut()
+            .flush()
+            .context("interpreter: could not write output")
+    }
+
+    /// Compiles and runs `program`, then flushes its output. Errors while
+    /// running are `RuntimeError`s, as they are from `Interpreter`.
+    pub fn interpret(&mut self, program: &Program) -> Result<()> {
+        let script = Rc::new(compiler::compile(program, &mut self.interner)?);
+        self.stack.push(Value::Compiled(Rc::clone(&script)));
+        self.frames.push(CallFrame {
+            function: script,
+            ip: 0,
+            base: 0,
+        });
+        let result = self.run().map_err(|error| self.unwind(error));
+        let flushed = self.flush();
+        result?;
+        flushed
+    }
+
+    // Adds a frame to `error` for every call in progress, then resets the
+    // stacks for the next program.
+    fn unwind(&mut self, error: anyhow::Error) -> RuntimeError {
+        let mut error = RuntimeError::from_error(error);
+        for (caller, callee) in self.frames.iter().zip(&self.frames[1..]).rev() {
+            error.trace.push(Frame {
+                function: format!("{:?}", callee.function),
+                location: caller.function.chunk.locations[caller.ip - 1],
+            });
+        }
+        self.stack.clear();
+        self.frames.clear();
+        error
+    }
+
+    fn run(&mut self) -> Result<()> {
+        loop {
+            let frame = self.frames.last_mut().expect("the script is running");
+            let op = frame.function.chunk.code[frame.ip];
+            frame.ip += 1;
+            let base = frame.base;
+
+            match op {
+                OpCode::Constant(index) => {
+                    let value = self.frame().function.chunk.constants[index].clone();
+                    self.stack.push(value);
+                }
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Bool(true)),
+                OpCode::False => self.stack.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.pop();
+                }
+                OpCode::GetLocal(slot) => self.stack.push(self.stack[base + slot].clone()),
+                OpCode::SetLocal(slot) => self.stack[base + slot] = self.peek(0).clone(),
+                OpCode::GetGlobal(name) => {
+                    let value = self.globals.get(&name).cloned();
+                    match value {
+                        Some(value) => self.stack.push(value),
+                        None => bail!(self.undefined_variable(name)),
+                    }
+                }
+                OpCode::DefineGlobal(name) => {
+                    let value = self.pop();
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal(name) => {
+                    let value = self.peek(0).clone();
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => bail!(self.undefined_variable(name)),
+                    }
+                }
+                OpCode::Binary(op) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value =
+                        interpreter::binary(op, left, right).map_err(|e| self.located(e))?;
+                    self.stack.push(value);
+                }
+                OpCode::Unary(op) => {
+                    let right = self.pop();
+                    let value = interpreter::unary(op, right).map_err(|e| self.located(e))?;
+                    self.stack.push(value);
+                }
+                OpCode::Update(op) => {
+                    let value = self.pop();
+                    let value = interpreter::update(op, value).map_err(|e| self.located(e))?;
+                    self.stack.push(value);
+                }
+                OpCode::Interpolate(count) => {
+                    let values = self.stack.split_off(self.stack.len() - count);
+                    let text = values.iter().map(Value::to_string).collect();
+                    self.stack.push(Value::String(text));
+                }
+                OpCode::Print => {
+                    let value = self.pop();
+                    writeln!(self.out.borrow_mut(), "{}", value)?;
+                }
+                OpCode::Jump(target) => self.frame_mut().ip = target,
+                OpCode::JumpIfFalse(target) => {
+                    if !self.peek(0).is_truthy() {
+                        self.frame_mut().ip = target;
+                    }
+                }
+                OpCode::Call(count) => self.call(count)?,
+                OpCode::Return => {
+                    let value = self.pop();
+                    let frame = self.frames.pop().expect("the script is running");
+                    self.stack.truncate(frame.base);
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.stack.push(value);
+                }
+            }
+        }
+    }
+
+    // Calls the value `count` slots below the top of the stack with the
+    // `count` values above it as arguments.
+    fn call(&mut self, count: usize) -> Result<()> {
+        let base = self.stack.len() - count - 1;
+        let callee = self.stack[base].clone();
+        let arity = match &callee {
+            Value::Compiled(function) => function.arity,
+            Value::Native(native) => native.arity(),
+            value => bail!(RuntimeError::new(
+                format!(
+                    "can only call functions and classes, found {}",
+                    value.type_name()
+                ),
+                Some(self.location())
+            )),
+        };
+        if count != arity {
+            bail!(RuntimeError::new(
+                format!("{} expected {} arguments but got {}", callee, arity, count),
+                Some(self.location())
+            ));
+        }
+
+        match callee {
+            Value::Compiled(function) => {
+                if self.frames.len() == MAX_FRAMES {
+                    bail!(RuntimeError::new(
+                        "stack overflow".to_string(),
+                        Some(self.location())
+                    ));
+                }
+                self.frames.push(CallFrame {
+                    function,
+                    ip: 0,
+                    base,
+                });
+            }
+            Value::Native(native) => {
+                let value = native
+                    .call_native(&self.stack[base + 1..])
+                    .map_err(|error| {
+                        let mut error = RuntimeError::from_error(error);
+                        error.trace.push(Frame {
+                            function: format!("{:?}", native),
+                            location: self.location(),
+                        });
+                        error
+                    })?;
+                self.stack.truncate(base);
+                self.stack.push(value);
+            }
+            _ => unreachable!("checked above"),
+        }
+        Ok(())
+    }
+
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().expect("the script is running")
+    }
+
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().expect("the script is running")
+    }
+
+    // The source location of the instruction being run.
+    fn location(&self) -> Location {
+        let frame = self.frame();
+        frame.function.chunk.locations[frame.ip - 1]
+    }
+
+    fn undefined_variable(&self, name: Symbol) -> RuntimeError {
+        RuntimeError::new(
+            format!("undefined variable '{}'", self.interner.resolve(name)),
+            Some(self.location()),
+        )
+    }
+
+    // `error`, raised by an operator, placed at the instruction being run.
+    fn located(&self, error: anyhow::Error) -> anyhow::Error {
+        let error = RuntimeError::from_error(error);
+        RuntimeError {
+            location: Some(self.location()),
+            ..error
+        }
+        .into()
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack
+            .pop()
+            .expect("the compiler keeps the stack balanced")
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+}
+
+impl Host for Vm {
+    fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value> + 'static,
+    ) {
+        Vm::define_native(self, name, arity, function);
+    }
+
+    fn output(&self) -> Output {
+        Rc::clone(&self.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::parser::parse;
+    use crate::scanner::scan;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_vm(source: &str) -> Result<String> {
+        let buffer = SharedBuffer::default();
+        let program = parse(&scan(source)?)?;
+        Vm::with_output(buffer.clone()).interpret(&program)?;
+        let output = buffer.0.borrow().clone();
+        Ok(String::from_utf8(output)?)
+    }
+
+    fn run_interpreter(source: &str) -> Result<String> {
+        let buffer = SharedBuffer::default();
+        let program = parse(&scan(source)?)?;
+        Interpreter::with_output(buffer.clone()).interpret(&program)?;
+        let output = buffer.0.borrow().clone();
+        Ok(String::from_utf8(output)?)
+    }
+
+    // Every program here should behave the same on both backends, down to
+    // the error messages.
+    #[test]
+    fn agrees_with_the_interpreter() {
+        let cases = [
+            ("arithmetic", "print 1 + 2 * 3 - -4 / 2;"),
+            ("modulo and exponent", "print -7 % 3 + 2 ** 3 ** 2 % 10;"),
+            (
+                "string interpolation",
+                r#"var n = 2; print "${n} + ${n} = ${n + n}, ${n > 3}";"#,
+            ),
+            (
+                "interpolation with 'str' shadowed",
+                r#"var str = "s"; { var str = 1; print "${str} \${str}"; }"#,
+            ),
+            (
+                "bitwise operators",
+                "var a = 12; print a & 10 | 1 << 4; print a ^ ~a; print -a >> 1 == a >> 1;",
+            ),
+            (
+                "increment and decrement",
+                "var a = 1; print a++ + a; print --a; { var b = a; b++; print -b-- ** 2; print b; }",
+            ),
+            (
+                "compound assignment",
+                "var a = 1; a += 2; a *= a - 1; { var b = 20; b /= a - 2; print b -= 1; } print a;",
+            ),
+            ("strings", r#"print "a" + "b"; print "a" == "a";"#),
+            ("logic", "print nil or 2; print 1 and false; print !nil;"),
+            (
+                "comma operator",
+                "var a = 1; print (a = a + 1, a * 3); fun f(x, y) { return x - y; } print f((1, 5), 2);",
+            ),
+            (
+                "conditional operator",
+                "var a = 1; print a > 0 ? \"pos\" : a < 0 ? \"neg\" : \"zero\"; a = (a ? 2 : 3) * 2; print a;",
+            ),
+            (
+                "globals",
+                "var a = 1; var b; print b; a = a + 1; print a; var a = 5; print a;",
+            ),
+            (
+                "locals and shadowing",
+                "var a = 1; { var a = a + 1; { var b = a * 10; print b; } print a; } print a;",
+            ),
+            (
+                "conditionals",
+                "if (1 < 2) print \"yes\"; else print \"no\"; if (nil) print 1;",
+            ),
+            (
+                "loops",
+                "var total = 0; for (var i = 0; i < 5; i = i + 1) { total = total + i; } print total;",
+            ),
+            (
+                "break and continue pop the body's locals",
+                "var total = 0; for (var i = 0; i < 10; i = i + 1) { var odd = i - i / 2 * 2; if (odd == 1) continue; { var big = i > 6; if (big) break; } total = total + i; } print total;\nfun f() { var n = 0; while (true) { var m = n + 1; n = m; if (n == 3) break; } return n; } print f();",
+            ),
+            (
+                "recursion",
+                "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); } print fib(15);",
+            ),
+            (
+                "functions return nil by default",
+                "fun f(a, b) { var c = a + b; print c; } print f(1, 2); print f;",
+            ),
+            ("natives", r#"print len("four"); print clock;"#),
+            ("undefined variable", "print 1; print missing;"),
+            ("assigning an undefined variable", "missing = 1;"),
+            ("operator errors", r#"print 1 + "a";"#),
+            ("incrementing a string", r#"var s = "a"; print s++;"#),
+            ("calling a non-function", "var x = 1; x();"),
+            ("arity", "fun f(a) {} f(1, 2);"),
+            (
+                "errors unwind through calls",
+                "fun inner() { return 1 / 0; }\nfun outer() { inner(); }\nouter();",
+            ),
+            ("errors in natives", "fun f() { return len(1); }\nf();"),
+            (
+                "deep recursion overflows the stack",
+                "fun f(n) { if (n == 254) return len(\"ok\"); return f(n + 1); } print f(0);\nfun g(n) { return g(n + 1); } g(0);",
+            ),
+        ];
+        for (name, source) in cases {
+            let expected = run_interpreter(source).map_err(|e| e.to_string());
+            let actual = run_vm(source).map_err(|e| e.to_string());
+            assert_eq!(actual, expected, "case: {}", name);
+        }
+    }
+
+    #[test]
+    fn runtime_errors_carry_a_trace() {
+        let source = "fun inner() { return missing; }\nfun outer() { return inner(); }\nouter();";
+        let program = parse(&scan(source).unwrap()).unwrap();
+        let error = Vm::with_output(SharedBuffer::default())
+            .interpret(&program)
+            .unwrap_err();
+        let error = error.downcast::<RuntimeError>().unwrap();
+        assert_eq!(error.message, "undefined variable 'missing'");
+        assert_eq!(
+            error.location,
+            Some(Location {
+                line: 1,
+                column: 22
+            })
+        );
+        let functions: Vec<&str> = error.trace.iter().map(|f| f.function.as_str()).collect();
+        assert_eq!(functions, ["<fn inner>", "<fn outer>"]);
+    }
+
+    #[test]
+    fn deep_recursion_overflows_the_stack() {
+        let error = run_vm("fun f(n) { return f(n + 1); } f(0);").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("interpreter: stack overflow at line 1, column 20"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn globals_persist_between_programs() {
+        let mut vm = Vm::with_output(SharedBuffer::default());
+        let first = parse(&scan("var a = 1; print missing;").unwrap()).unwrap();
+        assert!(vm.interpret(&first).is_err());
+        let second = parse(&scan("var b = a + 1;").unwrap()).unwrap();
+        vm.interpret(&second).unwrap();
+        assert_eq!(vm.get_global("b"), Some(Value::Number(2)));
+    }
+}
@@ -0,0 +1,125 @@
+// Uses the public API the way a downstream crate would, so that a change
+// that would break one fails to compile here first. Only paths re-exported
+// from `prelude`, `frontend` and `runtime` belong in this file.
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use rslox::frontend::{self, Diagnostics, Severity, Stmt, TokenKind};
+use rslox::prelude::*;
+use rslox::runtime::{Environment, Hooks, LoxFunction, LoxMap, MapKey, Vm};
+
+#[derive(Clone, Default)]
+struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn the_facade_runs_scripts_and_evaluates_expressions() {
+    let out = Buffer::default();
+    let mut lox = Lox::with_output(out.clone());
+    lox.interpreter()
+        .define_native("answer", 0, |_| Ok(Value::Number(42)));
+    lox.run_source("print answer();").unwrap();
+    assert_eq!(out.0.borrow().as_slice(), b"42\n");
+
+    // Enums are non-exhaustive, so downstream matches need a wildcard.
+    let description = match lox.eval_expr("\"a\" + \"b\"").unwrap() {
+        Value::String(s) => s,
+        _ => String::new(),
+    };
+    assert_eq!(description, "ab");
+
+    let error = lox.eval_expr("1 + nil").unwrap_err();
+    assert!(error.downcast_ref::<RuntimeError>().is_some());
+}
+
+#[test]
+fn the_frontend_exposes_tokens_and_syntax() {
+    let kinds: Vec<bool> = frontend::tokens("var x;")
+        .map(|t| matches!(t.unwrap().kind, TokenKind::Identifier(_)))
+        .collect();
+    assert_eq!(kinds, [false, true, false, false]);
+
+    let program = frontend::parse(&frontend::scan("print 1;").unwrap()).unwrap();
+    assert!(matches!(program[0], Stmt::Print(_)));
+
+    let error = frontend::scan("@ #").unwrap_err();
+    let diagnostics = &error.downcast_ref::<Diagnostics>().unwrap().0;
+    assert_eq!(diagnostics.len(), 2);
+    let severities: Vec<&str> = diagnostics
+        .iter()
+        .map(|d| match d.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            _ => "other",
+        })
+        .collect();
+    assert_eq!(severities, ["error", "error"]);
+}
+
+#[test]
+fn the_runtime_runs_on_both_backends() {
+    struct CountStatements(Rc<RefCell<usize>>);
+
+    impl Hooks for CountStatements {
+        fn before_statement(&mut self, _stmt: &Stmt) {
+            *self.0.borrow_mut() += 1;
+        }
+    }
+
+    let program = frontend::parse(&frontend::scan("var a = 1; a = a + 1;").unwrap()).unwrap();
+    let count = Rc::new(RefCell::new(0));
+    let mut interpreter = Interpreter::with_output(io::sink());
+    interpreter.set_hooks(CountStatements(Rc::clone(&count)));
+    interpreter.interpret(&program).unwrap();
+    assert_eq!(*count.borrow(), 2);
+
+    let mut vm = Vm::with_output(io::sink());
+    vm.interpret(&program).unwrap();
+    assert_eq!(
+        vm.get_global("a").map(|v| v.to_string()),
+        Some("2".to_string())
+    );
+}
+
+#[test]
+fn the_runtime_names_what_values_and_hooks_hold() {
+    struct Scopes(Rc<RefCell<Vec<String>>>);
+
+    impl Hooks for Scopes {
+        fn inspect_scope(&mut self, _stmt: &Stmt, scope: &Environment) {
+            if let Some(value) = scope.get_local("m") {
+                self.0.borrow_mut().push(value.to_string());
+            }
+        }
+    }
+
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut lox = Lox::with_output(io::sink());
+    lox.interpreter().set_hooks(Scopes(Rc::clone(&seen)));
+    lox.run_source("fun f() {} var m = {\"a\": 1}; m;").unwrap();
+    assert_eq!(*seen.borrow(), ["{\"a\": 1}"]);
+
+    let map: Rc<RefCell<LoxMap>> = match lox.eval_expr("m").unwrap() {
+        Value::Map(map) => map,
+        _ => panic!("expected a map"),
+    };
+    let keys: Vec<MapKey> = map.borrow().iter().map(|(key, _)| key.clone()).collect();
+    assert_eq!(keys, [MapKey::String("a".to_string())]);
+
+    let function: Rc<LoxFunction> = match lox.eval_expr("f").unwrap() {
+        Value::Function(function) => function,
+        _ => panic!("expected a function"),
+    };
+    assert_eq!(function.name(), "f");
+}
@@ -0,0 +1,29 @@
+// Runs the `rslox` binary on small scripts and checks what a shell would
+// see: the exit status and what is written to stderr.
+
+use std::fs;
+use std::process::{Command, Output};
+
+fn run(name: &str, source: &str) -> Output {
+    let path = std::env::temp_dir().join(format!("rslox-cli-{}.lox", name));
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_rslox"))
+        .arg("run")
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+#[test]
+fn an_operator_error_exits_70_and_points_at_the_operator() {
+    let output = run("operator-error", "var a = 1;\nprint a / 0;\n");
+    assert_eq!(output.status.code(), Some(70));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("division by zero at line 2, column 9"),
+        "{}",
+        stderr
+    );
+}